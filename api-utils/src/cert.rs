@@ -0,0 +1,411 @@
+//! The certificate-related utils.
+//!
+//! It provides a local root CA, [`CertificateAuthority`], which
+//! issues the leaf certificates for intercepting the HTTPS
+//! connections of the NetEase Cloud Music client.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use unm_api_utils::cert::CertificateAuthority;
+//!
+//! let ca = CertificateAuthority::load_or_generate("./ca").unwrap();
+//! let leaf = ca.issue("interface.music.163.com").unwrap();
+//! println!("{}", String::from_utf8_lossy(&leaf.cert_pem));
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use once_cell::sync::OnceCell;
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::extension::{
+    AuthorityKeyIdentifier,
+    BasicConstraints,
+    ExtendedKeyUsage,
+    KeyUsage,
+    SubjectAlternativeName,
+    SubjectKeyIdentifier,
+};
+use openssl::x509::{X509Builder, X509Name, X509NameBuilder, X509};
+use thiserror::Error;
+
+/// The common name of the root CA.
+const CA_COMMON_NAME: &str = "UnblockNeteaseMusic (Rust) Root CA";
+
+/// The file name of the root CA certificate.
+pub const CA_CERT_FILE: &str = "ca.crt";
+
+/// The file name of the root CA private key.
+const CA_KEY_FILE: &str = "ca.key";
+
+/// The directory to persist the leaf certificates, under the CA directory.
+const LEAF_DIR: &str = "hosts";
+
+/// How long the root CA is valid.
+const CA_VALID_DAYS: u32 = 3650;
+
+/// How long a leaf certificate is valid. The clients such as
+/// the ones on Apple platforms reject the ones valid for over 825 days.
+const LEAF_VALID_DAYS: u32 = 365;
+
+/// The bits of the RSA keys.
+const RSA_BITS: u32 = 2048;
+
+/// A leaf certificate and its private key, in PEM.
+#[derive(Clone, Debug)]
+pub struct LeafCertificate {
+    pub cert_pem: Vec<u8>,
+    /// The private key in PKCS #8.
+    pub key_pem: Vec<u8>,
+}
+
+/// The local root CA, which is persisted in a directory.
+///
+/// The directory contains `ca.crt` and `ca.key` of the root CA,
+/// and the leaf certificates issued for each host in `hosts/`.
+pub struct CertificateAuthority {
+    dir: PathBuf,
+    cert: X509,
+    key: PKey<Private>,
+    /// The leaf certificates by host. The map is only locked to get the
+    /// slot of a host, so issuing for different hosts doesn't block each other.
+    leaves: Mutex<HashMap<String, Arc<OnceCell<Arc<LeafCertificate>>>>>,
+}
+
+impl CertificateAuthority {
+    /// Load the root CA in `dir`, and generate one if there is no root CA.
+    pub fn load_or_generate(dir: impl AsRef<Path>) -> CertModuleResult<Self> {
+        let dir = dir.as_ref();
+
+        if dir.join(CA_CERT_FILE).exists() {
+            return Self::load(dir);
+        }
+
+        log::info!("Generating the root CA in “{}”…", dir.display());
+        let key = generate_key()?;
+        let name = build_name(CA_COMMON_NAME)?;
+
+        let mut builder = new_builder(&name, &key, CA_VALID_DAYS)?;
+        builder.set_issuer_name(&name)?;
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .build()?,
+        )?;
+        let subject_key_id =
+            SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(subject_key_id)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+        let cert = builder.build();
+
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(CA_CERT_FILE), cert.to_pem()?)?;
+        write_private(&dir.join(CA_KEY_FILE), &key.private_key_to_pem_pkcs8()?)?;
+
+        Ok(Self::new(dir, cert, key))
+    }
+
+    /// Load the root CA in `dir`.
+    pub fn load(dir: impl AsRef<Path>) -> CertModuleResult<Self> {
+        let dir = dir.as_ref();
+        log::debug!("Loading the root CA in “{}”…", dir.display());
+
+        let cert = X509::from_pem(&fs::read(dir.join(CA_CERT_FILE))?)?;
+        let key = PKey::private_key_from_pem(&fs::read(dir.join(CA_KEY_FILE))?)?;
+
+        Ok(Self::new(dir, cert, key))
+    }
+
+    fn new(dir: &Path, cert: X509, key: PKey<Private>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            cert,
+            key,
+            leaves: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The root CA certificate in PEM, which the clients should trust.
+    pub fn cert_pem(&self) -> CertModuleResult<Vec<u8>> {
+        Ok(self.cert.to_pem()?)
+    }
+
+    /// Get the leaf certificate for `host`.
+    ///
+    /// It is loaded from the CA directory if it has been issued and
+    /// is still valid; otherwise, a new one is issued and persisted.
+    /// Issuing one generates an RSA key, which blocks for a while,
+    /// so call it in a blocking thread in the asynchronous contexts.
+    pub fn issue(&self, host: &str) -> CertModuleResult<Arc<LeafCertificate>> {
+        if host.is_empty() || host.contains(['/', '\\']) || host.starts_with('.') {
+            return Err(CertModuleError::InvalidHost(host.to_string()));
+        }
+
+        let slot = self
+            .leaves
+            .lock()
+            .expect("the leaf cache is poisoned")
+            .entry(host.to_string())
+            .or_default()
+            .clone();
+
+        // The requests for the same host wait for the first one to issue.
+        slot.get_or_try_init(|| {
+            Ok(Arc::new(match self.load_leaf(host) {
+                Some(leaf) => leaf,
+                None => self.generate_leaf(host)?,
+            }))
+        })
+        .cloned()
+    }
+
+    /// Get the leaf certificate for `host` if it has been loaded or issued,
+    /// which doesn't block.
+    pub fn cached(&self, host: &str) -> Option<Arc<LeafCertificate>> {
+        self.leaves
+            .lock()
+            .expect("the leaf cache is poisoned")
+            .get(host)?
+            .get()
+            .cloned()
+    }
+
+    /// Load the persisted leaf certificate for `host`,
+    /// if it is valid for now and issued by this CA.
+    fn load_leaf(&self, host: &str) -> Option<LeafCertificate> {
+        let (cert_path, key_path) = self.leaf_paths(host);
+        let cert_pem = fs::read(cert_path).ok()?;
+        let key_pem = fs::read(key_path).ok()?;
+
+        let cert = X509::from_pem(&cert_pem).ok()?;
+        let now = Asn1Time::days_from_now(0).ok()?;
+        let issued_by_us = cert.verify(&self.key).unwrap_or_default();
+        if !issued_by_us || cert.not_after() <= now {
+            return None;
+        }
+
+        Some(LeafCertificate { cert_pem, key_pem })
+    }
+
+    /// Issue a leaf certificate for `host`, and persist it.
+    fn generate_leaf(&self, host: &str) -> CertModuleResult<LeafCertificate> {
+        log::info!("Issuing the certificate for “{host}”…");
+
+        let key = generate_key()?;
+        let mut builder = new_builder(&build_name(host)?, &key, LEAF_VALID_DAYS)?;
+        builder.set_issuer_name(self.cert.subject_name())?;
+        builder.append_extension(BasicConstraints::new().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .key_encipherment()
+                .build()?,
+        )?;
+        builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+
+        let context = builder.x509v3_context(Some(&self.cert), None);
+        let subject_key_id = SubjectKeyIdentifier::new().build(&context)?;
+        let authority_key_id = AuthorityKeyIdentifier::new().keyid(false).build(&context)?;
+        let alt_name = SubjectAlternativeName::new().dns(host).build(&context)?;
+        builder.append_extension(subject_key_id)?;
+        builder.append_extension(authority_key_id)?;
+        builder.append_extension(alt_name)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+
+        let leaf = LeafCertificate {
+            cert_pem: builder.build().to_pem()?,
+            key_pem: key.private_key_to_pem_pkcs8()?,
+        };
+
+        let (cert_path, key_path) = self.leaf_paths(host);
+        fs::create_dir_all(self.dir.join(LEAF_DIR))?;
+        fs::write(cert_path, &leaf.cert_pem)?;
+        write_private(&key_path, &leaf.key_pem)?;
+
+        Ok(leaf)
+    }
+
+    fn leaf_paths(&self, host: &str) -> (PathBuf, PathBuf) {
+        let dir = self.dir.join(LEAF_DIR);
+        (
+            dir.join(format!("{host}.crt")),
+            dir.join(format!("{host}.key")),
+        )
+    }
+}
+
+fn generate_key() -> Result<PKey<Private>, ErrorStack> {
+    PKey::from_rsa(Rsa::generate(RSA_BITS)?)
+}
+
+fn build_name(common_name: &str) -> Result<X509Name, ErrorStack> {
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("O", "UnblockNeteaseMusic")?;
+    name.append_entry_by_text("CN", common_name)?;
+    Ok(name.build())
+}
+
+/// Construct a certificate builder with a random serial number.
+fn new_builder(
+    subject: &X509Name,
+    key: &PKey<Private>,
+    valid_days: u32,
+) -> Result<X509Builder, ErrorStack> {
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    let serial = Asn1Integer::from_bn(&serial)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(subject)?;
+    builder.set_pubkey(key)?;
+    // Allow a little clock skew between us and the clients.
+    let not_before = Asn1Time::from_unix(unix_now() - 24 * 60 * 60)?;
+    let not_after = Asn1Time::days_from_now(valid_days)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+
+    Ok(builder)
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Write the private key to `path`, which only the owner can read on Unix.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::write(path, contents)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum CertModuleError {
+    #[error("failed to read or write the certificates: {0}")]
+    IoFailed(#[from] std::io::Error),
+
+    #[error("failed in OpenSSL: {0}")]
+    OpenSslFailed(#[from] ErrorStack),
+
+    #[error("invalid host to issue the certificate for: {0}")]
+    InvalidHost(String),
+}
+
+pub type CertModuleResult<T> = Result<T, CertModuleError>;
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use openssl::pkey::PKey;
+    use openssl::x509::X509;
+
+    use super::{CertModuleError, CertificateAuthority, CA_CERT_FILE};
+
+    fn temp_ca_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("unm-cert-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn issue_and_reload() {
+        let dir = temp_ca_dir("issue");
+        let ca = CertificateAuthority::load_or_generate(&dir).unwrap();
+        let ca_cert = X509::from_pem(&ca.cert_pem().unwrap()).unwrap();
+
+        let leaf = ca.issue("interface.music.163.com").unwrap();
+        let cert = X509::from_pem(&leaf.cert_pem).unwrap();
+        let key = PKey::private_key_from_pem(&leaf.key_pem).unwrap();
+
+        // The leaf is signed by the CA, and matches its private key.
+        assert!(cert.verify(&ca_cert.public_key().unwrap()).unwrap());
+        assert_eq!(cert.issuer_name_hash(), ca_cert.subject_name_hash());
+        assert!(cert.public_key().unwrap().public_eq(&key));
+
+        let alt_names = cert.subject_alt_names().expect("should have the SAN");
+        let dns_names = alt_names
+            .iter()
+            .filter_map(|name| name.dnsname())
+            .collect::<Vec<_>>();
+        assert_eq!(dns_names, ["interface.music.163.com"]);
+
+        // The cached leaf is returned without issuing again.
+        assert!(Arc::ptr_eq(
+            &ca.cached("interface.music.163.com").unwrap(),
+            &leaf
+        ));
+        assert!(ca.cached("music.163.com").is_none());
+
+        // The persisted CA and leaf are loaded instead of regenerated.
+        let reloaded = CertificateAuthority::load_or_generate(&dir).unwrap();
+        assert_eq!(
+            reloaded.cert_pem().unwrap(),
+            std::fs::read(dir.join(CA_CERT_FILE)).unwrap()
+        );
+        assert_eq!(reloaded.cert_pem().unwrap(), ca.cert_pem().unwrap());
+        let reloaded_leaf = reloaded.issue("interface.music.163.com").unwrap();
+        assert_eq!(reloaded_leaf.cert_pem, leaf.cert_pem);
+        assert_eq!(reloaded_leaf.key_pem, leaf.key_pem);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn issue_concurrently() {
+        let dir = temp_ca_dir("concurrent");
+        let ca = CertificateAuthority::load_or_generate(&dir).unwrap();
+
+        // The same host is only issued once.
+        let leaves = std::thread::scope(|s| {
+            let handles = (0..4)
+                .map(|_| s.spawn(|| ca.issue("music.163.com").unwrap()))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert!(leaves.iter().all(|leaf| Arc::ptr_eq(leaf, &leaves[0])));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn issue_invalid_host() {
+        let dir = temp_ca_dir("invalid");
+        let ca = CertificateAuthority::load_or_generate(&dir).unwrap();
+
+        for host in ["", "../ca", ".music.163.com"] {
+            assert!(matches!(
+                ca.issue(host),
+                Err(CertModuleError::InvalidHost(_))
+            ));
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
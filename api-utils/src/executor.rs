@@ -11,7 +11,8 @@ use unm_engine::executor::Executor;
 /// Build an [`Executor`] with all the official engines registered.
 ///
 /// Currently, it includes `bilibili`, `kugou`, `pyncm`,
-/// `ytdl`, `kuwo`, and `joox`.
+/// `ytdl`, `youtube`, `kuwo`, `joox` and `qq`. The metadata of songs are
+/// resolved with the NetEase Cloud Music API ([`unm_ncm`]).
 ///
 /// # Example
 ///
@@ -38,9 +39,12 @@ pub fn build_full_executor() -> Executor {
     push_engine!(kugou: KugouEngine);
     push_engine!(pyncm: PyNCMEngine);
     push_engine!(ytdl: YtDlEngine);
+    push_engine!(youtube: YouTubeEngine);
     push_engine!(kuwo: KuwoEngine);
     push_engine!(joox: JooxEngine);
     push_engine!(qq: QQEngine);
 
+    executor.set_metadata_resolver(Arc::new(unm_ncm::NcmMetadataResolver));
+
     executor
 }
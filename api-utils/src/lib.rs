@@ -13,4 +13,7 @@
 //! println!("{:?}", executor.list());
 //! ```
 
+#[cfg(feature = "openssl")]
+pub mod cert;
 pub mod executor;
+pub mod retrieve;
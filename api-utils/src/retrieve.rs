@@ -0,0 +1,68 @@
+//! The utilities for requesting the retrieved songs.
+
+use http::header::{HeaderMap, HeaderName, HeaderValue, REFERER, USER_AGENT};
+use unm_types::RetrievedSongInfo;
+
+/// Determine the header for requesting the `retrieved` song, which is
+/// the one that its engine requires, and the headers it specifies.
+pub fn determine_header(retrieved: &RetrievedSongInfo) -> HeaderMap {
+    log::debug!(
+        "Determining the header to request the song from {}…",
+        retrieved.source
+    );
+
+    let mut hm = HeaderMap::new();
+
+    if retrieved.source == unm_engine_bilibili::ENGINE_ID {
+        hm.insert(
+            REFERER,
+            HeaderValue::from_static(unm_engine_bilibili::api::BILIBILI_REFERER),
+        );
+        hm.insert(USER_AGENT, HeaderValue::from_static("okhttp/3.4.1"));
+    }
+
+    for (name, value) in &retrieved.headers {
+        match (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            (Ok(name), Ok(value)) => {
+                hm.insert(name, value);
+            }
+            _ => log::warn!("Ignoring the invalid header “{name}: {value}”"),
+        }
+    }
+
+    hm
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use http::header::{REFERER, USER_AGENT};
+    use unm_types::RetrievedSongInfo;
+
+    use super::determine_header;
+
+    #[test]
+    fn test_determine_header() {
+        let bilibili = RetrievedSongInfo::builder()
+            .source(unm_engine_bilibili::ENGINE_ID.into())
+            .url("https://upos-sz-mirrorkodo.bilivideo.com/audio.m4a".to_string())
+            .build();
+        let hm = determine_header(&bilibili);
+        assert_eq!(hm[REFERER], unm_engine_bilibili::api::BILIBILI_REFERER);
+        assert_eq!(hm[USER_AGENT], "okhttp/3.4.1");
+
+        // The headers of the engine take precedence.
+        let ytdl = RetrievedSongInfo::builder()
+            .source("ytdl".into())
+            .url("https://rr2---sn-oguelnzz.googlevideo.com/videoplayback".to_string())
+            .headers(BTreeMap::from([
+                ("User-Agent".to_string(), "Mozilla/5.0".to_string()),
+                ("bad header".to_string(), "ignored".to_string()),
+            ]))
+            .build();
+        let hm = determine_header(&ytdl);
+        assert_eq!(hm[USER_AGENT], "Mozilla/5.0");
+        assert_eq!(hm.len(), 1);
+    }
+}
@@ -0,0 +1,313 @@
+//! The offline golden corpus for the song matching.
+//!
+//! Each file in `tests/corpus` is named after a NCM song ID. It contains
+//! the NCM song, the search results every engine returned when searching
+//! it (in the format of the engine API), and the candidate we expect to
+//! be picked – or `null` if none of the candidates is the right song
+//! which can be played without an account.
+//!
+//! Each engine is evaluated twice on its candidates:
+//!
+//! - `selector`: the first candidate which [`SimilarSongSelector`] accepts.
+//! - `engine`: the `find_match` (or `select_best`) step of the engine,
+//!   which applies the extra rules of the engine, such as the common
+//!   artist of Kugou, the paid songs of Kuwo, or the "- Topic" channels
+//!   of YouTube.
+//!
+//! Run it with `--nocapture` to see the precision and recall report:
+//!
+//! ```sh
+//! cargo test -p unm_api_utils --test golden_corpus -- --nocapture
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value as Json;
+use unm_engine_bilibili::api::typing::{BilibiliSearchResult, BilibiliVideoSearchResult};
+use unm_engine_kuwo::api::typing::KuwoAudioInfo;
+use unm_engine_migu::types::MiguResponse;
+use unm_engine_qq::api::typing::QQSongEntry;
+use unm_engine_youtube::api::typing::YouTubeVideoRenderer;
+use unm_engine_ytdl::YtDlResponse;
+use unm_selector::SimilarSongSelector;
+use unm_types::Song;
+
+/// The lowest overall precision of the engines we accept.
+///
+/// Raise it when the matching logic is improved.
+const MIN_PRECISION: f64 = 0.8;
+
+/// The lowest overall recall of the engines we accept.
+///
+/// Raise it when the matching logic is improved.
+const MIN_RECALL: f64 = 0.9;
+
+#[derive(Deserialize)]
+struct CorpusEntry {
+    /// The song on NCM to search.
+    song: Song,
+    /// The recorded search result of each engine.
+    engines: BTreeMap<String, EngineCase>,
+}
+
+#[derive(Deserialize)]
+struct EngineCase {
+    /// The ID of the candidate that should be picked.
+    expected: Option<String>,
+    /// The search results of the engine, in the original order.
+    candidates: Json,
+}
+
+/// The search results of Bilibili, in both the audio area and the videos.
+#[derive(Deserialize)]
+struct BilibiliCandidates {
+    audios: Vec<BilibiliSearchResult>,
+    videos: Vec<BilibiliVideoSearchResult>,
+}
+
+/// The confusion counts of a matcher.
+#[derive(Clone, Copy, Default)]
+struct Score {
+    /// Picked the expected candidate.
+    true_positive: u32,
+    /// Picked a wrong candidate.
+    false_positive: u32,
+    /// Didn't pick the expected candidate.
+    false_negative: u32,
+}
+
+impl Score {
+    fn record(&mut self, expected: Option<&str>, picked: Option<&str>) {
+        match (expected, picked) {
+            (Some(expected), Some(picked)) if expected == picked => self.true_positive += 1,
+            (Some(_), Some(_)) => {
+                self.false_positive += 1;
+                self.false_negative += 1;
+            }
+            (None, Some(_)) => self.false_positive += 1,
+            (Some(_), None) => self.false_negative += 1,
+            (None, None) => {}
+        }
+    }
+
+    fn merge(&mut self, other: &Score) {
+        self.true_positive += other.true_positive;
+        self.false_positive += other.false_positive;
+        self.false_negative += other.false_negative;
+    }
+
+    fn precision(&self) -> f64 {
+        ratio(self.true_positive, self.true_positive + self.false_positive)
+    }
+
+    fn recall(&self) -> f64 {
+        ratio(self.true_positive, self.true_positive + self.false_negative)
+    }
+}
+
+/// The scores of the selector and the engine on the candidates of an engine.
+#[derive(Default)]
+struct EngineScore {
+    selector: Score,
+    engine: Score,
+}
+
+fn ratio(numerator: u32, denominator: u32) -> f64 {
+    if denominator == 0 {
+        1.0
+    } else {
+        f64::from(numerator) / f64::from(denominator)
+    }
+}
+
+fn load_corpus() -> Vec<(String, CorpusEntry)> {
+    let corpus_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut corpus = std::fs::read_dir(corpus_dir)
+        .expect("the corpus directory should be readable")
+        .map(|entry| entry.expect("should be able to read the entry").path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+        .map(|path| {
+            let content = std::fs::read_to_string(&path).expect("should be readable");
+            let entry = serde_json::from_str::<CorpusEntry>(&content)
+                .unwrap_or_else(|e| panic!("{} is not a valid corpus entry: {e}", path.display()));
+            let name = path
+                .file_stem()
+                .map(|v| v.to_string_lossy().to_string())
+                .unwrap_or_default();
+
+            (name, entry)
+        })
+        .collect::<Vec<_>>();
+
+    corpus.sort_by(|(a, _), (b, _)| a.cmp(b));
+    corpus
+}
+
+/// Deserialize the `candidates` to the search result type of the engine.
+fn parse<T: DeserializeOwned>(engine: &str, candidates: &Json) -> T {
+    serde_json::from_value(candidates.clone())
+        .unwrap_or_else(|e| panic!("the candidates of {engine} are invalid: {e}"))
+}
+
+/// Match the `candidates` of `engine` against `song`.
+///
+/// Returns the candidates converted to [`Song`] as the engine does,
+/// and the song picked by the engine.
+fn run_engine(engine: &str, song: &Song, candidates: &Json) -> (Vec<Song>, Option<Song>) {
+    match engine {
+        "kuwo" => {
+            let list = parse::<Vec<KuwoAudioInfo>>(engine, candidates);
+            let songs = list.iter().cloned().map(Song::from).collect();
+
+            // Evaluated without the credentials, so the paid songs are skipped.
+            let picked = unm_engine_kuwo::find_match(song, list, false)
+                .ok()
+                .flatten();
+            (songs, picked)
+        }
+        "kugou" => {
+            let entries = parse::<Vec<Json>>(engine, candidates);
+            let songs = entries
+                .iter()
+                .filter_map(|entry| unm_engine_kugou::format(entry).ok())
+                .collect();
+
+            (songs, unm_engine_kugou::find_match(song, &entries))
+        }
+        "qq" => {
+            let songs = parse::<Vec<QQSongEntry>>(engine, candidates)
+                .into_iter()
+                .map(Song::from)
+                .collect();
+            let picked = unm_engine_qq::find_match(song, parse(engine, candidates));
+
+            (songs, picked)
+        }
+        "migu" => {
+            let songs = parse::<Vec<MiguResponse>>(engine, candidates)
+                .into_iter()
+                .map(Song::from)
+                .collect();
+            let picked = unm_engine_migu::find_match(song, parse(engine, candidates));
+
+            (songs, picked)
+        }
+        "joox" => {
+            let items = parse::<Vec<Json>>(engine, candidates);
+            let songs = items
+                .iter()
+                .filter_map(|item| unm_engine_joox::format(item).ok())
+                .collect();
+
+            (songs, unm_engine_joox::find_match(song, &items))
+        }
+        "bilibili" => {
+            let BilibiliCandidates { audios, videos } = parse(engine, candidates);
+            let songs = audios
+                .iter()
+                .cloned()
+                .map(Song::from)
+                .chain(videos.iter().cloned().map(Song::from))
+                .collect();
+
+            (songs, unm_engine_bilibili::find_match(song, audios, videos))
+        }
+        "youtube" => {
+            let videos = parse::<Vec<YouTubeVideoRenderer>>(engine, candidates);
+            let songs = videos.iter().cloned().map(Song::from).collect();
+
+            (songs, unm_engine_youtube::find_match(song, videos))
+        }
+        "ytdl" => {
+            let songs = parse::<Vec<YtDlResponse>>(engine, candidates)
+                .into_iter()
+                .map(Song::from)
+                .collect();
+            let picked =
+                unm_engine_ytdl::select_best(song, parse(engine, candidates)).map(Song::from);
+
+            (songs, picked)
+        }
+        _ => panic!("unknown engine in the corpus: {engine}"),
+    }
+}
+
+/// Pick the first candidate that [`SimilarSongSelector`] accepts.
+fn pick_by_selector<'a>(song: &Song, candidates: &'a [Song]) -> Option<&'a Song> {
+    let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(song);
+
+    candidates.iter().find(selector)
+}
+
+#[test]
+fn golden_corpus_accuracy() {
+    let corpus = load_corpus();
+    assert!(!corpus.is_empty(), "the corpus should not be empty");
+
+    let mut scores = BTreeMap::<&str, EngineScore>::new();
+
+    for (name, entry) in corpus.iter() {
+        for (engine, case) in entry.engines.iter() {
+            let (candidates, picked) = run_engine(engine, &entry.song, &case.candidates);
+            let picked = picked.map(|s| s.id);
+            let picked_by_selector =
+                pick_by_selector(&entry.song, &candidates).map(|s| s.id.as_str());
+
+            if picked.as_deref() != case.expected.as_deref() {
+                println!(
+                    "[{name}] {engine}: expected {:?}, picked {:?} ({})",
+                    case.expected, picked, entry.song
+                );
+            }
+
+            let score = scores.entry(engine).or_default();
+            score
+                .selector
+                .record(case.expected.as_deref(), picked_by_selector);
+            score
+                .engine
+                .record(case.expected.as_deref(), picked.as_deref());
+        }
+    }
+
+    let mut overall = EngineScore::default();
+
+    println!("{:<10} {:>19} {:>19}", "", "selector", "engine");
+    println!(
+        "{:<10} {:>9} {:>9} {:>9} {:>9}",
+        "engine", "precision", "recall", "precision", "recall"
+    );
+    for (engine, score) in scores.iter() {
+        println!(
+            "{engine:<10} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+            score.selector.precision(),
+            score.selector.recall(),
+            score.engine.precision(),
+            score.engine.recall()
+        );
+        overall.selector.merge(&score.selector);
+        overall.engine.merge(&score.engine);
+    }
+    println!(
+        "{:<10} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+        "(overall)",
+        overall.selector.precision(),
+        overall.selector.recall(),
+        overall.engine.precision(),
+        overall.engine.recall()
+    );
+
+    assert!(
+        overall.engine.precision() >= MIN_PRECISION,
+        "precision regressed: {:.3} < {MIN_PRECISION}",
+        overall.engine.precision()
+    );
+    assert!(
+        overall.engine.recall() >= MIN_RECALL,
+        "recall regressed: {:.3} < {MIN_RECALL}",
+        overall.engine.recall()
+    );
+}
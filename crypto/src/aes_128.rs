@@ -1,32 +1,66 @@
 //! The AES-128 utilities, including the encryption and decryption methods
 //! of AES-128-ECB and AES-128-CBC.
 
-use openssl::symm::{decrypt as symm_decrypt, encrypt as symm_encrypt, Cipher};
-
+use crate::backend;
 use crate::error::CryptoResult;
 
 pub type AesResult = CryptoResult<Vec<u8>>;
 
 /// Decrypt data with AES-128-ECB using the given key.
 pub fn decrypt_ecb(data: &[u8], key: &[u8]) -> AesResult {
-    let cipher = Cipher::aes_128_ecb();
-    Ok(symm_decrypt(cipher, key, None, data)?)
+    backend::decrypt_ecb(data, key)
 }
 
 /// Encrypt data with AES-128-ECB using the given key.
 pub fn encrypt_ecb(data: &[u8], key: &[u8]) -> AesResult {
-    let cipher = Cipher::aes_128_ecb();
-    Ok(symm_encrypt(cipher, key, None, data)?)
+    backend::encrypt_ecb(data, key)
 }
 
 /// Decrypt data with AES-128-CBC using the given key and iv.
 pub fn decrypt_cbc(data: &[u8], key: &[u8], iv: &[u8]) -> AesResult {
-    let cipher = Cipher::aes_128_cbc();
-    Ok(symm_decrypt(cipher, key, Some(iv), data)?)
+    backend::decrypt_cbc(data, key, iv)
 }
 
 /// Encrypt data with AES-128-CBC using the given key and iv.
 pub fn encrypt_cbc(data: &[u8], key: &[u8], iv: &[u8]) -> AesResult {
-    let cipher = Cipher::aes_128_cbc();
-    Ok(symm_encrypt(cipher, key, Some(iv), data)?)
+    backend::encrypt_cbc(data, key, iv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt_cbc, decrypt_ecb, encrypt_cbc, encrypt_ecb};
+
+    // The vectors are generated with `openssl enc`, for example:
+    // > printf 'UnblockNeteaseMusic' | openssl enc -aes-128-ecb -K <key in hex> | xxd -p
+    const PLAIN_TEXT: &[u8] = b"UnblockNeteaseMusic";
+    const ECB_KEY: &[u8] = b"e82ckenh8dichen8";
+    const ECB_CIPHER_TEXT: &str =
+        "026bbf5c363ba866e326267e99efc12816f1a49bf79eb935b6493dcf79f3d15a";
+    const CBC_KEY: &[u8] = b"0CoJUm6Qyw8W8jud";
+    const CBC_IV: &[u8] = b"0102030405060708";
+    const CBC_CIPHER_TEXT: &str =
+        "a042b7b3bbba85aca4ec3b386d4eef614e606fcbbc46bd4221d82396a95cfd50";
+
+    #[test]
+    fn ecb_test() {
+        let encrypted = encrypt_ecb(PLAIN_TEXT, ECB_KEY).unwrap();
+        assert_eq!(faster_hex::hex_string(&encrypted), ECB_CIPHER_TEXT);
+        assert_eq!(decrypt_ecb(&encrypted, ECB_KEY).unwrap(), PLAIN_TEXT);
+    }
+
+    #[test]
+    fn cbc_test() {
+        let encrypted = encrypt_cbc(PLAIN_TEXT, CBC_KEY, CBC_IV).unwrap();
+        assert_eq!(faster_hex::hex_string(&encrypted), CBC_CIPHER_TEXT);
+        assert_eq!(
+            decrypt_cbc(&encrypted, CBC_KEY, CBC_IV).unwrap(),
+            PLAIN_TEXT
+        );
+    }
+
+    #[test]
+    fn invalid_input_test() {
+        assert!(encrypt_ecb(PLAIN_TEXT, b"short").is_err());
+        assert!(decrypt_ecb(PLAIN_TEXT, ECB_KEY).is_err());
+    }
 }
@@ -0,0 +1,19 @@
+//! The backends of the cryptographic primitives.
+//!
+//! By default, they are implemented with OpenSSL (the `openssl` feature).
+//! With the `pure-rust` feature, they are implemented in pure Rust,
+//! which is easier to cross-compile, and takes precedence over OpenSSL
+//! if both are enabled. Both backends produce identical outputs.
+
+#[cfg(not(any(feature = "openssl", feature = "pure-rust")))]
+compile_error!("Either the `openssl` or the `pure-rust` feature of unm_crypto should be enabled.");
+
+#[cfg(all(feature = "openssl", not(feature = "pure-rust")))]
+mod openssl;
+#[cfg(all(feature = "openssl", not(feature = "pure-rust")))]
+pub(crate) use self::openssl::*;
+
+#[cfg(feature = "pure-rust")]
+mod rust;
+#[cfg(feature = "pure-rust")]
+pub(crate) use self::rust::*;
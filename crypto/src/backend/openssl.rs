@@ -0,0 +1,41 @@
+//! The backend implemented with OpenSSL.
+
+use once_cell::sync::OnceCell;
+use openssl::pkey::Public;
+use openssl::rsa::{Padding, Rsa};
+use openssl::symm::{decrypt as symm_decrypt, encrypt as symm_encrypt, Cipher};
+
+use crate::error::CryptoResult;
+
+const WEAPI_PUBKEY: &[u8] = b"-----BEGIN PUBLIC KEY-----\nMIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDgtQn2JZ34ZC28NWYpAUd98iZ37BUrX/aKzmFbt7clFSs6sXqHauqKWqdtLkF2KexO40H1YTX8z2lSgBBOAxLsvaklV8k4cBFK9snQXE9/DDaFt6Rr7iVZMldczhC0JNgTz+SHXT6CBHuX3e9SdB1Ua44oncaTWz7OBGLbCiK45wIDAQAB\n-----END PUBLIC KEY-----";
+
+static WEAPI_RSA_INSTANCE: OnceCell<Rsa<Public>> = OnceCell::new();
+
+pub(crate) fn decrypt_ecb(data: &[u8], key: &[u8]) -> CryptoResult<Vec<u8>> {
+    Ok(symm_decrypt(Cipher::aes_128_ecb(), key, None, data)?)
+}
+
+pub(crate) fn encrypt_ecb(data: &[u8], key: &[u8]) -> CryptoResult<Vec<u8>> {
+    Ok(symm_encrypt(Cipher::aes_128_ecb(), key, None, data)?)
+}
+
+pub(crate) fn decrypt_cbc(data: &[u8], key: &[u8], iv: &[u8]) -> CryptoResult<Vec<u8>> {
+    Ok(symm_decrypt(Cipher::aes_128_cbc(), key, Some(iv), data)?)
+}
+
+pub(crate) fn encrypt_cbc(data: &[u8], key: &[u8], iv: &[u8]) -> CryptoResult<Vec<u8>> {
+    Ok(symm_encrypt(Cipher::aes_128_cbc(), key, Some(iv), data)?)
+}
+
+pub(crate) fn rand_bytes(buf: &mut [u8]) -> CryptoResult<()> {
+    Ok(openssl::rand::rand_bytes(buf)?)
+}
+
+fn get_weapi_rsa_instance() -> CryptoResult<&'static Rsa<Public>> {
+    Ok(WEAPI_RSA_INSTANCE.get_or_try_init(|| Rsa::public_key_from_pem(WEAPI_PUBKEY))?)
+}
+
+/// Encrypt the 128-byte `data` with the WEAPI public key without padding.
+pub(crate) fn weapi_rsa_encrypt(data: &[u8], to: &mut [u8; 128]) -> CryptoResult<usize> {
+    Ok(get_weapi_rsa_instance()?.public_encrypt(data, to, Padding::NONE)?)
+}
@@ -0,0 +1,72 @@
+//! The backend implemented in pure Rust.
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit};
+use aes::Aes128;
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+
+use crate::error::{CryptoError, CryptoResult};
+
+/// The modulus of the WEAPI public key, in hex.
+const WEAPI_MODULUS: &[u8] = b"e0b509f6259df8642dbc35662901477df22677ec152b5ff68ace615bb7b725152b3ab17a876aea8a5aa76d2e417629ec4ee341f56135fccf695280104e0312ecbda92557c93870114af6c9d05c4f7f0c3685b7a46bee255932575cce10b424d813cfe4875d3e82047b97ddef52741d546b8e289dc6935b3ece0462db0a22b8e7";
+
+/// The public exponent of the WEAPI public key.
+const WEAPI_EXPONENT: u32 = 65537;
+
+static WEAPI_MODULUS_INSTANCE: Lazy<BigUint> = Lazy::new(|| {
+    BigUint::parse_bytes(WEAPI_MODULUS, 16).expect("the WEAPI modulus should be valid hex")
+});
+
+const INVALID_LENGTH: CryptoError = CryptoError::CipherFail("invalid key or IV length");
+const UNPAD_FAILED: CryptoError = CryptoError::CipherFail("bad decrypt");
+
+pub(crate) fn decrypt_ecb(data: &[u8], key: &[u8]) -> CryptoResult<Vec<u8>> {
+    ecb::Decryptor::<Aes128>::new_from_slice(key)
+        .map_err(|_| INVALID_LENGTH)?
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| UNPAD_FAILED)
+}
+
+pub(crate) fn encrypt_ecb(data: &[u8], key: &[u8]) -> CryptoResult<Vec<u8>> {
+    Ok(ecb::Encryptor::<Aes128>::new_from_slice(key)
+        .map_err(|_| INVALID_LENGTH)?
+        .encrypt_padded_vec_mut::<Pkcs7>(data))
+}
+
+pub(crate) fn decrypt_cbc(data: &[u8], key: &[u8], iv: &[u8]) -> CryptoResult<Vec<u8>> {
+    cbc::Decryptor::<Aes128>::new_from_slices(key, iv)
+        .map_err(|_| INVALID_LENGTH)?
+        .decrypt_padded_vec_mut::<Pkcs7>(data)
+        .map_err(|_| UNPAD_FAILED)
+}
+
+pub(crate) fn encrypt_cbc(data: &[u8], key: &[u8], iv: &[u8]) -> CryptoResult<Vec<u8>> {
+    Ok(cbc::Encryptor::<Aes128>::new_from_slices(key, iv)
+        .map_err(|_| INVALID_LENGTH)?
+        .encrypt_padded_vec_mut::<Pkcs7>(data))
+}
+
+pub(crate) fn rand_bytes(buf: &mut [u8]) -> CryptoResult<()> {
+    getrandom::getrandom(buf).map_err(|e| CryptoError::RandomFail(e.to_string()))
+}
+
+/// Encrypt the 128-byte `data` with the WEAPI public key without padding,
+/// which is the textbook RSA: `data ^ e mod n`.
+pub(crate) fn weapi_rsa_encrypt(data: &[u8], to: &mut [u8; 128]) -> CryptoResult<usize> {
+    let modulus = &*WEAPI_MODULUS_INSTANCE;
+    let message = BigUint::from_bytes_be(data);
+    if message >= *modulus {
+        return Err(CryptoError::CipherFail("data too large for modulus"));
+    }
+
+    let encrypted = message
+        .modpow(&BigUint::from(WEAPI_EXPONENT), modulus)
+        .to_bytes_be();
+
+    // Pad the leading zeros, like what OpenSSL does.
+    to.fill(0);
+    to[128 - encrypted.len()..].copy_from_slice(&encrypted);
+
+    Ok(to.len())
+}
@@ -1,13 +1,18 @@
 use concat_string::concat_string;
 use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
 
 use crate::aes_128;
 use crate::aes_128::AesResult;
-use crate::error::CryptoResult;
+use crate::error::{CryptoError, CryptoResult};
 
 const EAPI_KEY: &[u8; 16] = b"e82ckenh8dichen8";
 
+/// The separator between the URL, the parameters and the digest.
+const SEPARATOR: &str = "-36cd479b6b5-";
+
 pub fn decrypt(data: &[u8]) -> AesResult {
     aes_128::decrypt_ecb(data, EAPI_KEY)
 }
@@ -21,19 +26,23 @@ pub struct EncryptRequestResponse {
     pub body: String,
 }
 
+/// The digest of the `serialized` parameters to `url`, in lowercase hex.
+fn digest(url: &str, serialized: &str) -> String {
+    let message = concat_string!("deprecate", url, "md5", serialized, "please");
+    faster_hex::hex_string(md5::compute(message.into_bytes()).as_slice())
+}
+
 pub fn encrypt_request<T: Serialize>(
     url: &str,
     object: &T,
 ) -> CryptoResult<EncryptRequestResponse> {
     let serialized: String = serde_json::to_string(object)?;
-    let message = concat_string!("deprecate", url, "md5", serialized, "please");
-    let digest = md5::compute(message.into_bytes());
     let data = concat_string!(
         url,
-        "-36cd479b6b5-",
+        SEPARATOR,
         serialized,
-        "-36cd479b6b5-",
-        faster_hex::hex_string(digest.as_slice())
+        SEPARATOR,
+        digest(url, &serialized)
     );
 
     Ok(EncryptRequestResponse {
@@ -49,3 +58,132 @@ pub fn encrypt_request<T: Serialize>(
         ),
     })
 }
+
+/// The request decrypted with [`decrypt_request`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecryptRequestResponse {
+    /// The URL in the plain text, for example, `/api/song/enhance/player/url`.
+    pub url: String,
+    /// The parameters of this request.
+    pub params: Value,
+    /// The MD5 digest of the URL and the parameters, in lowercase hex.
+    pub digest: String,
+}
+
+impl DecryptRequestResponse {
+    /// Deserialize the parameters to `T`.
+    pub fn params<T: DeserializeOwned>(&self) -> CryptoResult<T> {
+        Ok(T::deserialize(&self.params)?)
+    }
+}
+
+/// Decrypt the `body` of the request, which is the form with
+/// the hex-encoded `params` made by [`encrypt_request`].
+///
+/// The digest is validated, and [`CryptoError::DigestMismatch`]
+/// is returned if it does not match the URL and the parameters.
+pub fn decrypt_request(body: &str) -> CryptoResult<DecryptRequestResponse> {
+    let params = url::form_urlencoded::parse(body.as_bytes())
+        .find(|(key, _)| key == "params")
+        .map(|(_, value)| value)
+        .ok_or(CryptoError::InvalidPayload("no “params” in the body"))?;
+
+    let mut encrypted = vec![0; params.len() / 2];
+    faster_hex::hex_decode(params.as_bytes(), &mut encrypted)?;
+    let data = String::from_utf8(decrypt(&encrypted)?)?;
+
+    let mut parts = data.splitn(3, SEPARATOR);
+    let (Some(url), Some(serialized), Some(found)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(CryptoError::InvalidPayload(
+            "the request should be “url-36cd479b6b5-params-36cd479b6b5-digest”",
+        ));
+    };
+
+    let expected = digest(url, serialized);
+    if !expected.eq_ignore_ascii_case(found) {
+        return Err(CryptoError::DigestMismatch {
+            expected,
+            found: found.to_string(),
+        });
+    }
+
+    Ok(DecryptRequestResponse {
+        url: url.to_string(),
+        params: serde_json::from_str(serialized)?,
+        digest: expected,
+    })
+}
+
+/// Encrypt the response `object`, which the clients
+/// request with the `e_r` parameter.
+pub fn encrypt_response<T: Serialize>(object: &T) -> CryptoResult<Vec<u8>> {
+    encrypt(&serde_json::to_vec(object)?)
+}
+
+/// Decrypt the response made by [`encrypt_response`], and deserialize it to `T`.
+pub fn decrypt_response<T: DeserializeOwned>(data: &[u8]) -> CryptoResult<T> {
+    Ok(serde_json::from_slice(&decrypt(data)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+
+    use super::{decrypt_request, decrypt_response, encrypt_request, encrypt_response};
+    use crate::error::CryptoError;
+
+    #[test]
+    fn decrypt_request_test() {
+        let params = json!({ "ids": "[185809]", "br": 999000, "e_r": true });
+        let request = encrypt_request("/api/song/enhance/player/url", &params).unwrap();
+        assert_eq!(request.url, "/eapi/song/enhance/player/url");
+
+        let decrypted = decrypt_request(&request.body).unwrap();
+        assert_eq!(decrypted.url, "/api/song/enhance/player/url");
+        assert_eq!(decrypted.params, params);
+        assert_eq!(decrypted.params::<Value>().unwrap()["br"], 999000);
+        assert_eq!(decrypted.digest.len(), 32);
+    }
+
+    #[test]
+    fn decrypt_request_digest_mismatch_test() {
+        let data = "/api/song/detail-36cd479b6b5-{}-36cd479b6b5-00000000000000000000000000000000";
+        let body = format!(
+            "params={}",
+            faster_hex::hex_string(&super::encrypt(data.as_bytes()).unwrap()).to_uppercase()
+        );
+
+        assert!(matches!(
+            decrypt_request(&body),
+            Err(CryptoError::DigestMismatch { .. })
+        ));
+        assert!(matches!(
+            decrypt_request("eparams=00"),
+            Err(CryptoError::InvalidPayload(_))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn request_roundtrip(
+            url in "/api/[a-z0-9/]{1,40}",
+            params in prop::collection::hash_map("[a-z_]{1,8}", ".*", 0..8),
+        ) {
+            let request = encrypt_request(&url, &params).unwrap();
+            let decrypted = decrypt_request(&request.body).unwrap();
+
+            prop_assert_eq!(decrypted.url, url);
+            prop_assert_eq!(decrypted.params, json!(params));
+        }
+
+        #[test]
+        fn response_roundtrip(code in any::<i64>(), message in ".*") {
+            let response = json!({ "code": code, "message": message });
+            let encrypted = encrypt_response(&response).unwrap();
+
+            prop_assert_eq!(decrypt_response::<Value>(&encrypted).unwrap(), response);
+        }
+    }
+}
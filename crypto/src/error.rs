@@ -1,12 +1,16 @@
 use std::borrow::Cow;
 
-use openssl::error::ErrorStack;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum CryptoError {
+    #[cfg(feature = "openssl")]
     #[error("Failed in OpenSSL: {0}")]
-    OpenSSLFail(#[from] ErrorStack),
+    OpenSSLFail(#[from] openssl::error::ErrorStack),
+    #[error("Failed in the cipher: {0}")]
+    CipherFail(&'static str),
+    #[error("Failed to generate random bytes: {0}")]
+    RandomFail(String),
     #[error("Failed to XOR this ID char (u32) {0} with this key char (u32) {1}")]
     UriEncryptXorFail(u32, u32),
     #[error("Error in serde_json: {0}")]
@@ -17,6 +21,14 @@ pub enum CryptoError {
     Base64DecodeError(#[from] base64::DecodeError),
     #[error("Failed to read the specified char in the index ({0}) of string ({1})")]
     UnexpectedIndex(usize, Cow<'static, str>),
+    #[error("Failed to decode from hex: {0}")]
+    HexDecodeError(#[from] faster_hex::Error),
+    #[error("The decrypted data is not valid UTF-8: {0}")]
+    Utf8DecodeError(#[from] std::string::FromUtf8Error),
+    #[error("The payload is not in the expected format: {0}")]
+    InvalidPayload(&'static str),
+    #[error("The digest of the request is {found}, but {expected} is expected")]
+    DigestMismatch { expected: String, found: String },
 }
 
 pub type CryptoResult<T> = Result<T, CryptoError>;
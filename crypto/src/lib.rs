@@ -1,4 +1,5 @@
 pub mod aes_128;
+mod backend;
 pub mod base64;
 pub mod eapi;
 pub mod error;
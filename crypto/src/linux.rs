@@ -1,9 +1,10 @@
 use concat_string::concat_string;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use url::Url;
 
 use crate::aes_128;
-use crate::error::CryptoResult;
+use crate::error::{CryptoError, CryptoResult};
 
 const LINUX_API_KEY: &[u8; 16] = b"rFgB&h#%2?^eDg:Q";
 
@@ -49,3 +50,69 @@ pub fn encrypt_request<T: Serialize>(
         ),
     })
 }
+
+/// The request decrypted with [`decrypt_request`].
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DecryptRequestResponse {
+    /// The method of the forwarded request, for example, `POST`.
+    pub method: String,
+    /// The full URL of the forwarded request, for example,
+    /// `https://music.163.com/api/song/enhance/player/url`.
+    pub url: String,
+    /// The parameters of the forwarded request.
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// Decrypt the `body` of the request, which is the form with
+/// the hex-encoded `eparams` made by [`encrypt_request`].
+pub fn decrypt_request(body: &str) -> CryptoResult<DecryptRequestResponse> {
+    let eparams = url::form_urlencoded::parse(body.as_bytes())
+        .find(|(key, _)| key == "eparams")
+        .map(|(_, value)| value)
+        .ok_or(CryptoError::InvalidPayload("no “eparams” in the body"))?;
+
+    let mut encrypted = vec![0; eparams.len() / 2];
+    faster_hex::hex_decode(eparams.as_bytes(), &mut encrypted)?;
+
+    Ok(serde_json::from_slice(&decrypt(&encrypted)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde_json::json;
+
+    use super::{decrypt_request, encrypt_request};
+
+    #[test]
+    fn decrypt_request_test() {
+        let params = json!({ "ids": "[185809]" });
+        let request =
+            encrypt_request("https://music.163.com/api/song/enhance/player/url", &params).unwrap();
+        assert_eq!(request.url, "https://music.163.com/api/linux/forward");
+
+        let decrypted = decrypt_request(&request.body).unwrap();
+        assert_eq!(decrypted.method, "POST");
+        assert_eq!(
+            decrypted.url,
+            "https://music.163.com/api/song/enhance/player/url"
+        );
+        assert_eq!(decrypted.params, params);
+    }
+
+    proptest! {
+        #[test]
+        fn request_roundtrip(
+            path in "/api/[a-z0-9/]{1,40}",
+            params in prop::collection::hash_map("[a-z_]{1,8}", ".*", 0..8),
+        ) {
+            let url = format!("https://music.163.com{path}");
+            let request = encrypt_request(&url, &params).unwrap();
+            let decrypted = decrypt_request(&request.body).unwrap();
+
+            prop_assert_eq!(decrypted.url, url);
+            prop_assert_eq!(decrypted.params, json!(params));
+        }
+    }
+}
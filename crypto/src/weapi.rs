@@ -3,25 +3,19 @@
 //! It is originally from <https://github.com/Binaryify/NeteaseCloudMusicApi/blob/master/util/crypto.js>.
 //! Thanks to Binaryify!
 
-use once_cell::sync::OnceCell;
-use openssl::pkey::Public;
-use openssl::rand::rand_bytes;
-use openssl::rsa::{Padding, Rsa};
 use serde::Serialize;
 use serde_json::Value;
 use smallvec::SmallVec;
 
+use crate::backend;
 use crate::base64::encode;
 use crate::error::{CryptoError, CryptoResult};
 
 const BASE62_CHARSET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
 const WEAPI_PRESET_KEY: &[u8] = b"0CoJUm6Qyw8W8jud";
 const WEAPI_IV: &[u8] = b"0102030405060708";
-const WEAPI_PUBKEY: &[u8] = b"-----BEGIN PUBLIC KEY-----\nMIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDgtQn2JZ34ZC28NWYpAUd98iZ37BUrX/aKzmFbt7clFSs6sXqHauqKWqdtLkF2KexO40H1YTX8z2lSgBBOAxLsvaklV8k4cBFK9snQXE9/DDaFt6Rr7iVZMldczhC0JNgTz+SHXT6CBHuX3e9SdB1Ua44oncaTWz7OBGLbCiK45wIDAQAB\n-----END PUBLIC KEY-----";
-
-static WEAPI_RSA_INSTANCE: OnceCell<Rsa<Public>> = OnceCell::new();
-
-/// Generate random bytes with [`rand_bytes`], and store them in a [`SmallVec`].
+/// Generate random bytes with the cryptographically secure
+/// random number generator of the backend.
 ///
 /// # Example
 ///
@@ -37,7 +31,7 @@ static WEAPI_RSA_INSTANCE: OnceCell<Rsa<Public>> = OnceCell::new();
 pub fn gen_random_bytes<const LEN: usize>() -> CryptoResult<[u8; LEN]> {
     let mut bytes = [0; LEN];
 
-    rand_bytes(bytes.as_mut_slice())?;
+    backend::rand_bytes(bytes.as_mut_slice())?;
 
     Ok(bytes)
 }
@@ -78,10 +72,6 @@ pub fn gen_weapi_secret_key() -> CryptoResult<[u8; 16]> {
     Ok(result)
 }
 
-fn get_weapi_rsa_instance() -> CryptoResult<&'static Rsa<Public>> {
-    Ok(WEAPI_RSA_INSTANCE.get_or_try_init(|| Rsa::public_key_from_pem(WEAPI_PUBKEY))?)
-}
-
 /// Encrypts data using WEAPI's key, returning the number of encrypted bytes.
 pub fn encrypt_with_weapi_rsa(data: &[u8], to: &mut [u8; 128]) -> CryptoResult<usize> {
     let mut padded_data = SmallVec::<[u8; 128]>::new_const();
@@ -89,7 +79,7 @@ pub fn encrypt_with_weapi_rsa(data: &[u8], to: &mut [u8; 128]) -> CryptoResult<u
     padded_data.resize(128 - data.len(), 0);
     padded_data.extend_from_slice(data);
 
-    Ok(get_weapi_rsa_instance()?.public_encrypt(padded_data.as_slice(), to, Padding::NONE)?)
+    backend::weapi_rsa_encrypt(padded_data.as_slice(), to)
 }
 
 pub fn construct_weapi_payload<S: Serialize>(object: &S) -> CryptoResult<Value> {
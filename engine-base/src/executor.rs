@@ -6,14 +6,19 @@ use futures::{FutureExt, StreamExt};
 use log::{debug, error, info, trace, warn};
 use unm_types::{Context, RetrievedSongInfo, SearchMode, Song, SongSearchInformation};
 
-use crate::interface::Engine;
+use crate::interface::{Engine, MetadataResolver};
 
 pub type EngineId = Cow<'static, str>;
 pub type EngineImplementation = Arc<dyn Engine + Send + Sync>;
+pub type MetadataResolverImplementation = Arc<dyn MetadataResolver + Send + Sync>;
+
+/// How many songs are searched at the same time in [`Executor::search_batch`].
+const BATCH_SEARCH_CONCURRENCY: usize = 4;
 
 #[derive(Default)]
 pub struct Executor {
     engine_map: HashMap<EngineId, EngineImplementation>,
+    metadata_resolver: Option<MetadataResolverImplementation>,
 }
 
 impl Executor {
@@ -43,6 +48,13 @@ impl Executor {
         self.engine_map.remove(&engine_id);
     }
 
+    /// Set the resolver for looking up the metadata of a song
+    /// with its NCM track ID. See [`Executor::resolve_metadata`].
+    pub fn set_metadata_resolver(&mut self, resolver: MetadataResolverImplementation) {
+        debug!("Setting the metadata resolver…");
+        self.metadata_resolver = Some(resolver);
+    }
+
     /// Show all the registered engines.
     pub fn list(&self) -> Vec<&str> {
         self.engine_map
@@ -126,6 +138,57 @@ impl Executor {
         })
     }
 
+    /// Search each of the `songs` with the specified engines.
+    ///
+    /// The songs are searched a few at a time, and the results
+    /// are in the same order as `songs`.
+    pub async fn search_batch(
+        &self,
+        engines: &[EngineId],
+        songs: &[Song],
+        ctx: &Context,
+    ) -> Vec<ExecutorResult<SongSearchInformation>> {
+        info!("Searching {} songs with engines {engines:?}", songs.len());
+
+        let futures = songs
+            .iter()
+            .map(|song| self.search(engines, song, ctx).boxed())
+            .collect::<Vec<_>>();
+
+        futures::stream::iter(futures)
+            .buffered(BATCH_SEARCH_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Resolve the metadata of the song with the NCM track `id`,
+    /// so we can search it with only the ID.
+    pub async fn resolve_metadata(&self, id: &str, ctx: &Context) -> ExecutorResult<Song> {
+        info!("Resolving the metadata of the song {id}…");
+
+        let resolver = self
+            .metadata_resolver
+            .as_ref()
+            .ok_or(ExecutorError::MetadataResolverMissing)?;
+
+        resolver
+            .resolve(id, ctx)
+            .await
+            .map_err(ExecutorError::MetadataResolveError)
+    }
+
+    /// Search the song with the NCM track `id`, whose metadata
+    /// is resolved with [`Executor::resolve_metadata`].
+    pub async fn search_by_id(
+        &self,
+        engines: &[EngineId],
+        id: &str,
+        ctx: &Context,
+    ) -> ExecutorResult<SongSearchInformation> {
+        let song = self.resolve_metadata(id, ctx).await?;
+        self.search(engines, &song, ctx).await
+    }
+
     pub async fn retrieve<'a>(
         &self,
         song: &'a SongSearchInformation,
@@ -198,6 +261,12 @@ pub enum ExecutorError {
 
     #[error("No matched song of {keyword}.")]
     NoMatchedSong { keyword: String },
+
+    #[error("No metadata resolver is set.")]
+    MetadataResolverMissing,
+
+    #[error("Error resolving the metadata: {0}")]
+    MetadataResolveError(anyhow::Error),
 }
 
 pub type ExecutorResult<T> = Result<T, ExecutorError>;
@@ -19,3 +19,11 @@ pub trait Engine {
         ctx: &'a Context,
     ) -> anyhow::Result<RetrievedSongInfo>;
 }
+
+#[async_trait]
+/// The resolver that looks up the metadata of a song
+/// with its NetEase Cloud Music track ID.
+pub trait MetadataResolver {
+    /// Resolve the metadata of the song with the NCM track `id`.
+    async fn resolve<'a>(&self, id: &'a str, ctx: &'a Context) -> anyhow::Result<Song>;
+}
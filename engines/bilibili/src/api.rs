@@ -1,13 +1,31 @@
+use http::header::REFERER;
+use http::{HeaderMap, HeaderValue};
 use unm_request::build_client;
 use unm_types::Context;
 use url::Url;
 
-use self::typing::{SearchResult, TrackResult};
+use self::typing::{PageListResult, PlayUrlResult, SearchResult, TrackResult, VideoSearchResult};
+use crate::ENGINE_ID;
 
 pub mod typing;
 
+/// The `Referer` which Bilibili requires for its API and CDN.
+pub const BILIBILI_REFERER: &str = "https://www.bilibili.com/";
+
+/// The `fnval` of the playurl API, which requests the DASH
+/// manifest with all the audio streams, including Hi-Res.
+const PLAYURL_FNVAL: &str = "4048";
+
+/// Construct the header to pass to the Bilibili API.
+pub fn construct_header() -> HeaderMap {
+    let mut hm = HeaderMap::with_capacity(1);
+    hm.insert(REFERER, HeaderValue::from_static(BILIBILI_REFERER));
+
+    hm
+}
+
 pub async fn search(keyword: &str, context: &Context) -> anyhow::Result<SearchResult> {
-    let client = build_client(context.proxy_uri.as_deref())?;
+    let client = build_client(context, ENGINE_ID)?;
     let url = Url::parse_with_params(
         "https://api.bilibili.com/audio/music-service-c/s",
         &[
@@ -23,7 +41,7 @@ pub async fn search(keyword: &str, context: &Context) -> anyhow::Result<SearchRe
 }
 
 pub async fn track(id: &str, context: &Context) -> anyhow::Result<TrackResult> {
-    let client = build_client(context.proxy_uri.as_deref())?;
+    let client = build_client(context, ENGINE_ID)?;
     let url = Url::parse_with_params(
         "https://www.bilibili.com/audio/music-service-c/web/url",
         &[("rivilege", "2"), ("quality", "2"), ("sid", id)],
@@ -32,3 +50,52 @@ pub async fn track(id: &str, context: &Context) -> anyhow::Result<TrackResult> {
     let response = client.get(url).send().await?;
     Ok(response.json::<TrackResult>().await?)
 }
+
+/// Search the regular videos with the `keyword`.
+pub async fn search_video(keyword: &str, context: &Context) -> anyhow::Result<VideoSearchResult> {
+    let client = build_client(context, ENGINE_ID)?;
+    let url = Url::parse_with_params(
+        "https://api.bilibili.com/x/web-interface/search/type",
+        &[
+            ("search_type", "video"),
+            ("page", "1"),
+            ("keyword", keyword),
+        ],
+    )?;
+
+    let response = client.get(url).headers(construct_header()).send().await?;
+    Ok(response.json::<VideoSearchResult>().await?)
+}
+
+/// Get the pages (parts) of the video `bvid`, which have the `cid`s.
+pub async fn video_pages(bvid: &str, context: &Context) -> anyhow::Result<PageListResult> {
+    let client = build_client(context, ENGINE_ID)?;
+    let url = Url::parse_with_params(
+        "https://api.bilibili.com/x/player/pagelist",
+        &[("bvid", bvid)],
+    )?;
+
+    let response = client.get(url).headers(construct_header()).send().await?;
+    Ok(response.json::<PageListResult>().await?)
+}
+
+/// Get the DASH streams of the page `cid` of the video `bvid`.
+pub async fn video_playurl(
+    bvid: &str,
+    cid: i64,
+    context: &Context,
+) -> anyhow::Result<PlayUrlResult> {
+    let client = build_client(context, ENGINE_ID)?;
+    let url = Url::parse_with_params(
+        "https://api.bilibili.com/x/player/playurl",
+        &[
+            ("bvid", bvid),
+            ("cid", &cid.to_string()),
+            ("fnval", PLAYURL_FNVAL),
+            ("fourk", "1"),
+        ],
+    )?;
+
+    let response = client.get(url).headers(construct_header()).send().await?;
+    Ok(response.json::<PlayUrlResult>().await?)
+}
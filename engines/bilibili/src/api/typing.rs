@@ -1,8 +1,12 @@
 use serde::Deserialize;
+use unm_selector::metadata::parse_duration;
 use unm_types::{Artist, Song};
 
 pub type SearchResult = BilibiliApiResponse<BilibiliSearchApiData>;
 pub type TrackResult = BilibiliApiResponse<BilibiliTrackApiData>;
+pub type VideoSearchResult = BilibiliVideoApiResponse<BilibiliVideoSearchApiData>;
+pub type PageListResult = BilibiliVideoApiResponse<Vec<BilibiliVideoPage>>;
+pub type PlayUrlResult = BilibiliVideoApiResponse<BilibiliPlayUrlApiData>;
 
 #[derive(Debug, Clone, Deserialize)]
 #[non_exhaustive]
@@ -36,6 +40,10 @@ pub struct BilibiliSearchResult {
 
     /// The artist name.
     pub author: String,
+
+    /// The duration of this song in second.
+    #[serde(default)]
+    pub duration: Option<i64>,
 }
 
 impl From<BilibiliSearchResult> for Song {
@@ -45,6 +53,7 @@ impl From<BilibiliSearchResult> for Song {
         Song::builder()
             .id(result.id.to_string())
             .name(result.title)
+            .duration(result.duration.map(|d| d * 1000))
             .artists(vec![Artist::builder()
                 .id(result.mid.to_string())
                 .name(result.author)
@@ -60,3 +69,180 @@ impl BilibiliTrackApiData {
         self.cdns.get(0).map(|s| s.replace("https", "http"))
     }
 }
+
+/// The response of the video APIs, whose `data` is absent on errors.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliVideoApiResponse<T> {
+    /// The error code. Should be `0`.
+    pub code: i64,
+    #[serde(default)]
+    pub message: String,
+    pub data: Option<T>,
+}
+
+impl<T> BilibiliVideoApiResponse<T> {
+    /// Get the `data`, or the error in this response.
+    pub fn into_data(self) -> anyhow::Result<T> {
+        match self.data {
+            Some(data) if self.code == 0 => Ok(data),
+            _ => Err(anyhow::anyhow!(
+                "Bilibili API error {}: {}",
+                self.code,
+                self.message
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliVideoSearchApiData {
+    /// The videos, which is absent if nothing is found.
+    #[serde(default)]
+    pub result: Vec<BilibiliVideoSearchResult>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliVideoSearchResult {
+    /// The BV ID of this video.
+    pub bvid: String,
+
+    /// The title of this video, with the keywords in `<em>`.
+    pub title: String,
+
+    /// The uploader ID.
+    pub mid: i64,
+
+    /// The uploader name.
+    pub author: String,
+
+    /// The duration of this video, in `m:ss` or `h:mm:ss`.
+    pub duration: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliVideoPage {
+    /// The content ID of this page.
+    pub cid: i64,
+
+    /// The page number, starting from 1.
+    pub page: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliPlayUrlApiData {
+    pub dash: Option<BilibiliDash>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliDash {
+    /// The audio streams in AAC.
+    #[serde(default)]
+    pub audio: Option<Vec<BilibiliDashStream>>,
+
+    /// The Hi-Res audio stream in FLAC.
+    #[serde(default)]
+    pub flac: Option<BilibiliDashFlac>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliDashFlac {
+    pub audio: Option<BilibiliDashStream>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct BilibiliDashStream {
+    /// The quality ID, for example, `30280` for 192K.
+    pub id: i64,
+
+    /// The URL of this stream. The response has both `baseUrl` and
+    /// `base_url`, so we can't alias them.
+    #[serde(rename = "baseUrl")]
+    pub base_url: String,
+
+    /// The bitrate (bps).
+    #[serde(default)]
+    pub bandwidth: i64,
+}
+
+impl BilibiliDash {
+    /// Get the best audio stream. The Hi-Res one is only
+    /// chosen if `lossless` is `true`.
+    pub fn best_audio(&self, lossless: bool) -> Option<&BilibiliDashStream> {
+        log::debug!("Getting the best audio stream from BilibiliDash…");
+
+        let flac = self
+            .flac
+            .as_ref()
+            .and_then(|flac| flac.audio.as_ref())
+            .filter(|_| lossless);
+
+        flac.or_else(|| {
+            self.audio
+                .iter()
+                .flatten()
+                .max_by_key(|stream| stream.bandwidth)
+        })
+    }
+}
+
+/// Remove the HTML tags, such as `<em class="keyword">`, in the title,
+/// and unescape the entities.
+fn clean_title(title: &str) -> String {
+    let mut cleaned = String::with_capacity(title.len());
+    let mut in_tag = false;
+
+    for c in title.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => cleaned.push(c),
+            _ => {}
+        }
+    }
+
+    cleaned
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+impl From<BilibiliVideoSearchResult> for Song {
+    fn from(result: BilibiliVideoSearchResult) -> Self {
+        log::trace!("Converting BilibiliVideoSearchResult to Song…");
+
+        Song::builder()
+            .id(result.bvid)
+            .name(clean_title(&result.title))
+            .duration(parse_duration(&result.duration))
+            .artists(vec![Artist::builder()
+                .id(result.mid.to_string())
+                .name(result.author)
+                .build()])
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clean_title;
+
+    #[test]
+    fn clean_title_test() {
+        assert_eq!(
+            clean_title(
+                r#"【Hi-Res】<em class="keyword">青花瓷</em> - <em class="keyword">周杰伦</em> &amp; MV"#
+            ),
+            "【Hi-Res】青花瓷 - 周杰伦 & MV"
+        );
+    }
+}
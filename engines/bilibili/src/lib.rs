@@ -1,17 +1,23 @@
 //! UNM Engine: Bilibili
 //!
+//! It searches both the audio area of Bilibili Music and the regular
+//! videos. The audio of a video (whose identifier is its BV ID) is
+//! the best audio-only stream in its DASH manifest, which is Hi-Res
+//! FLAC if it is available and `enable_flac` is set.
+//!
 //! Note that you may need to include the following
 //! headers to fetch the song from the retrieved URL:
 //!
 //! ```http
 //! Referer: https://www.bilibili.com/
 //! User-Agent: okhttp/3.4.1
-//! ``
+//! ```
 
 pub mod api;
 
+use api::typing::{BilibiliSearchResult, BilibiliVideoSearchResult};
 use async_trait::async_trait;
-use log::{debug, info};
+use log::{debug, info, warn};
 use unm_engine::interface::Engine;
 use unm_selector::SimilarSongSelector;
 use unm_types::{Context, RetrievedSongInfo, SerializedIdentifier, Song, SongSearchInformation};
@@ -30,12 +36,25 @@ impl Engine for BilibiliEngine {
     ) -> anyhow::Result<Option<SongSearchInformation>> {
         info!("Searching with Bilibili engine…");
 
-        let response = api::search(&info.keyword(), ctx).await?;
-        let mut song_iterator = response.data.result.into_iter().map(Song::from);
+        let keyword = info.keyword();
+        let (audios, videos) =
+            futures::join!(api::search(&keyword, ctx), api::search_video(&keyword, ctx));
+
+        let audios = audios.map(|r| r.data.result);
+        let videos = videos.and_then(|r| r.into_data()).map(|d| d.result);
 
-        debug!("Matching the song…");
-        let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(info);
-        let matched = song_iterator.find(|s| selector(&s));
+        let matched = match (audios, videos) {
+            (Ok(audios), Ok(videos)) => find_match(info, audios, videos),
+            (Ok(audios), Err(e)) => {
+                warn!("Failed to search in one of the areas: {e}");
+                find_match(info, audios, Vec::new())
+            }
+            (Err(e), Ok(videos)) => {
+                warn!("Failed to search in one of the areas: {e}");
+                find_match(info, Vec::new(), videos)
+            }
+            (Err(e), Err(_)) => return Err(e),
+        };
 
         Ok(matched.map(|song| {
             SongSearchInformation::builder()
@@ -53,6 +72,10 @@ impl Engine for BilibiliEngine {
     ) -> anyhow::Result<RetrievedSongInfo> {
         info!("Retrieving the song by identifier…");
 
+        if is_video(identifier) {
+            return retrieve_video(identifier, ctx).await;
+        }
+
         let response = api::track(identifier, ctx).await?;
         let url = response
             .data
@@ -65,3 +88,127 @@ impl Engine for BilibiliEngine {
             .build())
     }
 }
+
+/// Find the song similar to `info` in the search results,
+/// preferring the songs in the audio area to the videos.
+pub fn find_match(
+    info: &Song,
+    audios: Vec<BilibiliSearchResult>,
+    videos: Vec<BilibiliVideoSearchResult>,
+) -> Option<Song> {
+    debug!("Matching the song…");
+    let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(info);
+
+    audios
+        .into_iter()
+        .map(Song::from)
+        .chain(videos.into_iter().map(Song::from))
+        .find(|s| selector(&s))
+}
+
+/// Whether the `identifier` is the BV ID of a video,
+/// instead of the song ID in the audio area.
+fn is_video(identifier: &str) -> bool {
+    identifier.starts_with("BV")
+}
+
+/// Retrieve the best audio stream of the first page of the video `bvid`.
+async fn retrieve_video(bvid: &str, ctx: &Context) -> anyhow::Result<RetrievedSongInfo> {
+    debug!("Retrieving the audio of the video “{bvid}”…");
+
+    let pages = api::video_pages(bvid, ctx).await?.into_data()?;
+    let page = pages
+        .iter()
+        .min_by_key(|page| page.page)
+        .ok_or_else(|| anyhow::anyhow!("no page in the video “{bvid}”"))?;
+
+    let playurl = api::video_playurl(bvid, page.cid, ctx).await?.into_data()?;
+    let stream = playurl
+        .dash
+        .as_ref()
+        .and_then(|dash| dash.best_audio(ctx.enable_flac))
+        .ok_or_else(|| anyhow::anyhow!("no audio stream in the video “{bvid}”"))?;
+    debug!("Picked the audio stream {} of “{bvid}”.", stream.id);
+
+    Ok(RetrievedSongInfo::builder()
+        .source(ENGINE_ID.into())
+        .url(stream.base_url.clone())
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use unm_engine::interface::Engine;
+    use unm_test_utils::{sample_song, search_and_retrieve, with_cassette};
+    use unm_types::{Context, ContextBuilder};
+
+    use super::{BilibiliEngine, ENGINE_ID};
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) = search_and_retrieve(
+                &BilibiliEngine,
+                ENGINE_ID,
+                &sample_song(),
+                &Context::default(),
+            )
+            .await;
+
+            assert_eq!(info.identifier, "1432957");
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.name, "青花瓷（Cover 周杰伦）");
+            assert_eq!(song.duration, Some(241000));
+            assert_eq!(song.artists[0].id, "16839145");
+            assert_eq!(song.artists[0].name, "一只小雪莉");
+
+            assert!(retrieved
+                .url
+                .starts_with("http://upos-sz-mirrorkodo.bilivideo.com/ugaxcode/"));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_search_and_retrieve_video() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search_video.json");
+
+        with_cassette(fixture, async {
+            let ctx = Context::default();
+            let info = BilibiliEngine
+                .search(&sample_song(), &ctx)
+                .await
+                .unwrap()
+                .expect("should have a search result");
+
+            // The piano version and the live version don't match the duration.
+            assert_eq!(info.identifier, "BV1Fs411Q7aM");
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.name, "【Hi-Res】周杰伦 - 青花瓷");
+            assert_eq!(song.duration, Some(239000));
+
+            let retrieved = BilibiliEngine
+                .retrieve(&info.identifier, &ctx)
+                .await
+                .unwrap();
+            assert_eq!(retrieved.source, ENGINE_ID);
+            assert!(retrieved.url.contains("/1187349201-1-30280.m4a?"));
+        })
+        .await;
+
+        with_cassette(fixture, async {
+            let ctx = ContextBuilder::default().enable_flac(true).build().unwrap();
+            let retrieved = BilibiliEngine
+                .retrieve(&"BV1Fs411Q7aM".into(), &ctx)
+                .await
+                .unwrap();
+            assert!(retrieved.url.contains("/1187349201-1-30251.m4a?"));
+        })
+        .await;
+    }
+}
@@ -4,6 +4,10 @@
 //! obtain its cookie and fill the cookie to
 //! the `joox:cookie` config.
 //!
+//! The cookie seeds the cookie jar of Joox, so the cookies refreshed
+//! by Joox are kept (and persisted if `ctx.cookie_dir` is set).
+//! If the session has expired, update the cookie in the config.
+//!
 //! You can configure the cookie in the `ctx.config` field.
 //! You can build a `ctx.config` with [`unm_types::config::ConfigManagerBuilder`],
 //! for example:
@@ -24,7 +28,7 @@ use std::borrow::Cow;
 
 use base64::prelude::*;
 use base64::Engine as B64Engine;
-use http::header::{COOKIE, ORIGIN, REFERER};
+use http::header::{ORIGIN, REFERER};
 use http::HeaderValue;
 use once_cell::sync::OnceCell;
 use regex::Regex;
@@ -32,6 +36,7 @@ use reqwest::header::HeaderMap;
 use reqwest::Url;
 use unm_engine::interface::Engine;
 use unm_request::build_client;
+use unm_request::cookie::cookie_jar;
 use unm_request::ext::ResponseExt;
 use unm_request::json::{Json, UnableToExtractJson};
 use unm_selector::SimilarSongSelector;
@@ -48,6 +53,9 @@ use unm_types::{
 static REPLACE_AUDIO_URL_REGEX: OnceCell<Regex> = OnceCell::new();
 
 pub const ENGINE_ID: &str = "joox";
+
+/// The domains that the cookie of Joox is sent to.
+const COOKIE_DOMAINS: &[&str] = &["joox.com", "sanook.com"];
 pub struct JooxEngine;
 
 #[async_trait::async_trait]
@@ -60,9 +68,9 @@ impl Engine for JooxEngine {
         log::debug!("Searching “{song}” with Joox Engine…");
 
         let keyword = fit(song);
-        let joox_cookie = get_cookie(ctx);
+        seed_cookie(ctx);
 
-        let client = build_client(ctx.proxy_uri.as_deref())?;
+        let client = build_client(ctx, ENGINE_ID)?;
         let url = Url::parse_with_params(
             "http://api-jooxtt.sanook.com/web-fcgi-bin/web_search",
             &[
@@ -74,34 +82,13 @@ impl Engine for JooxEngine {
             ],
         )?;
 
-        let response = client
-            .get(url)
-            .headers(construct_header(joox_cookie)?)
-            .send()
-            .await?;
+        let response = client.get(url).headers(construct_header()).send().await?;
 
         log::debug!("Deserializing the response of “{song}”…");
-        let json_string = response.text().await?.replace('\'', "\"");
-        let json = serde_json::from_str::<Json>(&json_string)?;
+        let json = response.js_literal::<Json>().await?;
 
-        log::debug!("Converting the Joox response to Vec<Song>…");
         let empty = Vec::new();
-        let mut song_iterator = json["itemlist"]
-            .as_array()
-            .unwrap_or(&empty)
-            .iter()
-            .map(format)
-            .filter_map(|v| match v {
-                Ok(v) => Some(v),
-                Err(e) => {
-                    log::warn!("Failed to parse an item: {e}. Ignoring.");
-                    None
-                }
-            });
-
-        log::debug!("Selecting the similar song…");
-        let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(song);
-        let matched = song_iterator.find(|s| selector(&s));
+        let matched = find_match(song, json["itemlist"].as_array().unwrap_or(&empty));
 
         Ok(matched.map(|matched| {
             SongSearchInformation::builder()
@@ -122,9 +109,9 @@ impl Engine for JooxEngine {
 
         let replace_audio_url_regex = REPLACE_AUDIO_URL_REGEX
             .get_or_init(|| Regex::new(r"M\d00([\w]+)\.mp3").expect("should be constructable"));
-        let joox_cookie = get_cookie(ctx);
+        seed_cookie(ctx);
 
-        let client = build_client(ctx.proxy_uri.as_deref())?;
+        let client = build_client(ctx, ENGINE_ID)?;
         let url = Url::parse_with_params(
             "http://api.joox.com/web-fcgi-bin/web_get_songinfo",
             &[
@@ -137,11 +124,7 @@ impl Engine for JooxEngine {
             ],
         )?;
 
-        let response = client
-            .get(url)
-            .headers(construct_header(joox_cookie)?)
-            .send()
-            .await?;
+        let response = client.get(url).headers(construct_header()).send().await?;
         let json = response.jsonp::<Json>().await?;
 
         let raw_audio_url = ["r320Url", "r192Url", "mp3Url", "m4aUrl"]
@@ -156,6 +139,8 @@ impl Engine for JooxEngine {
                 .url(url.to_string())
                 .build())
         } else {
+            // The audio is likely unavailable because the session has expired.
+            cookie_jar(ctx, ENGINE_ID).check_session()?;
             Err(anyhow::anyhow!("No audio URL found."))
         }
     }
@@ -169,7 +154,14 @@ fn get_cookie(context: &Context) -> Option<&str> {
     }
 }
 
-fn construct_header(cookie: Option<&str>) -> anyhow::Result<HeaderMap> {
+/// Seed the cookie jar of Joox with `joox:cookie`.
+fn seed_cookie(context: &Context) {
+    if let Some(cookie) = get_cookie(context) {
+        cookie_jar(context, ENGINE_ID).seed(COOKIE_DOMAINS, cookie);
+    }
+}
+
+fn construct_header() -> HeaderMap {
     log::debug!("Constructing the header for Joox…");
 
     let mut hm = HeaderMap::new();
@@ -177,11 +169,7 @@ fn construct_header(cookie: Option<&str>) -> anyhow::Result<HeaderMap> {
     hm.insert(ORIGIN, HeaderValue::from_static("http://www.joox.com"));
     hm.insert(REFERER, HeaderValue::from_static("http://www.joox.com"));
 
-    if let Some(cookie) = cookie {
-        hm.insert(COOKIE, HeaderValue::from_str(cookie)?);
-    }
-
-    Ok(hm)
+    hm
 }
 
 fn fit(song: &Song) -> String {
@@ -197,7 +185,24 @@ fn fit(song: &Song) -> String {
     }
 }
 
-fn format(item: &Json) -> anyhow::Result<Song> {
+/// Find the song similar to `song` in the search results, the `itemlist` items.
+pub fn find_match(song: &Song, items: &[Json]) -> Option<Song> {
+    log::debug!("Converting the Joox response to Vec<Song>…");
+    let mut song_iterator = items.iter().map(format).filter_map(|v| match v {
+        Ok(v) => Some(v),
+        Err(e) => {
+            log::warn!("Failed to parse an item: {e}. Ignoring.");
+            None
+        }
+    });
+
+    log::debug!("Selecting the similar song…");
+    let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(song);
+    song_iterator.find(|s| selector(&s))
+}
+
+/// Convert an item of the search results to [`Song`].
+pub fn format(item: &Json) -> anyhow::Result<Song> {
     log::debug!("Formatting a Joox song item…");
 
     let valstr = |data: &Json, json_pointer| {
@@ -266,3 +271,39 @@ fn get_timestamp() -> u128 {
         .map(|v| v.as_millis())
         .unwrap_or(0)
 }
+
+#[cfg(test)]
+mod tests {
+    use unm_test_utils::{sample_song, search_and_retrieve, with_cassette};
+    use unm_types::Context;
+
+    use super::{JooxEngine, ENGINE_ID};
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) =
+                search_and_retrieve(&JooxEngine, ENGINE_ID, &sample_song(), &Context::default())
+                    .await;
+
+            assert_eq!(info.identifier, "tV3+7lSQo7xbGbcBfV0b5Q==");
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.name, "青花瓷");
+            assert_eq!(song.duration, Some(239000));
+            assert_eq!(song.artists[0].id, "4558");
+            assert_eq!(song.artists[0].name, "周杰倫");
+            let album = song.album.expect("should have the album");
+            assert_eq!(album.id, "0024bjiL2aocxT");
+            assert_eq!(album.name, "我很忙");
+
+            // The M500 audio should be replaced with the M800 one.
+            assert_eq!(retrieved.url, "http://stream.music.joox.com/M800002qU5aY3Qu24y.mp3?guid=2965523218&vkey=8C0D5E7F1A2B3C4D5E6F7A8B9C0D1E2F3A4B5C6D");
+        })
+        .await;
+    }
+}
@@ -1,6 +1,10 @@
 //! UNM Engine: Kugou
 //!
 //! It can fetch audio from Kugou Music.
+//!
+//! The search results are fetched page by page (up to [`MAX_PAGES`])
+//! until a similar song is found. The entries without copyright, or
+//! without any playable hash, are skipped.
 
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -14,9 +18,10 @@ use serde::{Deserialize, Serialize};
 use unm_engine::interface::Engine;
 use unm_request::build_client;
 use unm_request::json::{Json, UnableToExtractJson};
-use unm_selector::SimilarSongSelector;
+use unm_selector::pages::{find_in_pages, find_similar, SearchPage};
 use unm_types::{
     Album,
+    Artist,
     Context,
     RetrievedSongInfo,
     SerializedIdentifier,
@@ -26,6 +31,18 @@ use unm_types::{
 
 pub const ENGINE_ID: &str = "kugou";
 
+/// The entries in a page of the search results.
+const PAGE_SIZE: usize = 10;
+
+/// The max pages of the search results to fetch.
+pub const MAX_PAGES: usize = 3;
+
+/// The separators between the artists in `singername`.
+const ARTIST_SEPARATORS: &[char] = &['、', '&'];
+
+/// The `privilege` of the entries without copyright, which can't be played.
+const PRIVILEGE_NO_COPYRIGHT: i64 = 5;
+
 /// The search and track engine powered by Kugou Music.
 pub struct KugouEngine;
 
@@ -118,13 +135,14 @@ impl Engine for KugouEngine {
 
         let song: Arc<Song> = Arc::new(serde_json::from_str(identifier)?);
 
-        let format_to_fetch = if ctx.enable_flac {
-            [KugouFormat::SqHash, KugouFormat::HqHash]
+        // Some songs only have the standard hash, so always fall back to it.
+        let format_to_fetch: &[KugouFormat] = if ctx.enable_flac {
+            &[KugouFormat::SqHash, KugouFormat::HqHash, KugouFormat::Hash]
         } else {
-            [KugouFormat::HqHash, KugouFormat::Hash]
+            &[KugouFormat::HqHash, KugouFormat::Hash]
         };
 
-        let song_futures = format_to_fetch.into_iter().map(|format| {
+        let song_futures = format_to_fetch.iter().copied().map(|format| {
             let song = song.clone();
 
             async move {
@@ -151,7 +169,33 @@ impl Engine for KugouEngine {
     }
 }
 
-fn format(entry: &Json) -> anyhow::Result<Song> {
+/// The hash of the audio in `key`, if it is present.
+///
+/// Kugou fills the absent hashes with an empty string or zeros.
+fn valid_hash(entry: &Json, key: &str) -> Option<String> {
+    entry[key]
+        .as_str()
+        .filter(|hash| !hash.is_empty() && hash.chars().any(|c| c != '0'))
+        .map(|hash| hash.to_string())
+}
+
+/// Parse the artists in `singername`, for example, `周杰伦、费玉清`.
+fn parse_artists(singername: &str) -> Vec<Artist> {
+    singername
+        .split(ARTIST_SEPARATORS)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| Artist::builder().name(name.to_string()).build())
+        .collect()
+}
+
+/// Whether the entry can be played, according to its `privilege`.
+fn is_playable(entry: &Json) -> bool {
+    entry["privilege"].as_i64() != Some(PRIVILEGE_NO_COPYRIGHT)
+}
+
+/// Convert an entry of the search results to [`Song`].
+pub fn format(entry: &Json) -> anyhow::Result<Song> {
     debug!("Formatting the response from Kugou Music…");
 
     let valstr = |key| {
@@ -164,59 +208,120 @@ fn format(entry: &Json) -> anyhow::Result<Song> {
             .map(|v| v.to_string())
     };
 
+    let context = KugouSongContext {
+        id: valid_hash(entry, "hash"),
+        id_hq: valid_hash(entry, "320hash"),
+        id_sq: valid_hash(entry, "sqhash"),
+    };
+    if context.id.is_none() && context.id_hq.is_none() && context.id_sq.is_none() {
+        return Err(anyhow::anyhow!("no playable hash"));
+    }
+
+    let album = match (entry["album_id"].as_str(), entry["album_name"].as_str()) {
+        (Some(id), Some(name)) if !name.is_empty() => Some(
+            Album::builder()
+                .id(id.to_string())
+                .name(name.to_string())
+                .build(),
+        ),
+        _ => None,
+    };
+
     Ok(Song::builder()
         .id(valstr("hash")?)
         .name(valstr("songname")?)
         .duration(entry["duration"].as_i64().map(|v| v * 1000))
-        .artists(vec![])
-        .album(Some(
-            Album::builder()
-                .id(valstr("album_id")?)
-                .name(valstr("album_name")?)
-                .build(),
-        ))
-        .context(Some(
-            KugouSongContext {
-                id: entry["hash"].as_str().map(|v| v.to_string()),
-                id_hq: entry["320hash"].as_str().map(|v| v.to_string()),
-                id_sq: entry["sqhash"].as_str().map(|v| v.to_string()),
-            }
-            .into(),
+        .artists(parse_artists(
+            entry["singername"].as_str().unwrap_or_default(),
         ))
+        .album(album)
+        .context(Some(context.into()))
         .build())
 }
 
-/// Search and get song (with metadata) from Kugou Music.
-pub async fn search(info: &Song, ctx: &Context) -> anyhow::Result<Option<Song>> {
-    info!("Searching with Kugou Engine…");
-
-    let client = build_client(ctx.proxy_uri.as_deref())?;
-    let url = Url::parse_with_params(
-        "http://mobilecdn.kugou.com/api/v3/search/song?page=1&pagesize=10",
-        &[("keyword", &info.keyword())],
-    )?;
+/// Whether the `song` shares an artist with the `expected` one.
+///
+/// It is `true` if either of them has no artist.
+fn has_common_artist(expected: &Song, song: &Song) -> bool {
+    if expected.artists.is_empty() || song.artists.is_empty() {
+        return true;
+    }
 
-    let response = client.get(url).send().await?;
-    let data = response.json::<Json>().await?;
+    expected.artists.iter().any(|expected| {
+        song.artists
+            .iter()
+            .any(|artist| artist.name.eq_ignore_ascii_case(&expected.name))
+    })
+}
 
-    debug!("Extracting data…");
-    let lists = data
-        .pointer("/data/info")
-        .and_then(|v| v.as_array())
-        .ok_or(UnableToExtractJson {
-            json_pointer: "/data/info",
-            expected_type: "string",
-        })?;
-
-    debug!("Finding the similar song…");
-    let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(info);
-    let similar_song = lists
+/// The playable entries in `entries` which share an artist with `info`.
+fn candidates(info: &Song, entries: &[Json]) -> Vec<Song> {
+    entries
         .iter()
+        .filter(|entry| is_playable(entry))
         .map(format)
-        .filter_map(|v| v.ok())
-        .find(|s| selector(&s));
+        .filter_map(|v| match v {
+            Ok(v) => Some(v),
+            Err(e) => {
+                debug!("Skipping an entry: {e}");
+                None
+            }
+        })
+        .filter(|song| has_common_artist(info, song))
+        .collect()
+}
 
-    Ok(similar_song)
+/// Find the song similar to `info` in a page of the search results,
+/// the `/data/info` entries.
+pub fn find_match(info: &Song, entries: &[Json]) -> Option<Song> {
+    find_similar(info, candidates(info, entries))
+}
+
+/// Search and get song (with metadata) from Kugou Music.
+pub async fn search(info: &Song, ctx: &Context) -> anyhow::Result<Option<Song>> {
+    info!("Searching with Kugou Engine…");
+
+    let client = build_client(ctx, ENGINE_ID)?;
+    let keyword = info.keyword();
+
+    find_in_pages(info, MAX_PAGES, |page| {
+        let (client, keyword) = (&client, &keyword);
+
+        async move {
+            debug!("Fetching the page {page} of the search results…");
+
+            let url = Url::parse_with_params(
+                "http://mobilecdn.kugou.com/api/v3/search/song",
+                &[
+                    ("page", page.to_string().as_str()),
+                    ("pagesize", PAGE_SIZE.to_string().as_str()),
+                    ("keyword", keyword),
+                ],
+            )?;
+
+            let response = client.get(url).send().await?;
+            let data = response.json::<Json>().await?;
+
+            debug!("Extracting data…");
+            let lists = data
+                .pointer("/data/info")
+                .and_then(|v| v.as_array())
+                .ok_or(UnableToExtractJson {
+                    json_pointer: "/data/info",
+                    expected_type: "array",
+                })?;
+            let total = data
+                .pointer("/data/total")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default() as usize;
+
+            anyhow::Ok(SearchPage {
+                songs: candidates(info, lists),
+                is_last: lists.is_empty() || page * PAGE_SIZE >= total,
+            })
+        }
+    })
+    .await
 }
 
 pub async fn single(
@@ -235,7 +340,7 @@ pub async fn single(
         .map(|v| v.id.to_string())
         .unwrap_or_else(|| String::from(""));
 
-    let client = build_client(ctx.proxy_uri.as_deref())?;
+    let client = build_client(ctx, ENGINE_ID)?;
     let url = Url::parse_with_params(
         "http://trackercdn.kugou.com/i/v2/?appid=1005&pid=2&cmd=25&behavior=play",
         &[("key", &key), ("hash", &hash), ("album_id", &album_id)],
@@ -265,3 +370,130 @@ pub fn extract_hash_id(song: &Song, format: KugouFormat) -> anyhow::Result<Strin
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use unm_engine::interface::Engine;
+    use unm_test_utils::{sample_song, search_and_retrieve, with_cassette};
+    use unm_types::{Context, ContextBuilder};
+
+    use super::{format, parse_artists, search, KugouEngine, ENGINE_ID};
+
+    #[test]
+    fn test_parse_artists() {
+        let artists = parse_artists("周杰伦、费玉清 & Lara");
+        let names = artists.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["周杰伦", "费玉清", "Lara"]);
+
+        assert!(parse_artists("").is_empty());
+    }
+
+    #[test]
+    fn test_format_skips_unplayable_hashes() {
+        let entry = json!({
+            "hash": "00000000000000000000000000000000",
+            "songname": "青花瓷",
+            "singername": "周杰伦",
+            "320hash": "",
+            "sqhash": "",
+        });
+        assert!(format(&entry).is_err());
+
+        let entry = json!({
+            "hash": "",
+            "songname": "青花瓷",
+            "singername": "周杰伦",
+            "320hash": "A1C9C4C5E8F1D8E0D0A7F5B2F2C7E1A8",
+            "album_id": "",
+            "album_name": "",
+        });
+        let song = format(&entry).unwrap();
+        assert!(song.album.is_none());
+        let context = song.context.unwrap();
+        assert_eq!(
+            context.get("hqhash").map(String::as_str),
+            Some("A1C9C4C5E8F1D8E0D0A7F5B2F2C7E1A8")
+        );
+        assert!(!context.contains_key("hash"));
+    }
+
+    #[tokio::test]
+    async fn test_search_next_page() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search_pages.json");
+
+        with_cassette(fixture, async {
+            // The cover, the one without copyright and the one without
+            // any hash on the first page should be skipped.
+            let song = search(&sample_song(), &Context::default())
+                .await
+                .unwrap()
+                .expect("should have a search result");
+
+            assert_eq!(song.id, "9D5E3C1B7A2F4E6D8C0B1A2F3E4D5C6B");
+            let artists = song
+                .artists
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(artists, ["周杰伦", "费玉清"]);
+            assert_eq!(song.album.unwrap().name, "2009 周杰伦 & 费玉清 合辑");
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) =
+                search_and_retrieve(&KugouEngine, ENGINE_ID, &sample_song(), &Context::default())
+                    .await;
+
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.id, "B2F11F3E0D1C5F1B8D5C1A9F4E9A3E13");
+            assert_eq!(song.name, "青花瓷");
+            assert_eq!(song.duration, Some(239000));
+            assert_eq!(song.artists[0].name, "周杰伦");
+            let album = song.album.expect("should have the album");
+            assert_eq!(album.id, "966846");
+            assert_eq!(album.name, "我很忙");
+
+            // The standard hash needs to pay, so we get the HQ one.
+            assert_eq!(retrieved.url, "http://fs.youthandroid2.kugou.com/202308011205/3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b/KGTX/CLTX001/a1c9c4c5e8f1d8e0d0a7f5b2f2c7e1a8.mp3");
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_flac_falls_back_to_plain_hash() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/retrieve_plain_hash.json"
+        );
+        // The song only has the standard hash.
+        let song = format(&json!({
+            "hash": "C3D2E1F0A9B8C7D6E5F4A3B2C1D0E9F8",
+            "songname": "青花瓷",
+            "singername": "周杰伦",
+            "320hash": "",
+            "sqhash": "",
+            "album_id": "966846",
+            "album_name": "我很忙",
+        }))
+        .unwrap();
+        let identifier = serde_json::to_string(&song).unwrap();
+        let ctx = ContextBuilder::default().enable_flac(true).build().unwrap();
+
+        with_cassette(fixture, async {
+            let retrieved = KugouEngine.retrieve(&identifier, &ctx).await.unwrap();
+
+            assert_eq!(retrieved.url, "http://fs.youthandroid2.kugou.com/202308011205/5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e/KGTX/CLTX001/c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8.mp3");
+        })
+        .await;
+    }
+}
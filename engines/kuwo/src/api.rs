@@ -1,15 +1,15 @@
 pub mod typing;
 
-use cached::proc_macro::once;
-use concat_string::concat_string;
-use http::header::{COOKIE, REFERER};
+use http::header::{REFERER, USER_AGENT};
 use http::HeaderValue;
 use reqwest::header::HeaderMap;
 use reqwest::Url;
 use unm_request::build_client;
+use unm_request::cookie::{cookie_jar, StoredCookie};
 use unm_types::Context;
 
-use self::typing::{GetPlayUrlResponse, MusicID, SearchResponse};
+use self::typing::{GetPlayUrlResponse, KuwoConvertUrlResponse, MusicID, SearchResponse};
+use crate::ENGINE_ID;
 
 pub fn genenate_kw_token() -> String {
     log::debug!("Generating kw_token…");
@@ -18,16 +18,38 @@ pub fn genenate_kw_token() -> String {
     random_string::generate(11, charset)
 }
 
-#[once(time = "3600" /* 1hr */, result = true)]
-pub fn construct_header() -> anyhow::Result<HeaderMap> {
-    log::debug!("Constructing header to pass to Kuwo Music…");
+/// How long a generated `kw_token` lives, in seconds.
+const KW_TOKEN_LIFETIME: u64 = 3600;
+
+/// Get the `kw_token` in the cookie jar of Kuwo,
+/// or generate one if there is no valid token.
+pub fn get_kw_token(ctx: &Context) -> String {
+    let jar = cookie_jar(ctx, ENGINE_ID);
+    let url = Url::parse("http://www.kuwo.cn/").expect("should be a valid URL");
+
+    if let Some(token) = jar.get(&url, "kw_token") {
+        return token;
+    }
+
     let token = genenate_kw_token();
-    let mut hm = HeaderMap::with_capacity(3);
+    let mut cookie = StoredCookie::new("kw_token", token.clone(), "kuwo.cn");
+    cookie.expires = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|now| now.as_secs() + KW_TOKEN_LIFETIME);
+    jar.insert(cookie);
+
+    token
+}
+
+/// Construct the header to pass to Kuwo Music.
+///
+/// The `kw_token` cookie is sent by the client with the cookie jar.
+pub fn construct_header(ctx: &Context) -> anyhow::Result<HeaderMap> {
+    log::debug!("Constructing header to pass to Kuwo Music…");
+    let token = get_kw_token(ctx);
+    let mut hm = HeaderMap::with_capacity(2);
 
-    hm.insert(
-        COOKIE,
-        HeaderValue::from_str(&concat_string!("kw_token=", token))?,
-    );
     hm.insert(REFERER, HeaderValue::from_static("http://www.kuwo.cn/"));
     hm.insert("csrf", HeaderValue::from_str(&token)?);
 
@@ -42,7 +64,7 @@ pub async fn search_music_by_keyword(
 ) -> anyhow::Result<SearchResponse> {
     log::debug!("Searching music in Kuwo by keyword “{keyword}”… [Page {page_number}, {entries_per_page} entries]");
 
-    let client = build_client(ctx.proxy_uri.as_deref())?;
+    let client = build_client(ctx, ENGINE_ID)?;
     let url = Url::parse_with_params(
         "http://www.kuwo.cn/api/www/search/searchMusicBykeyWord",
         &[
@@ -53,7 +75,11 @@ pub async fn search_music_by_keyword(
         ],
     )?;
 
-    let response = client.get(url).headers(construct_header()?).send().await?;
+    let response = client
+        .get(url)
+        .headers(construct_header(ctx)?)
+        .send()
+        .await?;
     let json = response.json::<SearchResponse>().await?;
 
     Ok(json)
@@ -62,7 +88,7 @@ pub async fn search_music_by_keyword(
 pub async fn get_music(mid: MusicID, ctx: &Context) -> anyhow::Result<GetPlayUrlResponse> {
     log::debug!("Fetch the music with MID “{mid}” from Kuwo Music…");
 
-    let client = build_client(ctx.proxy_uri.as_deref())?;
+    let client = build_client(ctx, ENGINE_ID)?;
     let url = Url::parse_with_params(
         "http://www.kuwo.cn/api/v1/www/music/playUrl",
         &[
@@ -72,33 +98,85 @@ pub async fn get_music(mid: MusicID, ctx: &Context) -> anyhow::Result<GetPlayUrl
         ],
     )?;
 
-    let response = client.get(url).headers(construct_header()?).send().await?;
+    let response = client
+        .get(url)
+        .headers(construct_header(ctx)?)
+        .send()
+        .await?;
     let json = response.json::<GetPlayUrlResponse>().await?;
 
     Ok(json)
 }
 
+/// The user agent of the Kuwo mobile client, which `mobi.s` expects.
+const MOBILE_USER_AGENT: &str = "okhttp/3.10.0";
+
+/// Fetch the music with MID `mid` in one of the `formats`,
+/// for example, `["flac", "ape"]`, from the Kuwo mobile API.
+///
+/// Unlike [`get_music`], it can fetch the lossless audio.
+pub async fn get_music_in_formats(
+    mid: MusicID,
+    formats: &[&str],
+    ctx: &Context,
+) -> anyhow::Result<KuwoConvertUrlResponse> {
+    log::debug!(
+        "Fetch the music with MID “{mid}” in {} from Kuwo Music…",
+        formats.join("|")
+    );
+
+    let client = build_client(ctx, ENGINE_ID)?;
+    let query = format!(
+        "corp=kuwo&p2p=1&type=convert_url2&sig=0&format={}&rid={mid}",
+        formats.join("|")
+    );
+    let url = Url::parse_with_params(
+        "http://mobi.kuwo.cn/mobi.s",
+        &[("f", "kuwo"), ("q", &crate::des::encrypt_query(&query))],
+    )?;
+
+    let response = client
+        .get(url)
+        .header(USER_AGENT, MOBILE_USER_AGENT)
+        .send()
+        .await?;
+    let text = response.text().await?;
+
+    text.parse()
+}
+
 #[cfg(test)]
 mod tests {
-    use concat_string::concat_string;
-    use http::header::{COOKIE, REFERER};
+    use http::header::REFERER;
+    use reqwest::Url;
+    use unm_request::cookie::cookie_jar;
+    use unm_types::Context;
 
     use super::construct_header;
+    use crate::ENGINE_ID;
 
     #[test]
     fn construct_header_test() {
-        let h = construct_header().expect("should be able to construct header");
+        let ctx = Context::default();
+        let h = construct_header(&ctx).expect("should be able to construct header");
         let getstr = |k: &str| {
             h.get(k)
-                .expect("should has cookie")
+                .expect("should has the header")
                 .to_str()
                 .expect("should able to convert to string")
         };
 
-        let token = getstr(COOKIE.as_str()).replace("kw_token=", "");
+        let token = getstr("csrf").to_string();
+        let cookie = cookie_jar(&ctx, ENGINE_ID).get(
+            &Url::parse("http://www.kuwo.cn/api/www/search/searchMusicBykeyWord").unwrap(),
+            "kw_token",
+        );
 
-        assert_eq!(getstr(COOKIE.as_str()), concat_string!("kw_token=", token));
+        assert_eq!(cookie.as_deref(), Some(token.as_str()));
         assert_eq!(getstr(REFERER.as_str()), "http://www.kuwo.cn/");
-        assert_eq!(getstr("csrf"), token);
+
+        // The token is reused until it expires.
+        let h = construct_header(&ctx).expect("should be able to construct header");
+        assert_eq!(h.get("csrf").unwrap(), token.as_str());
     }
 }
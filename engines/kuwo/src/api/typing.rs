@@ -17,6 +17,9 @@ pub struct KuwoResponseList<T> {
 pub struct KuwoResponse<T> {
     /// The HTTP code of this response. Should be `200`.
     pub code: i32,
+    /// The message of this response, which explains the error.
+    #[serde(default)]
+    pub msg: String,
     /// The data part of this response.
     pub data: T,
 }
@@ -62,8 +65,53 @@ pub struct KuwoPlayUrlData {
     pub url: String,
 }
 
+/// The response of the `convert_url2` query of `mobi.s`,
+/// which is in the `key=value` lines:
+///
+/// ```text
+/// format=flac
+/// bitrate=2000
+/// url=http://…/3866545620.flac
+/// sig=…
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct KuwoConvertUrlResponse {
+    /// The format of the audio, for example, `flac`.
+    pub format: String,
+    /// The bitrate of the audio (kbps).
+    pub bitrate: i64,
+    /// The URL of the audio.
+    pub url: String,
+}
+
+impl std::str::FromStr for KuwoConvertUrlResponse {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut response = Self::default();
+
+        for (key, value) in s.lines().filter_map(|line| line.trim().split_once('=')) {
+            match key {
+                "format" => response.format = value.to_string(),
+                "bitrate" => response.bitrate = value.parse().unwrap_or_default(),
+                "url" => response.url = value.to_string(),
+                _ => {}
+            }
+        }
+
+        if response.url.is_empty() {
+            anyhow::bail!("no URL in the response: {s}");
+        }
+
+        Ok(response)
+    }
+}
+
 pub type SearchResponse = KuwoResponse<KuwoResponseList<KuwoAudioInfo>>;
-pub type GetPlayUrlResponse = KuwoResponse<KuwoPlayUrlData>;
+/// The `data` is absent if Kuwo refuses to provide the audio,
+/// for example, the paid ones without the membership.
+pub type GetPlayUrlResponse = KuwoResponse<Option<KuwoPlayUrlData>>;
 
 impl From<KuwoAudioInfo> for Song {
     fn from(info: KuwoAudioInfo) -> Self {
@@ -93,7 +141,7 @@ impl From<KuwoAudioInfo> for Song {
 mod tests {
     use unm_types::Song;
 
-    use super::KuwoAudioInfo;
+    use super::{KuwoAudioInfo, KuwoConvertUrlResponse};
 
     #[test]
     fn kuwo_audio_info_into_song_test() {
@@ -122,4 +170,20 @@ mod tests {
             Some("Album name")
         );
     }
+
+    #[test]
+    fn kuwo_convert_url_response_parse_test() {
+        let response = "format=flac\r\nbitrate=2000\r\nurl=http://sy.sycdn.kuwo.cn/resource/n1/3866545620.flac\r\nsig=123\r\n"
+            .parse::<KuwoConvertUrlResponse>()
+            .unwrap();
+
+        assert_eq!(response.format, "flac");
+        assert_eq!(response.bitrate, 2000);
+        assert_eq!(
+            response.url,
+            "http://sy.sycdn.kuwo.cn/resource/n1/3866545620.flac"
+        );
+
+        assert!("res not found".parse::<KuwoConvertUrlResponse>().is_err());
+    }
 }
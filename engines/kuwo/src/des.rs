@@ -0,0 +1,299 @@
+//! The DES variant used by the Kuwo mobile API (`mobi.s`).
+//!
+//! It looks like DES, but the bits are numbered from the LSB of each
+//! little-endian block, and the expansion and the key schedule differ
+//! from the standard one. Therefore, a standard DES implementation
+//! can't be used here.
+//!
+//! The implementation is ported from
+//! [KuwoDES.java](https://github.com/Levi233/MusicPlayer/blob/master/app/src/main/java/com/chenhao/musicplayer/utils/crypt/KuwoDES.java).
+
+use base64::prelude::*;
+
+/// The key to encrypt the queries of `mobi.s`.
+const SECRET_KEY: &[u8; 8] = b"ylzsxkwm";
+
+/// The bit index of `-1` means this bit is always zero.
+type BitTable = [i8];
+
+/// The expansion. Note that the last bit is `30` instead of `0`.
+#[rustfmt::skip]
+const EXPANSION: [i8; 64] = [
+    31, 0, 1, 2, 3, 4, -1, -1, 3, 4, 5, 6, 7, 8, -1, -1,
+    7, 8, 9, 10, 11, 12, -1, -1, 11, 12, 13, 14, 15, 16, -1, -1,
+    15, 16, 17, 18, 19, 20, -1, -1, 19, 20, 21, 22, 23, 24, -1, -1,
+    23, 24, 25, 26, 27, 28, -1, -1, 27, 28, 29, 30, 31, 30, -1, -1,
+];
+
+#[rustfmt::skip]
+const INITIAL_PERMUTATION: [i8; 64] = [
+    57, 49, 41, 33, 25, 17, 9, 1, 59, 51, 43, 35, 27, 19, 11, 3,
+    61, 53, 45, 37, 29, 21, 13, 5, 63, 55, 47, 39, 31, 23, 15, 7,
+    56, 48, 40, 32, 24, 16, 8, 0, 58, 50, 42, 34, 26, 18, 10, 2,
+    60, 52, 44, 36, 28, 20, 12, 4, 62, 54, 46, 38, 30, 22, 14, 6,
+];
+
+#[rustfmt::skip]
+const INVERSE_PERMUTATION: [i8; 64] = [
+    39, 7, 47, 15, 55, 23, 63, 31, 38, 6, 46, 14, 54, 22, 62, 30,
+    37, 5, 45, 13, 53, 21, 61, 29, 36, 4, 44, 12, 52, 20, 60, 28,
+    35, 3, 43, 11, 51, 19, 59, 27, 34, 2, 42, 10, 50, 18, 58, 26,
+    33, 1, 41, 9, 49, 17, 57, 25, 32, 0, 40, 8, 48, 16, 56, 24,
+];
+
+#[rustfmt::skip]
+const PERMUTATION: [i8; 32] = [
+    15, 6, 19, 20, 28, 11, 27, 16, 0, 14, 22, 25, 4, 17, 30, 9,
+    1, 7, 23, 13, 31, 26, 2, 8, 18, 12, 29, 5, 21, 10, 3, 24,
+];
+
+#[rustfmt::skip]
+const PERMUTED_CHOICE_1: [i8; 56] = [
+    56, 48, 40, 32, 24, 16, 8, 0, 57, 49, 41, 33, 25, 17,
+    9, 1, 58, 50, 42, 34, 26, 18, 10, 2, 59, 51, 43, 35,
+    62, 54, 46, 38, 30, 22, 14, 6, 61, 53, 45, 37, 29, 21,
+    13, 5, 60, 52, 44, 36, 28, 20, 12, 4, 27, 19, 11, 3,
+];
+
+#[rustfmt::skip]
+const PERMUTED_CHOICE_2: [i8; 64] = [
+    13, 16, 10, 23, 0, 4, -1, -1, 2, 27, 14, 5, 20, 9, -1, -1,
+    22, 18, 11, 3, 25, 7, -1, -1, 15, 6, 26, 19, 12, 1, -1, -1,
+    40, 51, 30, 36, 46, 54, -1, -1, 29, 39, 50, 44, 32, 47, -1, -1,
+    43, 48, 38, 55, 33, 52, -1, -1, 45, 41, 49, 35, 28, 31, -1, -1,
+];
+
+/// How many bits to rotate the key in each round.
+const ROTATIONS: [u32; 16] = [1, 1, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2, 2, 2, 2, 1];
+
+/// The bits moved to the top when rotating 1 or 2 bits.
+const ROTATION_MASKS: [u64; 3] = [0, 0x100001, 0x300003];
+
+/// The standard DES S-boxes, indexed by `row * 16 + column`.
+#[rustfmt::skip]
+const S_BOXES: [[u8; 64]; 8] = [
+    [
+        14, 4, 13, 1, 2, 15, 11, 8, 3, 10, 6, 12, 5, 9, 0, 7,
+        0, 15, 7, 4, 14, 2, 13, 1, 10, 6, 12, 11, 9, 5, 3, 8,
+        4, 1, 14, 8, 13, 6, 2, 11, 15, 12, 9, 7, 3, 10, 5, 0,
+        15, 12, 8, 2, 4, 9, 1, 7, 5, 11, 3, 14, 10, 0, 6, 13,
+    ],
+    [
+        15, 1, 8, 14, 6, 11, 3, 4, 9, 7, 2, 13, 12, 0, 5, 10,
+        3, 13, 4, 7, 15, 2, 8, 14, 12, 0, 1, 10, 6, 9, 11, 5,
+        0, 14, 7, 11, 10, 4, 13, 1, 5, 8, 12, 6, 9, 3, 2, 15,
+        13, 8, 10, 1, 3, 15, 4, 2, 11, 6, 7, 12, 0, 5, 14, 9,
+    ],
+    [
+        10, 0, 9, 14, 6, 3, 15, 5, 1, 13, 12, 7, 11, 4, 2, 8,
+        13, 7, 0, 9, 3, 4, 6, 10, 2, 8, 5, 14, 12, 11, 15, 1,
+        13, 6, 4, 9, 8, 15, 3, 0, 11, 1, 2, 12, 5, 10, 14, 7,
+        1, 10, 13, 0, 6, 9, 8, 7, 4, 15, 14, 3, 11, 5, 2, 12,
+    ],
+    [
+        7, 13, 14, 3, 0, 6, 9, 10, 1, 2, 8, 5, 11, 12, 4, 15,
+        13, 8, 11, 5, 6, 15, 0, 3, 4, 7, 2, 12, 1, 10, 14, 9,
+        10, 6, 9, 0, 12, 11, 7, 13, 15, 1, 3, 14, 5, 2, 8, 4,
+        3, 15, 0, 6, 10, 1, 13, 8, 9, 4, 5, 11, 12, 7, 2, 14,
+    ],
+    [
+        2, 12, 4, 1, 7, 10, 11, 6, 8, 5, 3, 15, 13, 0, 14, 9,
+        14, 11, 2, 12, 4, 7, 13, 1, 5, 0, 15, 10, 3, 9, 8, 6,
+        4, 2, 1, 11, 10, 13, 7, 8, 15, 9, 12, 5, 6, 3, 0, 14,
+        11, 8, 12, 7, 1, 14, 2, 13, 6, 15, 0, 9, 10, 4, 5, 3,
+    ],
+    [
+        12, 1, 10, 15, 9, 2, 6, 8, 0, 13, 3, 4, 14, 7, 5, 11,
+        10, 15, 4, 2, 7, 12, 9, 5, 6, 1, 13, 14, 0, 11, 3, 8,
+        9, 14, 15, 5, 2, 8, 12, 3, 7, 0, 4, 10, 1, 13, 11, 6,
+        4, 3, 2, 12, 9, 5, 15, 10, 11, 14, 1, 7, 6, 0, 8, 13,
+    ],
+    [
+        4, 11, 2, 14, 15, 0, 8, 13, 3, 12, 9, 7, 5, 10, 6, 1,
+        13, 0, 11, 7, 4, 9, 1, 10, 14, 3, 5, 12, 2, 15, 8, 6,
+        1, 4, 11, 13, 12, 3, 7, 14, 10, 15, 6, 8, 0, 5, 9, 2,
+        6, 11, 13, 8, 1, 4, 10, 7, 9, 5, 0, 15, 14, 2, 3, 12,
+    ],
+    [
+        13, 2, 8, 4, 6, 15, 11, 1, 10, 9, 3, 14, 5, 0, 12, 7,
+        1, 15, 13, 8, 10, 3, 7, 4, 12, 5, 6, 11, 0, 14, 9, 2,
+        7, 11, 4, 1, 9, 12, 14, 2, 0, 6, 10, 13, 15, 3, 5, 8,
+        2, 1, 14, 7, 4, 10, 8, 13, 15, 12, 9, 0, 3, 5, 6, 11,
+    ],
+];
+
+/// The S-boxes indexed by the 6 input bits directly,
+/// which are numbered from the LSB.
+const NS_BOXES: [[u8; 64]; 8] = {
+    let mut boxes = [[0; 64]; 8];
+
+    let mut s = 0;
+    while s < 8 {
+        let mut i = 0;
+        while i < 64 {
+            let row = ((i & 1) << 1) | ((i >> 5) & 1);
+            let column =
+                ((i >> 1) & 1) << 3 | ((i >> 2) & 1) << 2 | ((i >> 3) & 1) << 1 | (i >> 4) & 1;
+            boxes[s][i] = S_BOXES[s][row * 16 + column];
+            i += 1;
+        }
+        s += 1;
+    }
+
+    boxes
+};
+
+/// The mode of [`crypt`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Encrypt,
+    Decrypt,
+}
+
+/// Set the bit `i` of the output if the bit `table[i]` of `input` is set.
+fn bit_transform(table: &BitTable, input: u64) -> u64 {
+    table
+        .iter()
+        .enumerate()
+        .filter(|(_, bit)| **bit >= 0 && input & (1 << **bit) != 0)
+        .fold(0, |output, (i, _)| output | (1 << i))
+}
+
+fn sub_keys(key: u64, mode: Mode) -> [u64; 16] {
+    let mut key = bit_transform(&PERMUTED_CHOICE_1, key);
+    let mut sub_keys = [0; 16];
+
+    for (sub_key, rotation) in sub_keys.iter_mut().zip(ROTATIONS) {
+        let mask = ROTATION_MASKS[rotation as usize];
+        key = ((key & mask) << (28 - rotation)) | ((key & !mask) >> rotation);
+        *sub_key = bit_transform(&PERMUTED_CHOICE_2, key);
+    }
+
+    if mode == Mode::Decrypt {
+        sub_keys.reverse();
+    }
+
+    sub_keys
+}
+
+fn des_block(sub_keys: &[u64; 16], block: u64) -> u64 {
+    let permuted = bit_transform(&INITIAL_PERMUTATION, block);
+    let (mut left, mut right) = (permuted & 0xffff_ffff, permuted >> 32);
+
+    for sub_key in sub_keys {
+        let expanded = bit_transform(&EXPANSION, right) ^ sub_key;
+        let substituted = NS_BOXES
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(0, |output, (i, s_box)| {
+                let index = (expanded >> (i * 8)) & 0x3f;
+                (output << 4) | u64::from(s_box[index as usize])
+            });
+
+        let next = left ^ bit_transform(&PERMUTATION, substituted);
+        left = right;
+        right = next;
+    }
+
+    bit_transform(&INVERSE_PERMUTATION, (left << 32) | right)
+}
+
+/// Load 8 bytes (or less, padded with zero) as a little-endian block.
+fn load_block(bytes: &[u8]) -> u64 {
+    let mut block = [0; 8];
+    block[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(block)
+}
+
+fn crypt(data: &[u8], key: &[u8; 8], mode: Mode) -> Vec<u8> {
+    let sub_keys = sub_keys(u64::from_le_bytes(*key), mode);
+    let chunks = data.chunks_exact(8);
+    let remainder = chunks.remainder();
+
+    let mut blocks = chunks.map(load_block).collect::<Vec<_>>();
+    // The data is always padded when encrypting,
+    // even if its length is a multiple of 8.
+    if !remainder.is_empty() || mode == Mode::Encrypt {
+        blocks.push(load_block(remainder));
+    }
+
+    blocks
+        .into_iter()
+        .flat_map(|block| des_block(&sub_keys, block).to_le_bytes())
+        .collect()
+}
+
+/// Encrypt the `data` with the `key`, padded with zero.
+pub fn encrypt_with_key(data: &[u8], key: &[u8; 8]) -> Vec<u8> {
+    crypt(data, key, Mode::Encrypt)
+}
+
+/// Decrypt the `data` with the `key`.
+///
+/// The zero padding is kept, which the caller should trim.
+pub fn decrypt_with_key(data: &[u8], key: &[u8; 8]) -> Vec<u8> {
+    crypt(data, key, Mode::Decrypt)
+}
+
+pub fn encrypt(data: &[u8]) -> Vec<u8> {
+    encrypt_with_key(data, SECRET_KEY)
+}
+
+pub fn decrypt(data: &[u8]) -> Vec<u8> {
+    decrypt_with_key(data, SECRET_KEY)
+}
+
+/// Encrypt the `query` of `mobi.s` to the value of the `q` parameter.
+pub fn encrypt_query(query: &str) -> String {
+    BASE64_STANDARD.encode(encrypt(query.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::prelude::*;
+    use proptest::prelude::*;
+
+    use super::{decrypt, encrypt, encrypt_query, NS_BOXES};
+
+    #[test]
+    fn test_ns_boxes() {
+        // The first S-box in the order of the original implementation.
+        assert_eq!(
+            NS_BOXES[0][..16],
+            [14, 4, 3, 15, 2, 13, 5, 3, 13, 14, 6, 9, 11, 2, 0, 5]
+        );
+    }
+
+    #[test]
+    fn test_encrypt_query() {
+        let query = "corp=kuwo&p2p=1&type=convert_url2&sig=0&format=flac|mp3&rid=6544178";
+        let encrypted = encrypt_query(query);
+
+        assert_eq!(
+            encrypted,
+            "NI8S5evAnmGldi4g47EsqtfDbGsJckckbTQQd2LAgmDPITUWSd51OkjHRFj6xHPE5fnDnpvElvewRldMDkR81vz/+/ZUY/SF"
+        );
+
+        let decrypted = decrypt(&BASE64_STANDARD.decode(&encrypted).unwrap());
+        assert_eq!(decrypted.len(), query.len() / 8 * 8 + 8);
+        assert_eq!(&decrypted[..query.len()], query.as_bytes());
+    }
+
+    #[test]
+    fn test_encrypt_padding() {
+        assert_eq!(encrypt(b"").len(), 8);
+        assert_eq!(encrypt(b"ylzsxkw").len(), 8);
+        assert_eq!(encrypt(b"ylzsxkwm").len(), 16);
+    }
+
+    proptest! {
+        #[test]
+        fn test_roundtrip(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            let decrypted = decrypt(&encrypt(&data));
+
+            prop_assert_eq!(&decrypted[..data.len()], &data[..]);
+            prop_assert!(decrypted[data.len()..].iter().all(|b| *b == 0));
+        }
+    }
+}
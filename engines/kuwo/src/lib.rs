@@ -1,18 +1,51 @@
 //! UNM Engine: Kuwo
 //!
-//! Note that we only implemented the API that can
-//! only fetch the 320kbps audio file.
+//! The 320kbps audio is fetched with the web API. If `enable_flac`
+//! is set in the context, the lossless audio is fetched with the
+//! mobile API, whose queries are encrypted with [`des`].
 //!
-//! FIXME: KuwoDES implementation for Lossless file.
+//! The paid tracks are skipped unless you fill the cookie of a Kuwo
+//! account with the membership to the `kuwo:cookie` config. The cookie
+//! seeds the cookie jar of Kuwo, so you can also put a `kw_token` in it.
+//!
+//! ```
+//! use unm_types::{ContextBuilder, config::ConfigManagerBuilder};
+//!
+//! let config = ConfigManagerBuilder::new()
+//!     .set("kuwo:cookie", r#"userid=<your_userid>; websid=<your_websid>;"#)
+//!     .build();
+//!
+//! let context = ContextBuilder::default()
+//!     .config(config)
+//!     .build();
+//! ```
+
+use std::borrow::Cow;
 
-use api::typing::MusicID;
+use api::typing::{KuwoAudioInfo, MusicID};
 use async_trait::async_trait;
 use unm_engine::interface::Engine;
+use unm_request::cookie::cookie_jar;
 use unm_types::{Context, RetrievedSongInfo, SerializedIdentifier, Song, SongSearchInformation};
+use url::Url;
 
 pub mod api;
+pub mod des;
 
 pub const ENGINE_ID: &str = "kuwo";
+
+/// The lossless formats to fetch if `enable_flac` is set, in order.
+const LOSSLESS_FORMATS: &[&str] = &["flac", "ape"];
+
+/// The domains that the cookie of Kuwo is sent to.
+const COOKIE_DOMAINS: &[&str] = &["kuwo.cn"];
+
+/// The URL to look up the session cookies in the jar.
+const SESSION_URL: &str = "https://www.kuwo.cn/";
+
+/// The cookies of a logged-in session of Kuwo.
+const SESSION_COOKIES: &[&str] = &["userid", "websid"];
+
 pub struct KuwoEngine;
 
 #[async_trait]
@@ -24,19 +57,11 @@ impl Engine for KuwoEngine {
     ) -> anyhow::Result<Option<SongSearchInformation>> {
         log::info!("Searching “{info}” in Kuwo Music…");
 
+        seed_cookie(ctx);
+        let has_credentials = get_cookie(ctx).is_some();
         let response = api::search_music_by_keyword(&info.keyword(), 1, 30, ctx).await?;
 
-        let mut song_iterator = response
-            .data
-            .list
-            .into_iter()
-            .filter(|d| d.pay == "0")
-            .map(Song::from);
-
-        log::debug!("Matching the song…");
-        let unm_selector::SimilarSongSelector { selector, .. } =
-            unm_selector::SimilarSongSelector::new(info);
-        let matched_song = song_iterator.find(|s| selector(&s));
+        let matched_song = find_match(info, response.data.list, has_credentials)?;
 
         Ok(matched_song.map(|song| {
             SongSearchInformation::builder()
@@ -55,12 +80,285 @@ impl Engine for KuwoEngine {
         log::info!("Retrieving MID “{identifier}” from Kuwo Music…");
 
         let mid = MusicID::from_str_radix(identifier, 10)?;
+        seed_cookie(ctx);
+
+        if ctx.enable_flac {
+            match api::get_music_in_formats(mid, LOSSLESS_FORMATS, ctx).await {
+                Ok(response) if LOSSLESS_FORMATS.contains(&response.format.as_str()) => {
+                    return Ok(RetrievedSongInfo::builder()
+                        .source(ENGINE_ID.into())
+                        .url(response.url)
+                        .bitrate(
+                            u32::try_from(response.bitrate * 1000)
+                                .ok()
+                                .filter(|v| *v > 0),
+                        )
+                        .format(Some(response.format))
+                        .build());
+                }
+                Ok(response) => log::debug!(
+                    "No lossless audio for MID “{mid}” but {}; fallback to 320kbps.",
+                    response.format
+                ),
+                Err(e) => log::warn!(
+                    "Failed to fetch the lossless audio for MID “{mid}”: {e}; fallback to 320kbps."
+                ),
+            }
+        }
+
         let response = api::get_music(mid, ctx).await?;
-        let url = response.data.url;
+        match response.data {
+            Some(data) if response.code == 200 => Ok(RetrievedSongInfo::builder()
+                .source(ENGINE_ID.into())
+                .url(data.url)
+                .build()),
+            _ => {
+                // The audio is likely unavailable because the session has expired.
+                if get_cookie(ctx).is_some() {
+                    cookie_jar(ctx, ENGINE_ID).check_session()?;
+                }
+                Err(anyhow::anyhow!(
+                    "Kuwo Music refused to provide the audio of MID “{mid}”: {} ({})",
+                    response.msg,
+                    response.code
+                ))
+            }
+        }
+    }
+}
+
+/// Find the song similar to `info` in the search results `list`.
+///
+/// The paid songs are skipped without the credentials. If only the
+/// paid songs are similar, it returns an error to explain why.
+pub fn find_match(
+    info: &Song,
+    list: Vec<KuwoAudioInfo>,
+    has_credentials: bool,
+) -> anyhow::Result<Option<Song>> {
+    log::debug!("Matching the song…");
+
+    let unm_selector::SimilarSongSelector { selector, .. } =
+        unm_selector::SimilarSongSelector::new(info);
+    let mut skipped_paid_song = None;
+    let matched_song = list.into_iter().find_map(|d| {
+        let paid = d.pay != "0";
+        let song = Song::from(d);
+
+        if !selector(&&song) {
+            None
+        } else if paid && !has_credentials {
+            log::debug!("Skipping the paid song “{song}” without the credentials…");
+            skipped_paid_song.get_or_insert(song);
+            None
+        } else {
+            Some(song)
+        }
+    });
+
+    match (matched_song, skipped_paid_song) {
+        (Some(song), _) => Ok(Some(song)),
+        (None, Some(song)) => Err(anyhow::anyhow!(
+            "The matched song “{song}” (MID “{}”) is paid in Kuwo Music. \
+             Fill the cookie of an account with the membership to `kuwo:cookie` to retrieve it.",
+            song.id
+        )),
+        (None, None) => Ok(None),
+    }
+}
+
+fn get_cookie(context: &Context) -> Option<&str> {
+    if let Some(ref config) = context.config {
+        config
+            .get_deref(Cow::Borrowed("kuwo:cookie"))
+            .filter(|cookie| !cookie.trim().is_empty())
+    } else {
+        None
+    }
+}
+
+/// Seed the cookie jar of Kuwo with `kuwo:cookie`,
+/// if there is no session of Kuwo in the jar.
+///
+/// The session refreshed by Kuwo is kept, even if it is different from
+/// `kuwo:cookie`.
+fn seed_cookie(context: &Context) {
+    let Some(cookie) = get_cookie(context) else {
+        return;
+    };
+
+    let jar = cookie_jar(context, ENGINE_ID);
+    let url = Url::parse(SESSION_URL).expect("SESSION_URL should be valid");
+    if SESSION_COOKIES
+        .iter()
+        .any(|name| jar.get(&url, name).is_some())
+    {
+        log::trace!("The session of Kuwo is in the jar; not seeding `kuwo:cookie`.");
+        return;
+    }
+
+    jar.seed(COOKIE_DOMAINS, cookie);
+}
+
+#[cfg(test)]
+mod tests {
+    use unm_engine::interface::Engine;
+    use unm_request::cookie::{cookie_jar, StoredCookie};
+    use unm_request::transport::cassette::RecordedRequest;
+    use unm_test_utils::{sample_song, search_and_retrieve, with_cassette, with_cassette_requests};
+    use unm_types::config::ConfigManagerBuilder;
+    use unm_types::{Context, ContextBuilder};
+
+    use super::{KuwoEngine, ENGINE_ID};
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) =
+                search_and_retrieve(&KuwoEngine, ENGINE_ID, &sample_song(), &Context::default())
+                    .await;
+
+            // The paid one and the live version should be skipped.
+            assert_eq!(info.identifier, "6544178");
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.name, "青花瓷");
+            assert_eq!(song.duration, Some(238000));
+            assert_eq!(song.artists[0].id, "336");
+            assert_eq!(song.artists[0].name, "周杰伦");
+            let album = song.album.expect("should have the album");
+            assert_eq!(album.id, "584637");
+            assert_eq!(album.name, "周杰伦的青花瓷");
+
+            assert_eq!(retrieved.url, "https://other-web-ra01-sycdn.kuwo.cn/2a3b4c5d6e7f8091a2b3c4d5e6f70819/64c8f1a2/resource/n1/41/17/3866545620.mp3");
+        })
+        .await;
+    }
+
+    /// `retrieve_lossless.json` is synthetic: the response of `mobi.s`
+    /// is made up after its `key=value` format, and its URL and `sig` are
+    /// fake. Only the encrypted query is checked against [`super::des`].
+    #[tokio::test]
+    async fn test_retrieve_lossless() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/retrieve_lossless.json"
+        );
+
+        with_cassette(fixture, async {
+            let ctx = ContextBuilder::default().enable_flac(true).build().unwrap();
+
+            let retrieved = KuwoEngine.retrieve(&"6544178".into(), &ctx).await.unwrap();
+            assert_eq!(retrieved.source, ENGINE_ID);
+            assert!(retrieved.url.ends_with("/3866545620.flac"));
+            assert_eq!(retrieved.format.as_deref(), Some("flac"));
+            assert_eq!(retrieved.bitrate, Some(2000000));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_paid_song_without_credentials() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search_paid.json");
+
+        with_cassette(fixture, async {
+            let Err(error) = KuwoEngine.search(&sample_song(), &Context::default()).await else {
+                panic!("the paid song should be skipped");
+            };
+
+            assert!(error.to_string().contains("324244"));
+            assert!(error.to_string().contains("kuwo:cookie"));
+        })
+        .await;
+    }
+
+    /// Get the `Cookie` header of each request,
+    /// which also has the `kw_token` generated by us.
+    fn cookies(requests: &[RecordedRequest]) -> Vec<&str> {
+        requests
+            .iter()
+            .map(|request| {
+                request
+                    .headers
+                    .get("cookie")
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// The context with `kuwo:cookie`, whose cookies are persisted to `cookie_dir`.
+    fn context_with_cookie(cookie_dir: &str) -> Context {
+        let config = ConfigManagerBuilder::new()
+            .set("kuwo:cookie", "userid=123456; websid=abcdef;")
+            .build();
+
+        ContextBuilder::default()
+            .config(config)
+            .cookie_dir(Some(
+                std::env::temp_dir().join(format!("unm-kuwo-{cookie_dir}-{}", std::process::id())),
+            ))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_paid_song_with_credentials() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search_paid.json");
+        let ctx = context_with_cookie("credentials");
+
+        let ((), requests) = with_cassette_requests(fixture, async {
+            let info = KuwoEngine
+                .search(&sample_song(), &ctx)
+                .await
+                .unwrap()
+                .expect("should have a search result");
+            assert_eq!(info.identifier, "324244");
+
+            let retrieved = KuwoEngine.retrieve(&info.identifier, &ctx).await.unwrap();
+            assert!(retrieved.url.ends_with("/3866545621.mp3"));
+        })
+        .await;
+
+        // Both the search and the retrieval are sent with the cookie.
+        let cookies = cookies(&requests);
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies
+            .iter()
+            .all(|cookie| cookie.starts_with("userid=123456; websid=abcdef;")));
+        std::fs::remove_dir_all(ctx.cookie_dir.unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keep_refreshed_session() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search_paid.json");
+        let ctx = context_with_cookie("refreshed");
+
+        // Kuwo has refreshed the session.
+        let jar = cookie_jar(&ctx, ENGINE_ID);
+        jar.insert(StoredCookie::new("userid", "654321", "kuwo.cn"));
+        jar.insert(StoredCookie::new("websid", "fedcba", "kuwo.cn"));
+
+        let ((), requests) = with_cassette_requests(fixture, async {
+            let info = KuwoEngine
+                .search(&sample_song(), &ctx)
+                .await
+                .unwrap()
+                .expect("should have a search result");
+            KuwoEngine.retrieve(&info.identifier, &ctx).await.unwrap();
+        })
+        .await;
 
-        Ok(RetrievedSongInfo::builder()
-            .source(ENGINE_ID.into())
-            .url(url)
-            .build())
+        // The refreshed session is not overwritten by `kuwo:cookie`.
+        let cookies = cookies(&requests);
+        assert_eq!(cookies.len(), 2);
+        assert!(cookies
+            .iter()
+            .all(|cookie| cookie.starts_with("userid=654321; websid=fedcba;")));
+        std::fs::remove_dir_all(ctx.cookie_dir.unwrap()).unwrap();
     }
 }
@@ -2,7 +2,7 @@
 //!
 //! It can fetch audio from Migu Music.
 
-mod types;
+pub mod types;
 
 use std::collections::HashMap;
 
@@ -31,7 +31,7 @@ impl Engine for MiguEngine {
         log::info!("Searching “{info}” with Migu engine…");
 
         let api = construct_search_api(info.keyword().as_str())?;
-        let client = build_client(ctx.proxy_uri.as_deref())?;
+        let client = build_client(ctx, ENGINE_ID)?;
 
         let response = client.get(api).send().await?;
         let result = response.json::<Json>().await?;
@@ -120,7 +120,8 @@ fn construct_search_api(keyword: &str) -> anyhow::Result<Url> {
     )?)
 }
 
-fn find_match(info: &Song, data: Vec<MiguResponse>) -> Option<Song> {
+/// Find the song similar to `info` in the search results `data`.
+pub fn find_match(info: &Song, data: Vec<MiguResponse>) -> Option<Song> {
     log::debug!("Finding the matched song from data…");
 
     let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(info);
@@ -131,8 +132,10 @@ fn find_match(info: &Song, data: Vec<MiguResponse>) -> Option<Song> {
 #[cfg(test)]
 mod tests {
     use reqwest::Url;
+    use unm_test_utils::{sample_song, search_and_retrieve, with_cassette};
+    use unm_types::Context;
 
-    use crate::construct_search_api;
+    use crate::{construct_search_api, MiguEngine, ENGINE_ID};
 
     #[test]
     fn construct_search_api_test() {
@@ -143,4 +146,31 @@ mod tests {
         assert_eq!(construct_search_api("Twice    ").unwrap(), url("https://pd.musicapp.migu.cn/MIGUM2.0/v1.0/content/search_all.do?&ua=Android_migu&version=5.0.1&pageNo=1&pageSize=10&searchSwitch={\"song\":1,\"album\":0,\"singer\":0,\"tagSong\":0,\"mvSong\":0,\"songlist\":0,\"bestShow\":1}&text=Twice"));
         assert_eq!(construct_search_api("     TT").unwrap(), url("https://pd.musicapp.migu.cn/MIGUM2.0/v1.0/content/search_all.do?&ua=Android_migu&version=5.0.1&pageNo=1&pageSize=10&searchSwitch={\"song\":1,\"album\":0,\"singer\":0,\"tagSong\":0,\"mvSong\":0,\"songlist\":0,\"bestShow\":1}&text=TT"));
     }
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) =
+                search_and_retrieve(&MiguEngine, ENGINE_ID, &sample_song(), &Context::default())
+                    .await;
+
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.id, "1004516016");
+            assert_eq!(song.name, "青花瓷");
+            assert_eq!(song.artists[0].id, "112");
+            assert_eq!(song.artists[0].name, "周杰伦");
+            let album = song.album.expect("should have the album");
+            assert_eq!(album.id, "1004516012");
+            assert_eq!(album.name, "我很忙");
+
+            // The FTP URL should be corrected to the HTTP one.
+            assert_eq!(retrieved.url, "http://freetyst.nf.migu.cn/public/product5th/product30/2019/02/2612/2009年06月26日博尔普斯/标清高清/MP3_320_16_Stero/60084600640.mp3");
+        })
+        .await;
+    }
 }
@@ -88,7 +88,7 @@ async fn fetch_song_info(id: &str, ctx: &Context) -> anyhow::Result<PyNCMRespons
         &[("song_ids", id), ("bitrate", &bitrate.to_string())],
     )?;
 
-    let client = build_client(ctx.proxy_uri.as_deref())?;
+    let client = build_client(ctx, ENGINE_ID)?;
     let response = client.get(url).send().await?;
     Ok(response.json::<PyNCMResponse>().await?)
 }
@@ -109,14 +109,23 @@ fn find_match(data: &[PyNCMResponseEntry], song_id: &str) -> anyhow::Result<Opti
 
 #[cfg(test)]
 mod tests {
-    use unm_types::ContextBuilder;
+    use unm_test_utils::{search_and_retrieve, with_cassette};
+    use unm_types::{Context, ContextBuilder, Song};
+
+    use super::{PyNCMEngine, ENGINE_ID};
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/fetch_song_info.json");
 
     #[tokio::test]
     async fn test_fetch_song_info() {
         use super::fetch_song_info;
 
         let song_id = "1939601619"; // Madeon – Love You Back
-        let result = fetch_song_info(song_id, &ContextBuilder::default().build().unwrap()).await;
+        let result = with_cassette(
+            FIXTURE,
+            fetch_song_info(song_id, &ContextBuilder::default().build().unwrap()),
+        )
+        .await;
 
         if let Ok(response) = result {
             assert_eq!(response.code, 200);
@@ -127,4 +136,22 @@ mod tests {
             panic!("failed to fetch song info");
         }
     }
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        with_cassette(FIXTURE, async {
+            let song = Song::builder()
+                .id("1939601619".to_string())
+                .name("Love You Back".to_string())
+                .build();
+
+            // PyNCM returns the URL as the identifier, without the song.
+            let (info, retrieved) =
+                search_and_retrieve(&PyNCMEngine, ENGINE_ID, &song, &Context::default()).await;
+            assert!(info.song.is_none());
+            assert!(retrieved.url.starts_with("http://m801.music.126.net/"));
+            assert_eq!(retrieved.url, info.identifier);
+        })
+        .await;
+    }
 }
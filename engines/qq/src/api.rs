@@ -6,7 +6,7 @@ pub mod typing;
 
 use std::borrow::Cow;
 
-use http::header::{COOKIE, ORIGIN, REFERER};
+use http::header::{ORIGIN, REFERER};
 use http::{HeaderMap, HeaderValue};
 use log::{debug, trace};
 use once_cell::sync::Lazy;
@@ -15,36 +15,34 @@ use reqwest::Url;
 use serde::Deserialize;
 use serde_json::json;
 use thiserror::Error;
+use unm_request::cookie::cookie_jar;
 use unm_request::json::{Json, UnableToExtractJson};
 use unm_request::{build_client, RequestModuleError};
 use unm_types::Context;
 
 use self::format::QQFormat;
 use self::identifier::QQResourceIdentifier;
-use self::typing::QQSongData;
+use self::typing::{QQSearchMeta, QQSongData};
 use crate::api::typing::QQSingleResponseRoot;
+use crate::ENGINE_ID;
 
-/// Search for the specified keyword.
-pub async fn search_by_keyword(keyword: &str, ctx: &Context) -> QQApiModuleResult<QQSongData> {
-    debug!("Searching “{keyword}” in QQ Music…");
+/// The entries in a page of the search results.
+pub const PAGE_SIZE: usize = 10;
 
-    let url = construct_search_url(keyword)?;
-    let cookie = extract_cookie(ctx);
-
-    let client = build_client(ctx.proxy_uri.as_deref())?;
-    let mut request = client
-        .get(url)
-        .header(ORIGIN, HeaderValue::from_static("http://y.qq.com"))
-        .header(REFERER, HeaderValue::from_static("http://y.qq.com"));
+/// Search for the specified keyword, and get the page `page` (from 1)
+/// of the results, with the metadata of this page.
+pub async fn search_by_keyword(
+    keyword: &str,
+    page: usize,
+    ctx: &Context,
+) -> QQApiModuleResult<(QQSongData, QQSearchMeta)> {
+    debug!("Searching “{keyword}” in QQ Music (page {page})…");
 
-    if let Some(cookie) = cookie {
-        request = request.header(COOKIE, HeaderValue::from_str(cookie)?);
-    }
+    let url = construct_search_url(keyword, page)?;
+    seed_cookie(ctx);
 
-    let response = request
-        .send()
-        .await
-        .map_err(QQApiModuleError::RequestFailed)?;
+    let client = build_client(ctx, ENGINE_ID)?;
+    let response = client.get(url).headers(construct_header()).send().await?;
     let json = response
         .json::<Json>()
         .await
@@ -57,41 +55,56 @@ pub async fn search_by_keyword(keyword: &str, ctx: &Context) -> QQApiModuleResul
         },
     )?)
     .map_err(QQApiModuleError::JsonDeserializeFailed)?;
+    let meta = json
+        .pointer("/search/data/meta")
+        .and_then(|meta| QQSearchMeta::deserialize(meta).ok())
+        .unwrap_or_default();
 
-    Ok(data)
+    Ok((data, meta))
 }
 
 /// Retrieve the song URL of specified single.
+///
+/// The formats are requested at once, from the best one that matches
+/// the preference ([`QQFormat::from_context`]) to the worst one,
+/// skipping the ones which don't exist. The `midurlinfo` in the
+/// response is sorted in this order, and only the ones we can
+/// fetch are kept.
 pub async fn retrieve_single(
     identifier: &str,
     ctx: &Context,
 ) -> QQApiModuleResult<QQSingleResponseRoot> {
     debug!("Retrieving the song URL of “{identifier}” from QQ Music…");
     let identifier = QQResourceIdentifier::deserialize(identifier)?;
-    let mode = QQFormat::from_context(ctx);
-    let cookie = extract_cookie(ctx);
+    let formats = identifier.formats_to_try(QQFormat::from_context(ctx));
+    if formats.is_empty() {
+        return Err(QQApiModuleError::NoAvailableFormat);
+    }
+    let filenames = formats
+        .iter()
+        .map(|format| format.to_filename(identifier.file))
+        .collect::<Vec<_>>();
+    seed_cookie(ctx);
 
-    let client = build_client(ctx.proxy_uri.as_deref())?;
-    let url = construct_single_url(&identifier, mode, ctx)?;
+    let client = build_client(ctx, ENGINE_ID)?;
+    let url = construct_single_url(&identifier, &filenames, ctx)?;
 
-    let response = client
-        .get(url)
-        .headers(construct_header(cookie)?)
-        .send()
-        .await
-        .map_err(QQApiModuleError::RequestFailed)?;
+    let response = client.get(url).headers(construct_header()).send().await?;
     let json = response
         .json::<Json>()
         .await
         .map_err(QQApiModuleError::ResponseJsonDeserializeFailed)?;
 
-    let data = json
+    let mut data = json
         .pointer("/req_0")
         .and_then(|data| QQSingleResponseRoot::deserialize(data).ok())
         .ok_or(UnableToExtractJson {
             json_pointer: "/req_0",
             expected_type: "QQSingleResponseRoot",
         })?;
+    if let Some(data) = &mut data.data {
+        data.retain_in_order(&filenames);
+    }
 
     Ok(data)
 }
@@ -107,24 +120,28 @@ fn extract_cookie(ctx: &Context) -> Option<&str> {
     }
 }
 
-fn construct_header(cookie: Option<&str>) -> QQApiModuleResult<HeaderMap> {
-    trace!("Constructing header with cookie…");
+/// Seed the cookie jar of QQ Music with `qq:cookie`.
+///
+/// The cookie is sent by the client with the jar, which keeps
+/// the cookies refreshed by QQ Music.
+fn seed_cookie(ctx: &Context) {
+    if let Some(cookie) = extract_cookie(ctx) {
+        cookie_jar(ctx, ENGINE_ID).seed(&["qq.com"], cookie);
+    }
+}
 
-    let mut hm = HeaderMap::with_capacity(3);
+fn construct_header() -> HeaderMap {
+    trace!("Constructing header…");
+
+    let mut hm = HeaderMap::with_capacity(2);
 
     hm.insert(ORIGIN, HeaderValue::from_static("http://y.qq.com"));
     hm.insert(REFERER, HeaderValue::from_static("http://y.qq.com"));
 
-    if let Some(cookie) = cookie {
-        if !cookie.is_empty() {
-            hm.insert(COOKIE, HeaderValue::from_str(cookie)?);
-        }
-    }
-
-    Ok(hm)
+    hm
 }
 
-fn construct_search_url(keyword: &str) -> QQApiModuleResult<Url> {
+fn construct_search_url(keyword: &str, page: usize) -> QQApiModuleResult<Url> {
     trace!("Constructing search URL with parameters…");
 
     let data = json!({
@@ -132,8 +149,8 @@ fn construct_search_url(keyword: &str) -> QQApiModuleResult<Url> {
             "method": "DoSearchForQQMusicDesktop",
             "module": "music.search.SearchCgiService",
             "param": {
-                "num_per_page": 5,
-                "page_num": 1,
+                "num_per_page": PAGE_SIZE,
+                "page_num": page,
                 "query": keyword,
                 "search_type": 0
             }
@@ -148,7 +165,7 @@ fn construct_search_url(keyword: &str) -> QQApiModuleResult<Url> {
 
 fn construct_single_url(
     ident: &QQResourceIdentifier,
-    format: QQFormat,
+    filenames: &[String],
     ctx: &Context,
 ) -> QQApiModuleResult<Url> {
     trace!("Constructing single URL with parameters…");
@@ -165,13 +182,9 @@ fn construct_single_url(
     let param = json!({
         "guid": fastrand::i32(1..10000000),
         "loginflag": 1u8,
-        "filename": [
-            format.to_filename(ident.file),
-        ],
-        "songmid": [
-            &ident.mid,
-        ],
-        "songtype": [0u8],
+        "filename": filenames,
+        "songmid": vec![ident.mid; filenames.len()],
+        "songtype": vec![0u8; filenames.len()],
         "uin": uin_value,
         "platform": "20",
     });
@@ -194,11 +207,8 @@ fn construct_single_url(
 
 #[derive(Debug, Error)]
 pub enum QQApiModuleError {
-    #[error("failed to send request: {0}")]
-    RequestFailed(reqwest::Error),
-
     #[error("failed to deserialize the response JSON: {0}")]
-    ResponseJsonDeserializeFailed(reqwest::Error),
+    ResponseJsonDeserializeFailed(RequestModuleError),
 
     #[error("invalid header value: {0}")]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
@@ -220,6 +230,9 @@ pub enum QQApiModuleError {
 
     #[error("unable to extract such a JSON pointer: {0}")]
     NoSuchField(#[from] UnableToExtractJson<'static>),
+
+    #[error("no format of the song matches the quality preference")]
+    NoAvailableFormat,
 }
 
 pub type QQApiModuleResult<T> = Result<T, QQApiModuleError>;
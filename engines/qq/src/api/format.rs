@@ -3,31 +3,63 @@ use unm_types::Context;
 
 use super::extract_cookie;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum QQFormat {
+    /// `C400`: AAC 96 kbps in M4A.
+    Aac96,
     /// `M500`: MP3, for guest (without cookie).
     GuestMp3,
+    /// `C600`: AAC 192 kbps in M4A.
+    Aac192,
+    /// `O600`: OGG Vorbis 192 kbps.
+    Ogg192,
     /// `M800`: MP3, for members (with cookie).
     MemberMp3,
     /// `F000`: FLAC, for members (with cookie).
     MemberFlac,
+    /// `RS01`: Hi-Res FLAC, for members (with cookie).
+    HiResFlac,
 }
 
+/// All the formats, from the best quality to the worst.
+pub const QUALITY_LADDER: [QQFormat; 7] = [
+    QQFormat::HiResFlac,
+    QQFormat::MemberFlac,
+    QQFormat::MemberMp3,
+    QQFormat::Ogg192,
+    QQFormat::Aac192,
+    QQFormat::GuestMp3,
+    QQFormat::Aac96,
+];
+
 impl QQFormat {
     /// Get the format ID according to the format.
     pub fn as_format_id(&self) -> &'static str {
         match self {
+            QQFormat::Aac96 => "C400",
             QQFormat::GuestMp3 => "M500",
+            QQFormat::Aac192 => "C600",
+            QQFormat::Ogg192 => "O600",
             QQFormat::MemberMp3 => "M800",
             QQFormat::MemberFlac => "F000",
+            QQFormat::HiResFlac => "RS01",
         }
     }
 
+    /// Get the format according to the format ID.
+    pub fn from_format_id(format_id: &str) -> Option<QQFormat> {
+        QUALITY_LADDER
+            .into_iter()
+            .find(|format| format.as_format_id() == format_id)
+    }
+
     /// Get the file extension according to the format.
     fn as_extension(&self) -> &'static str {
         match self {
+            QQFormat::Aac96 | QQFormat::Aac192 => ".m4a",
             QQFormat::GuestMp3 | QQFormat::MemberMp3 => ".mp3",
-            QQFormat::MemberFlac => ".flac",
+            QQFormat::Ogg192 => ".ogg",
+            QQFormat::MemberFlac | QQFormat::HiResFlac => ".flac",
         }
     }
 
@@ -36,13 +68,23 @@ impl QQFormat {
         concat_string!(self.as_format_id(), filename, self.as_extension())
     }
 
-    /// Determine the mode according to the context
+    /// Get the formats to try when `self` is preferred,
+    /// which are `self` and the worse ones, from the best one.
+    pub fn ladder(&self) -> impl Iterator<Item = QQFormat> {
+        let preferred = *self;
+
+        QUALITY_LADDER
+            .into_iter()
+            .skip_while(move |format| *format != preferred)
+    }
+
+    /// Determine the preferred format according to the context
     pub fn from_context(ctx: &Context) -> QQFormat {
         let cookie = extract_cookie(ctx);
 
         if cookie.is_some() {
             if ctx.enable_flac {
-                QQFormat::MemberFlac
+                QQFormat::HiResFlac
             } else {
                 QQFormat::MemberMp3
             }
@@ -56,7 +98,7 @@ impl QQFormat {
 mod tests {
     use concat_string::concat_string;
 
-    use crate::api::format::QQFormat;
+    use crate::api::format::{QQFormat, QUALITY_LADDER};
 
     const FILE: &str = "Aod01NqoG";
 
@@ -83,4 +125,43 @@ mod tests {
             concat_string!("F000", FILE, ".flac")
         );
     }
+
+    #[test]
+    fn test_format_other_formats() {
+        assert_eq!(
+            QQFormat::Aac96.to_filename(FILE),
+            concat_string!("C400", FILE, ".m4a")
+        );
+        assert_eq!(
+            QQFormat::Aac192.to_filename(FILE),
+            concat_string!("C600", FILE, ".m4a")
+        );
+        assert_eq!(
+            QQFormat::Ogg192.to_filename(FILE),
+            concat_string!("O600", FILE, ".ogg")
+        );
+        assert_eq!(
+            QQFormat::HiResFlac.to_filename(FILE),
+            concat_string!("RS01", FILE, ".flac")
+        );
+    }
+
+    #[test]
+    fn test_format_ladder() {
+        assert_eq!(
+            QQFormat::GuestMp3.ladder().collect::<Vec<_>>(),
+            vec![QQFormat::GuestMp3, QQFormat::Aac96]
+        );
+        assert_eq!(
+            QQFormat::MemberMp3.ladder().collect::<Vec<_>>(),
+            vec![
+                QQFormat::MemberMp3,
+                QQFormat::Ogg192,
+                QQFormat::Aac192,
+                QQFormat::GuestMp3,
+                QQFormat::Aac96
+            ]
+        );
+        assert_eq!(QQFormat::HiResFlac.ladder().count(), QUALITY_LADDER.len());
+    }
 }
@@ -1,26 +1,68 @@
 use std::error::Error;
 use std::fmt::Display;
 
+use super::format::QQFormat;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct QQResourceIdentifier<'a> {
     pub mid: &'a str,
     pub file: &'a str,
+    /// The file sizes of the available formats.
+    ///
+    /// It is empty if they are unknown, for example,
+    /// the identifier is serialized by the older version.
+    pub sizes: Vec<(QQFormat, u64)>,
 }
 
 impl<'a> QQResourceIdentifier<'a> {
     pub fn serialize(&self) -> String {
         log::debug!("Serializing a QQResourceIdentifier to String…");
-        concat_string::concat_string!(self.mid, ":::", self.file)
+
+        if self.sizes.is_empty() {
+            return concat_string::concat_string!(self.mid, ":::", self.file);
+        }
+
+        let sizes = self
+            .sizes
+            .iter()
+            .map(|(format, size)| format!("{}={size}", format.as_format_id()))
+            .collect::<Vec<_>>()
+            .join(",");
+        concat_string::concat_string!(self.mid, ":::", self.file, ":::", sizes)
     }
 
     pub fn deserialize(serialized: &'a str) -> DeserializationResult<Self> {
         log::debug!("Deserializing “{serialized}” to QQResourceIdentifier…");
 
-        let mut parts = serialized.splitn(2, ":::");
+        let mut parts = serialized.splitn(3, ":::");
         let mid = parts.next().ok_or(DeserializationFailed("song"))?;
         let file = parts.next().ok_or(DeserializationFailed("file"))?;
+        let sizes = match parts.next() {
+            Some(sizes) => sizes
+                .split(',')
+                .map(|entry| {
+                    let (format_id, size) = entry.split_once('=')?;
+                    Some((QQFormat::from_format_id(format_id)?, size.parse().ok()?))
+                })
+                .collect::<Option<Vec<_>>>()
+                .ok_or(DeserializationFailed("sizes"))?,
+            None => Vec::new(),
+        };
 
-        Ok(Self { mid, file })
+        Ok(Self { mid, file, sizes })
+    }
+
+    /// Get the formats to try when `preferred` is preferred,
+    /// from the best one.
+    ///
+    /// The formats not in [`Self::sizes`] are skipped if it is known.
+    pub fn formats_to_try(&self, preferred: QQFormat) -> Vec<QQFormat> {
+        preferred
+            .ladder()
+            .filter(|format| {
+                self.sizes.is_empty() || self.sizes.iter().any(|(f, size)| f == format && *size > 0)
+            })
+            .collect()
     }
 }
 
@@ -43,17 +85,36 @@ impl Error for DeserializationFailed {}
 #[cfg(test)]
 mod tests {
     use super::QQResourceIdentifier;
+    use crate::api::format::QQFormat;
 
     #[test]
     fn test_identifier_serialization() {
         let identifier = QQResourceIdentifier {
             mid: "mid",
             file: "file",
+            sizes: vec![],
         };
 
         assert_eq!(identifier.serialize(), "mid:::file");
     }
 
+    #[test]
+    fn test_identifier_serialization_with_sizes() {
+        let identifier = QQResourceIdentifier {
+            mid: "mid",
+            file: "file",
+            sizes: vec![
+                (QQFormat::GuestMp3, 4538390),
+                (QQFormat::MemberFlac, 33298463),
+            ],
+        };
+
+        assert_eq!(
+            identifier.serialize(),
+            "mid:::file:::M500=4538390,F000=33298463"
+        );
+    }
+
     #[test]
     fn test_identifier_deserialization() {
         let identifier = "mid113:::file113";
@@ -63,10 +124,32 @@ mod tests {
             QQResourceIdentifier {
                 mid: "mid113",
                 file: "file113",
+                sizes: vec![],
             }
         );
     }
 
+    #[test]
+    fn test_identifier_deserialization_with_sizes() {
+        let identifier = "mid113:::file113:::C400=3012345,M800=11345629";
+
+        let deserialized = QQResourceIdentifier::deserialize(identifier).unwrap();
+        assert_eq!(
+            deserialized.sizes,
+            vec![(QQFormat::Aac96, 3012345), (QQFormat::MemberMp3, 11345629)]
+        );
+        assert_eq!(
+            deserialized.formats_to_try(QQFormat::HiResFlac),
+            vec![QQFormat::MemberMp3, QQFormat::Aac96]
+        );
+        assert_eq!(
+            deserialized.formats_to_try(QQFormat::GuestMp3),
+            vec![QQFormat::Aac96]
+        );
+
+        assert!(QQResourceIdentifier::deserialize("mid113:::file113:::X999=1").is_err());
+    }
+
     #[test]
     fn test_identifier_failed_deserialization() {
         let identifier = "mid113::file113";
@@ -4,6 +4,7 @@ use concat_string::concat_string;
 use serde::Deserialize;
 use unm_types::{Album, Artist, Song};
 
+use super::format::QQFormat;
 use super::identifier::QQResourceIdentifier;
 
 #[derive(Debug, Deserialize)]
@@ -12,6 +13,15 @@ pub struct QQSongData {
     pub list: Vec<QQSongEntry>,
 }
 
+/// The metadata of a page of the search results.
+#[derive(Debug, Default, Deserialize)]
+#[non_exhaustive]
+pub struct QQSearchMeta {
+    /// The count of all the search results.
+    #[serde(default)]
+    pub sum: usize,
+}
+
 #[derive(Debug, Deserialize)]
 #[non_exhaustive]
 pub struct QQSongEntry {
@@ -30,6 +40,64 @@ pub struct QQSongEntry {
 
     /// The song MID of this song entry.
     pub mid: String,
+
+    /// The files of this song entry.
+    #[serde(default)]
+    pub file: Option<QQSongFile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[non_exhaustive]
+pub struct QQSongFile {
+    /// The media MID, which is the name of the files.
+    #[serde(default)]
+    pub media_mid: String,
+
+    #[serde(default)]
+    pub size_96aac: u64,
+    #[serde(default)]
+    pub size_128mp3: u64,
+    #[serde(default)]
+    pub size_192aac: u64,
+    #[serde(default)]
+    pub size_192ogg: u64,
+    #[serde(default)]
+    pub size_320mp3: u64,
+    #[serde(default)]
+    pub size_flac: u64,
+    #[serde(default)]
+    pub size_hires: u64,
+}
+
+impl QQSongFile {
+    /// Get the sizes of the available formats, from the best one.
+    pub fn sizes(&self) -> Vec<(QQFormat, u64)> {
+        [
+            (QQFormat::HiResFlac, self.size_hires),
+            (QQFormat::MemberFlac, self.size_flac),
+            (QQFormat::MemberMp3, self.size_320mp3),
+            (QQFormat::Ogg192, self.size_192ogg),
+            (QQFormat::Aac192, self.size_192aac),
+            (QQFormat::GuestMp3, self.size_128mp3),
+            (QQFormat::Aac96, self.size_96aac),
+        ]
+        .into_iter()
+        .filter(|(_, size)| *size > 0)
+        .collect()
+    }
+}
+
+impl QQSongEntry {
+    /// Whether this song entry has any file to fetch.
+    ///
+    /// It is `true` if the files are unknown.
+    pub fn is_available(&self) -> bool {
+        !self.mid.is_empty()
+            && self
+                .file
+                .as_ref()
+                .map_or(true, |file| !file.sizes().is_empty())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -96,14 +164,20 @@ impl From<QQSongEntry> for Song {
             .artists(entry.singer.into_iter().map(Into::into).collect())
             .context({
                 let mut ctx = HashMap::new();
-                let songmid = entry.mid.clone();
-                let media_mid = entry.mid;
+                let file = entry.file.unwrap_or_default();
+                let songmid = entry.mid;
+                let media_mid = if file.media_mid.is_empty() {
+                    songmid.clone()
+                } else {
+                    file.media_mid.clone()
+                };
 
                 ctx.insert(
                     "identifier".into(),
                     QQResourceIdentifier {
                         mid: &songmid,
                         file: &media_mid,
+                        sizes: file.sizes(),
                     }
                     .serialize(),
                 );
@@ -140,6 +214,15 @@ impl QQSingleResponse {
 
         Ok(concat_string!(server, url_info.purl))
     }
+
+    /// Keep the audio in `filenames` which we can fetch (whose `purl`
+    /// is not empty), and sort them in the order of `filenames`.
+    pub fn retain_in_order(&mut self, filenames: &[String]) {
+        self.midurlinfo
+            .retain(|info| !info.purl.is_empty() && filenames.iter().any(|f| *f == info.filename));
+        self.midurlinfo
+            .sort_by_key(|info| filenames.iter().position(|f| *f == info.filename));
+    }
 }
 
 /// The error that will return if no element is in the
@@ -157,6 +240,36 @@ impl std::error::Error for FieldNotPickable {}
 mod tests {
     use super::{QQSingleResponse, QQSingleUrlInfo};
 
+    #[test]
+    fn test_single_response_retain_in_order() {
+        let mut single_response = QQSingleResponse {
+            sip: vec!["http://helloworld.com/".into()],
+            midurlinfo: ["F000mid.flac", "M800mid.mp3", "O600mid.ogg", "M500mid.mp3"]
+                .into_iter()
+                .map(|filename| QQSingleUrlInfo {
+                    filename: filename.into(),
+                    purl: if filename.starts_with('F') {
+                        String::new()
+                    } else {
+                        concat_string::concat_string!(filename, "?114514")
+                    },
+                })
+                .collect(),
+        };
+
+        single_response.retain_in_order(&[
+            "F000mid.flac".to_string(),
+            "O600mid.ogg".to_string(),
+            "M800mid.mp3".to_string(),
+        ]);
+
+        assert_eq!(
+            single_response.get_url().unwrap(),
+            "http://helloworld.com/O600mid.ogg?114514"
+        );
+        assert_eq!(single_response.midurlinfo.len(), 2);
+    }
+
     #[test]
     fn test_single_response_get_url_with_single_data() {
         let single_response = QQSingleResponse {
@@ -187,7 +300,7 @@ mod tests {
         };
 
         let data = single_response.get_url().unwrap();
-        assert!(vec![
+        assert!([
             "http://helloworld.com/purl?114514",
             "http://helloworld.org/purl?114514"
         ]
@@ -2,6 +2,8 @@
 //!
 //! You may need to login your QQ Music account in your browser,
 //! obtain its cookie and fill the cookie to the `qq:cookie` config.
+//! The cookie seeds the cookie jar of QQ Music, so the cookies refreshed
+//! by QQ Music are kept (and persisted if `ctx.cookie_dir` is set).
 //!
 //! You can configure the cookie in the `ctx.config` field.
 //! You can build a `ctx.config` with [`unm_types::config::ConfigManagerBuilder`],
@@ -18,18 +20,30 @@
 //!     .config(config)
 //!     .build();
 //! ```
+//!
+//! The search results are fetched page by page (up to [`MAX_PAGES`]).
+//! The identifier records the sizes of the available formats, and
+//! the best available one is retrieved: Hi-Res (`RS01`) and FLAC
+//! (`F000`) if `enable_flac` is set, MP3 320k (`M800`), OGG 192k
+//! (`O600`) and AAC 192k (`C600`) if the cookie is set, and MP3 128k
+//! (`M500`) or AAC 96k (`C400`) otherwise. If a format is not allowed
+//! for the account, the next one is chosen.
 
 pub mod api;
 
-use api::{retrieve_single, search_by_keyword};
+use api::typing::QQSongEntry;
+use api::{retrieve_single, search_by_keyword, PAGE_SIZE};
 use async_trait::async_trait;
-use log::{debug, info};
+use log::info;
 use unm_engine::interface::Engine;
-use unm_selector::SimilarSongSelector;
+use unm_selector::pages::{find_in_pages, find_similar, SearchPage};
 use unm_types::{Context, RetrievedSongInfo, SerializedIdentifier, Song, SongSearchInformation};
 
 pub const ENGINE_ID: &str = "qq";
 
+/// The max pages of the search results to fetch.
+pub const MAX_PAGES: usize = 3;
+
 pub struct QQEngine;
 
 #[async_trait]
@@ -41,16 +55,21 @@ impl Engine for QQEngine {
     ) -> anyhow::Result<Option<SongSearchInformation>> {
         info!("Searching {info} with QQ Engine…");
 
-        let response = search_by_keyword(&info.keyword(), ctx).await?;
-        let mut song_iterator = response
-            .list
-            .into_iter()
-            .filter(|song| !song.mid.is_empty())
-            .map(Song::from);
+        let keyword = info.keyword();
+        let matched = find_in_pages(info, MAX_PAGES, |page| {
+            let keyword = &keyword;
+
+            async move {
+                let (data, meta) = search_by_keyword(keyword, page, ctx).await?;
+                let is_last = data.list.is_empty() || page * PAGE_SIZE >= meta.sum;
 
-        debug!("Matching the song…");
-        let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(info);
-        let matched = song_iterator.find(|s| selector(&s));
+                anyhow::Ok(SearchPage {
+                    songs: available_songs(data.list),
+                    is_last,
+                })
+            }
+        })
+        .await?;
 
         Ok(matched.map::<anyhow::Result<_>, _>(|song| Ok({
             SongSearchInformation::builder()
@@ -76,10 +95,14 @@ impl Engine for QQEngine {
 
         let response = retrieve_single(identifier, ctx).await?;
 
-        let url = response
-            .data
-            .map(|v| v.get_url())
-            .ok_or_else(|| anyhow::anyhow!("no data found"))??;
+        let url = match response.data.map(|v| v.get_url()) {
+            Some(Ok(url)) => url,
+            result => {
+                // The audio is likely unavailable because the session has expired.
+                unm_request::cookie::cookie_jar(ctx, ENGINE_ID).check_session()?;
+                result.ok_or_else(|| anyhow::anyhow!("no data found"))??
+            }
+        };
 
         Ok(RetrievedSongInfo::builder()
             .source(ENGINE_ID.into())
@@ -88,10 +111,26 @@ impl Engine for QQEngine {
     }
 }
 
+/// The songs in `entries` which have any file to fetch.
+fn available_songs(entries: Vec<QQSongEntry>) -> Vec<Song> {
+    entries
+        .into_iter()
+        .filter(|song| song.is_available())
+        .map(Song::from)
+        .collect()
+}
+
+/// Find the song similar to `info` in a page of the search results.
+pub fn find_match(info: &Song, entries: Vec<QQSongEntry>) -> Option<Song> {
+    find_similar(info, available_songs(entries))
+}
+
 #[cfg(test)]
 mod tests {
     use tokio::test;
-    use unm_types::{Artist, Context};
+    use unm_test_utils::{search_and_retrieve, with_cassette};
+    use unm_types::config::ConfigManagerBuilder;
+    use unm_types::{Artist, Context, ContextBuilder};
 
     use super::*;
 
@@ -104,16 +143,70 @@ mod tests {
     }
 
     #[test]
-    #[ignore]
     async fn qq_search() {
-        let info = get_info_1();
-        let info = QQEngine
-            .search(&info, &Context::default())
-            .await
-            .unwrap()
-            .unwrap();
-
-        assert!(info.identifier.contains(":::"));
-        assert_eq!(info.source, ENGINE_ID);
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) =
+                search_and_retrieve(&QQEngine, ENGINE_ID, &get_info_1(), &Context::default()).await;
+
+            assert_eq!(
+                info.identifier,
+                "003PkXzH3H2zCL:::003PkXzH3H2zCL:::F000=33298463,M800=11345629,M500=4538390"
+            );
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.id, "4830342");
+            assert_eq!(song.name, "干杯");
+            assert_eq!(song.duration, Some(283000));
+            assert_eq!(song.artists[0].id, "4367");
+            assert_eq!(song.artists[0].name, "五月天");
+            let album = song.album.expect("should have the album");
+            assert_eq!(album.id, "246745");
+            assert_eq!(album.name, "第二人生 末日版");
+
+            assert!(retrieved
+                .url
+                .starts_with("http://ws.stream.qqmusic.qq.com/M500003PkXzH3H2zCL.mp3?"));
+        })
+        .await;
+    }
+
+    #[test]
+    async fn qq_search_next_page_and_fallback() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/search_pages.json");
+
+        with_cassette(fixture, async {
+            let ctx = ContextBuilder::default()
+                .enable_flac(true)
+                .config(
+                    ConfigManagerBuilder::new()
+                        .set("qq:cookie", "uin=10001; qm_keyst=Q_H_L_5abc")
+                        .build(),
+                )
+                .build()
+                .unwrap();
+
+            // The live version and the concert version don't match the
+            // duration, and the accompaniment has no file to fetch.
+            let mut info = get_info_1();
+            info.duration = Some(283000);
+            let info = QQEngine.search(&info, &ctx).await.unwrap().unwrap();
+            assert_eq!(
+                info.identifier,
+                "003PkXzH3H2zCL:::003PkXzH3H2zCL:::F000=33298463,M800=11345629,\
+                 O600=6912345,C600=6823456,M500=4538390,C400=3412345"
+            );
+
+            // FLAC and MP3 320k are not allowed for this account,
+            // and there is no Hi-Res file.
+            let retrieved = QQEngine.retrieve(&info.identifier, &ctx).await.unwrap();
+            assert!(retrieved
+                .url
+                .starts_with("http://ws.stream.qqmusic.qq.com/O600003PkXzH3H2zCL.ogg?"));
+        })
+        .await;
     }
 }
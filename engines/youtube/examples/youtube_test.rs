@@ -0,0 +1,4 @@
+#[tokio::main]
+async fn main() {
+    unm_test_utils::engine_example_wrapper(unm_engine_youtube::YouTubeEngine).await;
+}
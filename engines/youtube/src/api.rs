@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use http::header::{HeaderName, USER_AGENT};
+use http::{HeaderMap, HeaderValue};
+use serde_json::json;
+use unm_request::build_client;
+use unm_request::json::Json;
+use unm_types::Context;
+use url::Url;
+
+use self::typing::{collect_videos, YouTubePlayerResponse, YouTubeVideoRenderer};
+use crate::ENGINE_ID;
+
+pub mod typing;
+
+/// The endpoint of the InnerTube API.
+const INNERTUBE_API: &str = "https://www.youtube.com/youtubei/v1";
+
+/// The `params` of the search API, which filters the results to the videos.
+const SEARCH_VIDEOS_PARAMS: &str = "EgIQAQ%3D%3D";
+
+/// The header to specify the InnerTube client ID.
+const X_YOUTUBE_CLIENT_NAME: &str = "x-youtube-client-name";
+
+/// The header to specify the InnerTube client version.
+const X_YOUTUBE_CLIENT_VERSION: &str = "x-youtube-client-version";
+
+/// The client that we pretend to be when requesting the InnerTube API.
+#[derive(Debug, Clone, Copy)]
+pub struct InnerTubeClient {
+    /// The `clientName` in the context, for example, `WEB`.
+    pub name: &'static str,
+    /// The `clientVersion` in the context.
+    pub version: &'static str,
+    /// The numeric ID of this client, for `X-YouTube-Client-Name`.
+    pub id: &'static str,
+    /// The `User-Agent` of the official app of this client.
+    pub user_agent: Option<&'static str>,
+}
+
+/// The web client, which is used to search.
+pub const WEB_CLIENT: InnerTubeClient = InnerTubeClient {
+    name: "WEB",
+    version: "2.20230728.00.00",
+    id: "1",
+    user_agent: None,
+};
+
+/// The Android client, whose formats have the plain URLs.
+pub const ANDROID_CLIENT: InnerTubeClient = InnerTubeClient {
+    name: "ANDROID",
+    version: "18.11.34",
+    id: "3",
+    user_agent: Some("com.google.android.youtube/18.11.34 (Linux; U; Android 11) gzip"),
+};
+
+/// The iOS client, whose formats have the plain URLs.
+pub const IOS_CLIENT: InnerTubeClient = InnerTubeClient {
+    name: "IOS",
+    version: "18.11.34",
+    id: "5",
+    user_agent: Some("com.google.ios.youtube/18.11.34 (iPhone14,3; U; CPU iOS 15_6 like Mac OS X)"),
+};
+
+impl InnerTubeClient {
+    /// Construct the `context` in the request body.
+    fn context(&self) -> Json {
+        let mut client = json!({
+            "clientName": self.name,
+            "clientVersion": self.version,
+            "hl": "en",
+            "gl": "US",
+        });
+
+        match self.name {
+            "ANDROID" => {
+                client["androidSdkVersion"] = json!(30);
+                client["osName"] = json!("Android");
+                client["osVersion"] = json!("11");
+            }
+            "IOS" => {
+                client["deviceModel"] = json!("iPhone14,3");
+                client["osName"] = json!("iOS");
+                client["osVersion"] = json!("15.6.0.19G71");
+            }
+            _ => {}
+        }
+
+        json!({ "client": client })
+    }
+
+    /// The headers required to request the streams in the player
+    /// response of this client, which is its `User-Agent`.
+    pub fn stream_headers(&self) -> BTreeMap<String, String> {
+        self.user_agent
+            .map(|user_agent| ("User-Agent".to_string(), user_agent.to_string()))
+            .into_iter()
+            .collect()
+    }
+
+    /// Construct the header to pass to the InnerTube API.
+    fn construct_header(&self) -> HeaderMap {
+        let mut hm = HeaderMap::with_capacity(3);
+        hm.insert(
+            HeaderName::from_static(X_YOUTUBE_CLIENT_NAME),
+            HeaderValue::from_static(self.id),
+        );
+        hm.insert(
+            HeaderName::from_static(X_YOUTUBE_CLIENT_VERSION),
+            HeaderValue::from_static(self.version),
+        );
+        if let Some(user_agent) = self.user_agent {
+            hm.insert(USER_AGENT, HeaderValue::from_static(user_agent));
+        }
+
+        hm
+    }
+}
+
+/// Construct the URL of the InnerTube `endpoint`, for example, `search`.
+fn construct_url(endpoint: &str) -> anyhow::Result<Url> {
+    Ok(Url::parse_with_params(
+        &format!("{INNERTUBE_API}/{endpoint}"),
+        &[("prettyPrint", "false")],
+    )?)
+}
+
+/// Search the videos with the `keyword`.
+pub async fn search(keyword: &str, context: &Context) -> anyhow::Result<Vec<YouTubeVideoRenderer>> {
+    let client = build_client(context, ENGINE_ID)?;
+    let body = json!({
+        "context": WEB_CLIENT.context(),
+        "query": keyword,
+        "params": SEARCH_VIDEOS_PARAMS,
+    });
+
+    let response = client
+        .post(construct_url("search")?)
+        .headers(WEB_CLIENT.construct_header())
+        .json(&body)
+        .send()
+        .await?;
+    Ok(collect_videos(&response.json::<Json>().await?))
+}
+
+/// Get the player response, which has the streams, of the video `video_id`
+/// as the `innertube_client`.
+pub async fn player(
+    video_id: &str,
+    innertube_client: &InnerTubeClient,
+    context: &Context,
+) -> anyhow::Result<YouTubePlayerResponse> {
+    let client = build_client(context, ENGINE_ID)?;
+    let body = json!({
+        "context": innertube_client.context(),
+        "videoId": video_id,
+        "contentCheckOk": true,
+        "racyCheckOk": true,
+    });
+
+    let response = client
+        .post(construct_url("player")?)
+        .headers(innertube_client.construct_header())
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json::<YouTubePlayerResponse>().await?)
+}
@@ -0,0 +1,360 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+use unm_request::json::Json;
+use unm_selector::metadata::parse_duration;
+use unm_types::{Artist, Song};
+
+/// The text in the InnerTube responses, which is
+/// either a `simpleText` or some `runs`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct YouTubeText {
+    #[serde(default)]
+    pub simple_text: Option<String>,
+
+    #[serde(default)]
+    pub runs: Vec<YouTubeTextRun>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct YouTubeTextRun {
+    pub text: String,
+}
+
+impl YouTubeText {
+    /// Get the plain text.
+    pub fn text(&self) -> String {
+        match &self.simple_text {
+            Some(text) => text.clone(),
+            None => self.runs.iter().map(|run| run.text.as_str()).collect(),
+        }
+    }
+}
+
+/// The video in the search results.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct YouTubeVideoRenderer {
+    /// The video ID.
+    pub video_id: String,
+
+    /// The video title.
+    pub title: YouTubeText,
+
+    /// The duration, for example, `3:59`.
+    #[serde(default)]
+    pub length_text: Option<YouTubeText>,
+
+    /// The channel name.
+    #[serde(default)]
+    pub owner_text: Option<YouTubeText>,
+}
+
+impl From<YouTubeVideoRenderer> for Song {
+    fn from(video: YouTubeVideoRenderer) -> Self {
+        log::trace!("Converting YouTubeVideoRenderer to Song…");
+
+        Song::builder()
+            .id(video.video_id)
+            .name(video.title.text())
+            .duration(
+                video
+                    .length_text
+                    .and_then(|length| parse_duration(&length.text())),
+            )
+            .artists(vec![Artist::builder()
+                .name(
+                    video
+                        .owner_text
+                        .map(|owner| owner.text())
+                        .unwrap_or_default(),
+                )
+                .build()])
+            .build()
+    }
+}
+
+/// Find all the videos (`videoRenderer`) in the search response.
+///
+/// The search results are deeply nested in the sections and shelves,
+/// so we walk through the whole response instead of the exact path.
+pub fn collect_videos(response: &Json) -> Vec<YouTubeVideoRenderer> {
+    let mut videos = Vec::new();
+    let mut stack = vec![response];
+
+    while let Some(value) = stack.pop() {
+        match value {
+            Json::Object(object) => {
+                if let Some(renderer) = object.get("videoRenderer") {
+                    match serde_json::from_value(renderer.clone()) {
+                        Ok(video) => videos.push(video),
+                        Err(e) => log::warn!("Failed to parse a video: {e}. Ignoring."),
+                    }
+                    continue;
+                }
+
+                stack.extend(object.values().rev());
+            }
+            Json::Array(array) => stack.extend(array.iter().rev()),
+            _ => {}
+        }
+    }
+
+    videos
+}
+
+/// The response of the player API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct YouTubePlayerResponse {
+    pub playability_status: YouTubePlayabilityStatus,
+
+    #[serde(default)]
+    pub streaming_data: Option<YouTubeStreamingData>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct YouTubePlayabilityStatus {
+    /// `OK` if the video is playable, or `LOGIN_REQUIRED`,
+    /// `UNPLAYABLE`, `ERROR`…
+    pub status: String,
+
+    /// Why the video is not playable.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl YouTubePlayabilityStatus {
+    pub fn is_ok(&self) -> bool {
+        self.status == "OK"
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct YouTubeStreamingData {
+    #[serde(default)]
+    pub adaptive_formats: Vec<YouTubeFormat>,
+}
+
+/// The stream of a video.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct YouTubeFormat {
+    pub itag: i64,
+
+    /// The URL of this stream, which is absent if
+    /// the URL is in the `signature_cipher`.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// The URL and signature of this stream in the form-urlencoded
+    /// string, for example, `s=…&sp=sig&url=…`.
+    #[serde(default)]
+    pub signature_cipher: Option<String>,
+
+    /// The MIME type, for example, `audio/webm; codecs="opus"`.
+    pub mime_type: String,
+
+    /// The bitrate of this stream (bps).
+    pub bitrate: i64,
+}
+
+/// The codec of the audio-only streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YouTubeAudioCodec {
+    /// Opus in WebM, `audio/webm; codecs="opus"`.
+    Opus,
+    /// AAC in MP4, `audio/mp4; codecs="mp4a.40.2"`.
+    M4a,
+}
+
+impl YouTubeAudioCodec {
+    /// The file extension of the streams in this codec.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Opus => "webm",
+            Self::M4a => "m4a",
+        }
+    }
+}
+
+impl FromStr for YouTubeAudioCodec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "opus" => Ok(Self::Opus),
+            "m4a" => Ok(Self::M4a),
+            _ => Err(anyhow::anyhow!("unknown audio format “{s}”")),
+        }
+    }
+}
+
+impl YouTubeFormat {
+    /// Get the URL to request this stream, if it needs no JavaScript.
+    ///
+    /// The plain `url` is used as is. In the `signature_cipher`, the
+    /// signature which is already deciphered (`sig` or `signature`) is
+    /// appended to the `url` as the `sp` parameter. The enciphered
+    /// one (`s`) needs the JavaScript of the player, so we return `None`.
+    pub fn stream_url(&self) -> Option<String> {
+        if let Some(url) = &self.url {
+            return Some(url.clone());
+        }
+
+        let mut url = None;
+        let mut signature = None;
+        let mut signature_param = None;
+        for (key, value) in url::form_urlencoded::parse(self.signature_cipher.as_ref()?.as_bytes())
+        {
+            match key.as_ref() {
+                "url" => url = Some(value),
+                "sig" | "signature" => signature = Some(value),
+                "sp" => signature_param = Some(value),
+                "s" => {
+                    log::debug!(
+                        "The stream {} has an enciphered signature. Skipping.",
+                        self.itag
+                    );
+                    return None;
+                }
+                _ => {}
+            }
+        }
+
+        let mut url = url::Url::parse(&url?).ok()?;
+        if let Some(signature) = signature {
+            url.query_pairs_mut().append_pair(
+                signature_param.as_deref().unwrap_or("signature"),
+                &signature,
+            );
+        }
+
+        Some(url.into())
+    }
+
+    /// Get the codec of this stream if this is an audio-only stream.
+    pub fn audio_codec(&self) -> Option<YouTubeAudioCodec> {
+        let (mime, codecs) = self
+            .mime_type
+            .split_once(';')
+            .unwrap_or((&self.mime_type, ""));
+
+        match mime.trim() {
+            "audio/webm" if codecs.contains("opus") => Some(YouTubeAudioCodec::Opus),
+            "audio/mp4" if codecs.contains("mp4a") => Some(YouTubeAudioCodec::M4a),
+            _ => None,
+        }
+    }
+}
+
+impl YouTubeStreamingData {
+    /// Get the audio-only stream with the highest bitrate.
+    /// Only the streams in `codec` are chosen if it is specified.
+    ///
+    /// The streams without a [`YouTubeFormat::stream_url`] are skipped,
+    /// since deciphering their signatures needs running the JavaScript
+    /// of the player.
+    pub fn best_audio(&self, codec: Option<YouTubeAudioCodec>) -> Option<&YouTubeFormat> {
+        log::debug!("Getting the best audio stream from YouTubeStreamingData…");
+
+        self.adaptive_formats
+            .iter()
+            .filter(|format| format.stream_url().is_some())
+            .filter(|format| match (format.audio_codec(), codec) {
+                (Some(actual), Some(expected)) => actual == expected,
+                (actual, None) => actual.is_some(),
+                (None, _) => false,
+            })
+            .max_by_key(|format| format.bitrate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{YouTubeAudioCodec, YouTubeFormat, YouTubeStreamingData};
+
+    fn format(itag: i64, mime_type: &str, bitrate: i64, url: bool) -> YouTubeFormat {
+        YouTubeFormat {
+            itag,
+            url: url.then(|| {
+                format!("https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag={itag}")
+            }),
+            signature_cipher: (!url).then(|| format!("s=AOq0QJ8&sp=sig&url=itag%3D{itag}")),
+            mime_type: mime_type.to_string(),
+            bitrate,
+        }
+    }
+
+    #[test]
+    fn best_audio_test() {
+        let data = YouTubeStreamingData {
+            adaptive_formats: vec![
+                format(137, r#"video/mp4; codecs="avc1.640028""#, 4000000, true),
+                format(140, r#"audio/mp4; codecs="mp4a.40.2""#, 130000, true),
+                format(251, r#"audio/webm; codecs="opus""#, 140000, true),
+                format(250, r#"audio/webm; codecs="opus""#, 160000, false),
+            ],
+        };
+
+        assert_eq!(data.best_audio(None).map(|f| f.itag), Some(251));
+        assert_eq!(
+            data.best_audio(Some(YouTubeAudioCodec::M4a))
+                .map(|f| f.itag),
+            Some(140)
+        );
+
+        let data = YouTubeStreamingData {
+            adaptive_formats: vec![format(251, r#"audio/webm; codecs="opus""#, 140000, false)],
+        };
+        assert!(data.best_audio(None).is_none());
+    }
+
+    #[test]
+    fn stream_url_test() {
+        let cipher = |signature_cipher: &str| YouTubeFormat {
+            url: None,
+            signature_cipher: Some(signature_cipher.to_string()),
+            ..format(251, r#"audio/webm; codecs="opus""#, 140000, false)
+        };
+        let url = "https%3A%2F%2Frr1---sn-a5mekn6s.googlevideo.com%2Fvideoplayback%3Fitag%3D251";
+
+        // The plain URL.
+        assert_eq!(
+            format(251, r#"audio/webm; codecs="opus""#, 140000, true).stream_url(),
+            Some("https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag=251".to_string())
+        );
+
+        // The deciphered signature.
+        assert_eq!(
+            cipher(&format!("sig=AOq0QJ8wRAIg&sp=sig&url={url}")).stream_url(),
+            Some(
+                "https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag=251&sig=AOq0QJ8wRAIg"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            cipher(&format!("url={url}&signature=AOq0QJ8wRAIg")).stream_url(),
+            Some(
+                "https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag=251&signature=AOq0QJ8wRAIg"
+                    .to_string()
+            )
+        );
+
+        // The enciphered signature needs the JavaScript of the player.
+        assert_eq!(
+            cipher(&format!("s=AOq0QJ8&sp=sig&url={url}")).stream_url(),
+            None
+        );
+        assert_eq!(cipher("sp=sig").stream_url(), None);
+    }
+}
@@ -0,0 +1,290 @@
+//! UNM Engine: YouTube
+//!
+//! It searches and fetches the audio from YouTube with the InnerTube API,
+//! the internal JSON API of YouTube, so it needs neither `yt-dlp` nor
+//! a JavaScript runtime, which the [`ytdl`](https://docs.rs/unm_engine_ytdl)
+//! engine requires.
+//!
+//! The identifier is the video ID. The audio is the audio-only stream
+//! (Opus or M4A) with the highest bitrate. You can choose the format by
+//! configuring `youtube:format` to `opus` or `m4a`.
+//!
+//! The streams of the web client need deciphering their signatures and
+//! transforming their `n` parameters with the JavaScript of the player,
+//! so we request the player of the Android and iOS clients instead,
+//! whose streams have the plain URLs, or the signatures which are already
+//! deciphered, and aren't throttled by the `n` parameter. Their URLs are
+//! only accepted with the `User-Agent` of the client, which we return in
+//! [`RetrievedSongInfo::headers`]. The streams with the enciphered
+//! signatures, and the videos only available to the web client, can't be
+//! retrieved.
+
+pub mod api;
+
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use unm_engine::interface::Engine;
+use unm_selector::metadata::is_topic_channel;
+use unm_selector::SimilarSongSelector;
+use unm_types::config::ConfigManager;
+use unm_types::{Context, RetrievedSongInfo, SerializedIdentifier, Song, SongSearchInformation};
+
+use crate::api::typing::{YouTubeAudioCodec, YouTubeVideoRenderer};
+use crate::api::{InnerTubeClient, ANDROID_CLIENT, IOS_CLIENT};
+
+pub const ENGINE_ID: &str = "youtube";
+
+/// The clients to request the player with, in order.
+const PLAYER_CLIENTS: [InnerTubeClient; 2] = [ANDROID_CLIENT, IOS_CLIENT];
+
+/// The `youtube` engine that can fetch audio from YouTube.
+pub struct YouTubeEngine;
+
+#[async_trait]
+impl Engine for YouTubeEngine {
+    async fn search<'a>(
+        &self,
+        info: &'a Song,
+        ctx: &'a Context,
+    ) -> anyhow::Result<Option<SongSearchInformation>> {
+        info!("Searching with YouTube engine…");
+
+        let videos = api::search(&info.keyword(), ctx).await?;
+
+        Ok(find_match(info, videos).map(|song| {
+            SongSearchInformation::builder()
+                .source(ENGINE_ID.into())
+                .identifier(song.id.to_string())
+                .song(Some(song))
+                .build()
+        }))
+    }
+
+    async fn retrieve<'a>(
+        &self,
+        identifier: &'a SerializedIdentifier,
+        ctx: &'a Context,
+    ) -> anyhow::Result<RetrievedSongInfo> {
+        info!("Retrieving the song by identifier…");
+
+        let codec = decide_codec(&ctx.config);
+        let mut error = None;
+
+        for client in &PLAYER_CLIENTS {
+            debug!(
+                "Requesting the player of “{identifier}” as {}…",
+                client.name
+            );
+
+            // Another client may still work if this one fails.
+            let response = match api::player(identifier, client, ctx).await {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!(
+                        "Failed to request the player of “{identifier}” as {}: {e}",
+                        client.name
+                    );
+                    error = Some(e);
+                    continue;
+                }
+            };
+            let status = &response.playability_status;
+            if !status.is_ok() {
+                let reason = status.reason.as_deref().unwrap_or("no reason");
+                warn!(
+                    "“{identifier}” is not playable as {}: {reason}",
+                    client.name
+                );
+                error = Some(anyhow::anyhow!(
+                    "the video “{identifier}” is not playable ({}): {reason}",
+                    status.status
+                ));
+                continue;
+            }
+
+            let format = response
+                .streaming_data
+                .as_ref()
+                .and_then(|data| data.best_audio(codec));
+            match format {
+                Some(format) => {
+                    debug!("Picked the audio stream {} of “{identifier}”.", format.itag);
+
+                    return Ok(RetrievedSongInfo::builder()
+                        .source(ENGINE_ID.into())
+                        .url(format.stream_url().expect("best_audio has the URL"))
+                        .headers(client.stream_headers())
+                        .bitrate(u32::try_from(format.bitrate).ok())
+                        .format(
+                            format
+                                .audio_codec()
+                                .map(|codec| codec.extension().to_string()),
+                        )
+                        .build());
+                }
+                None => {
+                    error = Some(anyhow::anyhow!(
+                    "no audio stream without the enciphered signature in the video “{identifier}”"
+                ))
+                }
+            }
+        }
+
+        Err(error.expect("PLAYER_CLIENTS should not be empty"))
+    }
+}
+
+/// Find the song similar to `info` in the search results `videos`,
+/// preferring the official audio uploaded by the "- Topic" channels.
+pub fn find_match(info: &Song, videos: Vec<YouTubeVideoRenderer>) -> Option<Song> {
+    debug!("Matching the song…");
+    let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(info);
+    let candidates = videos
+        .into_iter()
+        .map(Song::from)
+        .filter(|s| selector(&s))
+        .collect::<Vec<_>>();
+
+    let topic = candidates.iter().position(is_topic).unwrap_or(0);
+    candidates.into_iter().nth(topic)
+}
+
+/// Whether the `song` is uploaded by an auto-generated "- Topic" channel.
+fn is_topic(song: &Song) -> bool {
+    song.artists
+        .iter()
+        .any(|artist| is_topic_channel(&artist.name))
+}
+
+/// The audio format in `youtube:format`. Any format is accepted if unset.
+fn decide_codec(config: &Option<ConfigManager>) -> Option<YouTubeAudioCodec> {
+    let format = config
+        .as_ref()?
+        .get_deref(Cow::Borrowed("youtube:format"))?;
+
+    match format.parse() {
+        Ok(codec) => Some(codec),
+        Err(e) => {
+            warn!("Ignoring `youtube:format`: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unm_engine::interface::Engine;
+    use unm_test_utils::{sample_song, search_and_retrieve, with_cassette};
+    use unm_types::config::ConfigManagerBuilder;
+    use unm_types::{Context, ContextBuilder};
+
+    use super::{YouTubeEngine, ENGINE_ID};
+
+    #[tokio::test]
+    async fn test_search_and_retrieve() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/search_and_retrieve.json"
+        );
+
+        with_cassette(fixture, async {
+            let (info, retrieved) = search_and_retrieve(
+                &YouTubeEngine,
+                ENGINE_ID,
+                &sample_song(),
+                &Context::default(),
+            )
+            .await;
+
+            // The cover is too long, and the "- Topic" upload is preferred
+            // over the music video.
+            assert_eq!(info.identifier, "Jk4yV7oQ2Ws");
+            let song = info.song.expect("should have the song");
+            assert_eq!(song.name, "青花瓷");
+            assert_eq!(song.duration, Some(239000));
+            assert_eq!(song.artists[0].name, "Jay Chou - Topic");
+
+            // The best stream with a signature is skipped.
+            assert!(retrieved
+                .url
+                .starts_with("https://rr2---sn-oguelnzz.googlevideo.com/videoplayback?"));
+            assert!(retrieved.url.contains("itag=251"));
+            assert_eq!(retrieved.format.as_deref(), Some("webm"));
+            assert_eq!(retrieved.bitrate, Some(141232));
+            // The stream only accepts the client which requested it.
+            assert!(retrieved.headers["User-Agent"].starts_with("com.google.android.youtube/"));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_fallback() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/retrieve_fallback.json"
+        );
+
+        // The Android client requires signing in, so we ask the iOS client.
+        with_cassette(fixture, async {
+            let ctx = ContextBuilder::default()
+                .config(
+                    ConfigManagerBuilder::new()
+                        .set("youtube:format", "m4a")
+                        .build(),
+                )
+                .build()
+                .unwrap();
+
+            let retrieved = YouTubeEngine
+                .retrieve(&"Jk4yV7oQ2Ws".into(), &ctx)
+                .await
+                .unwrap();
+            assert!(retrieved.url.contains("itag=140"));
+            assert!(retrieved.url.contains("c=IOS"));
+            assert!(retrieved.headers["User-Agent"].starts_with("com.google.ios.youtube/"));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_client_error() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/retrieve_client_error.json"
+        );
+
+        // The Android client is rejected, so we ask the iOS client.
+        with_cassette(fixture, async {
+            let retrieved = YouTubeEngine
+                .retrieve(&"Jk4yV7oQ2Ws".into(), &Context::default())
+                .await
+                .unwrap();
+            assert!(retrieved.url.contains("c=IOS"));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_unplayable() {
+        let fixture = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/fixtures/retrieve_unplayable.json"
+        );
+
+        with_cassette(fixture, async {
+            let Err(error) = YouTubeEngine
+                .retrieve(&"bGlCGMSfx1M".into(), &Context::default())
+                .await
+            else {
+                panic!("the video should be unplayable");
+            };
+
+            let message = error.to_string();
+            assert!(message.contains("UNPLAYABLE"));
+            assert!(message.contains("not made this video available in your country"));
+        })
+        .await;
+    }
+}
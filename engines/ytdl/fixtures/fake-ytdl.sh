@@ -0,0 +1,21 @@
+#!/bin/sh
+# Stand-in of `yt-dlp` for the tests: print the canned `--dump-json`
+# output of the search extractor in the last argument, such as
+# `scsearch.json` for `scsearch5:<query>`, or the canned result
+# whose `webpage_url` is the URL in the last argument.
+# It fails as if the proxy were unreachable when `--proxy` is passed.
+proxy=
+previous=
+for query; do
+    [ "$previous" = "--proxy" ] && proxy="$query"
+    previous="$query"
+done
+if [ -n "$proxy" ]; then
+    echo "ERROR: Unable to download webpage: <urlopen error [Errno 111] Connection refused> (caused by ProxyError('$proxy'))" >&2
+    exit 1
+fi
+fixtures="$(dirname "$0")"
+case "$query" in
+    http*) exec grep -hF "\"webpage_url\": \"$query\"" "$fixtures"/*.json ;;
+    *) exec cat "$fixtures/${query%%[0-9]*}.json" ;;
+esac
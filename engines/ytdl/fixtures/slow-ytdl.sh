@@ -0,0 +1,3 @@
+#!/bin/sh
+# Stand-in of `yt-dlp` for the tests: hang until it is killed.
+exec sleep 60
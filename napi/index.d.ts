@@ -23,6 +23,15 @@ export const enum SearchMode {
    */
   OrderFirst = 1
 }
+/** The HTTP version preference of a client. */
+export const enum HttpVersion {
+  /** Negotiate with the upstream. HTTP/2 is preferred in ALPN. */
+  Auto = 0,
+  /** Use HTTP/1.1 only. */
+  Http1 = 1,
+  /** Use HTTP/2 directly without negotiating. The upstream must support it. */
+  Http2PriorKnowledge = 2
+}
 /** [napi-rs] The metadata of the artist of a song. */
 export interface Artist {
   /** The identifier of this artist. */
@@ -67,12 +76,51 @@ export interface SongSearchInformation {
   /** The pre-retrieve result of this search. */
   preRetrieveResult?: RetrievedSongInfo
 }
+/** [napi-rs] The playlist or album of NetEase Cloud Music. */
+export interface TrackList {
+  /** The NetEase Cloud Music ID of this playlist or album. */
+  id: string
+  /** The name of this playlist or album. */
+  name: string
+  /** The tracks, in the order of this playlist or album. */
+  tracks: Array<Track>
+}
+/** [napi-rs] A track in a playlist or an album, with its availability. */
+export interface Track {
+  /** The metadata of this track. */
+  song: Song
+  /**
+   * Whether this track is unavailable on NetEase Cloud Music,
+   * for example, greyed out or for VIP only.
+   */
+  unavailable: boolean
+  /** The search result of this track, if it has been searched. */
+  searchResult?: SongSearchInformation
+  /** Why the search of this track failed, if it has been searched. */
+  searchError?: string
+}
+/** [napi-rs] The result of searching a song in `searchBatch()`. */
+export interface BatchSearchResult {
+  /** The search result, if the song is found. */
+  result?: SongSearchInformation
+  /** Why the search failed, if the song is not found. */
+  error?: string
+}
 /** [napi-rs] The information of the song retrieved with `retrieve()`. */
 export interface RetrievedSongInfo {
   /** The retrieve source of this song, for example: `bilibili`. */
   source: string
   /** The URL of this song. */
   url: string
+  /** The headers required to request the URL, for example, the `Referer`. */
+  headers?: Record<string, string>
+  /** The bitrate (bps) of this song, if the engine knows it. */
+  bitrate?: number
+  /**
+   * The format (file extension) of this song, for example: `flac`,
+   * if the engine knows it.
+   */
+  format?: string
 }
 /** [napi-rs] The context. */
 export interface Context {
@@ -84,6 +132,120 @@ export interface Context {
   searchMode?: SearchMode
   /** The config for engines. */
   config?: Record<string, string>
+  /**
+   * The host mapping applied to the requests of engines,
+   * for example: `{ "www.kuwo.cn": "203.0.113.10" }`.
+   */
+  hostMapping?: Record<string, string>
+  /** The DNS settings applied to the requests of engines. */
+  dns?: DnsConfig
+  /** The per-engine and per-host proxy settings. */
+  proxy?: ProxyConfig
+  /** The rate limits and concurrency caps of the upstream hosts. */
+  throttle?: ThrottleConfig
+  /**
+   * The directory to persist the cookies of engines, for example: `./cookies`.
+   * The cookies are kept in memory only if it is not set.
+   */
+  cookieDir?: string
+  /** The HTTP client profiles (timeouts, user agents, headers) of engines. */
+  clientProfiles?: ClientProfiles
+}
+/**
+ * [napi-rs] The HTTP client profiles of engines.
+ *
+ * The profile of an engine overrides the fields set in the `default` one.
+ */
+export interface ClientProfiles {
+  /** The profile for every engine. */
+  default?: ClientProfile
+  /** The profile of each engine, for example: `kuwo`. */
+  engines?: Record<string, ClientProfile>
+}
+/** [napi-rs] The settings of an HTTP client. */
+export interface ClientProfile {
+  /** The timeout (in milliseconds) of connecting to the upstream. */
+  connectTimeoutMs?: number
+  /** The timeout (in milliseconds) of a whole request. By default, it is `10000`. */
+  timeoutMs?: number
+  /**
+   * The longest time (in milliseconds) without receiving anything
+   * from the upstream, when waiting for the response or reading its body.
+   */
+  readTimeoutMs?: number
+  /**
+   * The user agents to send. If there are several,
+   * they are used in rotation for each request.
+   * They are ignored if `User-Agent` is set in `headers`.
+   */
+  userAgents?: Array<string>
+  /**
+   * The extra default headers, which override the built-in ones.
+   * `User-Agent` here takes precedence over `user_agents`.
+   */
+  headers?: Record<string, string>
+  /** Which HTTP version to use. */
+  httpVersion?: HttpVersion
+  /** How many idle connections to keep for each host. */
+  poolMaxIdlePerHost?: number
+}
+/**
+ * [napi-rs] The rate limits and concurrency caps of the upstream hosts.
+ *
+ * The limit of a host takes precedence over the limit of an engine.
+ */
+export interface ThrottleConfig {
+  /** The limit of each host pattern, for example: `*.kuwo.cn`. */
+  hosts?: Record<string, UpstreamLimit>
+  /** The limit of the hosts requested by each engine, for example: `qq`. */
+  engines?: Record<string, UpstreamLimit>
+}
+/** [napi-rs] The limit of an upstream host. */
+export interface UpstreamLimit {
+  /** How many requests can be sent per second on average. */
+  requestsPerSecond?: number
+  /** How many requests can be sent at once after idling. By default, it is `1`. */
+  burst?: number
+  /** How many requests can be in flight at the same time. */
+  maxConcurrency?: number
+}
+/** [napi-rs] The DNS settings. */
+export interface DnsConfig {
+  /**
+   * The static overrides of hosts to IP addresses,
+   * for example: `{ "www.kuwo.cn": "203.0.113.10" }`.
+   *
+   * The invalid IP addresses are ignored.
+   */
+  hosts?: Record<string, string>
+  /**
+   * The DNS server, for example: `8.8.8.8:53` (UDP)
+   * or `https://1.1.1.1/dns-query` (DNS-over-HTTPS).
+   */
+  server?: string
+}
+/**
+ * [napi-rs] The per-engine and per-host proxy settings.
+ *
+ * An empty proxy URI means connecting directly.
+ */
+export interface ProxyConfig {
+  /**
+   * The proxy URI of each engine, for example:
+   * `{ "ytdl": "socks5://127.0.0.1:1080", "kuwo": "" }`.
+   */
+  engines?: Record<string, string>
+  /** The proxy rules matched with the host of requests, in order. */
+  rules?: Array<ProxyRule>
+  /** The host patterns to connect directly, for example: `["*.kuwo.cn"]`. */
+  noProxy?: Array<string>
+}
+/** [napi-rs] A rule to use `uri` as the proxy of `hosts`. */
+export interface ProxyRule {
+  /** The host patterns this rule applies to, for example: `["*.googlevideo.com"]`. */
+  hosts: Array<string>
+  /** The proxy URI. Connect directly if it is empty. */
+  uri: string
 }
 /** The available logging output. */
 export const enum LoggingType {
@@ -109,5 +271,25 @@ export class Executor {
   constructor()
   list(): Array<string>
   search(engines: Array<string>, song: Song, ctx: Context): Promise<SongSearchInformation>
+  /** Search the song with its NetEase Cloud Music track `id`. */
+  searchById(engines: Array<string>, id: string, ctx: Context): Promise<SongSearchInformation>
+  /** Resolve the metadata of the song with its NetEase Cloud Music track `id`. */
+  resolveMetadata(id: string, ctx: Context): Promise<Song>
+  /** Search each of the `songs`. The results are in the same order as `songs`. */
+  searchBatch(engines: Array<string>, songs: Array<Song>, ctx: Context): Promise<Array<BatchSearchResult>>
+  /**
+   * Resolve the NetEase Cloud Music playlist `id` to the tracks.
+   *
+   * If `searchEngines` is specified, the unavailable tracks
+   * are searched with these engines.
+   */
+  resolvePlaylist(id: string, ctx: Context, searchEngines?: Array<string> | undefined | null): Promise<TrackList>
+  /**
+   * Resolve the NetEase Cloud Music album `id` to the tracks.
+   *
+   * If `searchEngines` is specified, the unavailable tracks
+   * are searched with these engines.
+   */
+  resolveAlbum(id: string, ctx: Context, searchEngines?: Array<string> | undefined | null): Promise<TrackList>
   retrieve(song: SongSearchInformation, ctx: Context): Promise<RetrievedSongInfo>
 }
@@ -3,7 +3,9 @@ use napi_derive::napi;
 use std::borrow::Cow;
 use unm_engine::executor::Executor;
 
-use crate::types::{Context, RetrievedSongInfo, Song, SongSearchInformation};
+use crate::types::{
+  BatchSearchResult, Context, RetrievedSongInfo, Song, SongSearchInformation, TrackList,
+};
 
 #[napi(js_name = "Executor")]
 pub struct JsExecutor {
@@ -42,6 +44,107 @@ impl JsExecutor {
       .map_err(|e| Error::new(Status::GenericFailure, format!("Unable to search: {e:?}")))
   }
 
+  /// Search the song with its NetEase Cloud Music track `id`.
+  #[napi]
+  pub async fn search_by_id(
+    &self,
+    engines: Vec<String>,
+    id: String,
+    ctx: Context,
+  ) -> Result<SongSearchInformation> {
+    let engines = engines
+      .into_iter()
+      .map(|engine| engine.into())
+      .collect::<Vec<Cow<'static, str>>>();
+
+    self
+      .executor
+      .search_by_id(&engines, &id, &ctx.into())
+      .await
+      .map(|v| v.into())
+      .map_err(|e| Error::new(Status::GenericFailure, format!("Unable to search: {e:?}")))
+  }
+
+  /// Resolve the metadata of the song with its NetEase Cloud Music track `id`.
+  #[napi]
+  pub async fn resolve_metadata(&self, id: String, ctx: Context) -> Result<Song> {
+    self
+      .executor
+      .resolve_metadata(&id, &ctx.into())
+      .await
+      .map(|v| v.into())
+      .map_err(|e| {
+        Error::new(
+          Status::GenericFailure,
+          format!("Unable to resolve the metadata: {e:?}"),
+        )
+      })
+  }
+
+  /// Search each of the `songs`. The results are in the same order as `songs`.
+  #[napi]
+  pub async fn search_batch(
+    &self,
+    engines: Vec<String>,
+    songs: Vec<Song>,
+    ctx: Context,
+  ) -> Vec<BatchSearchResult> {
+    let engines = to_engine_ids(engines);
+    let songs = songs.into_iter().map(Into::into).collect::<Vec<_>>();
+
+    self
+      .executor
+      .search_batch(&engines, &songs, &ctx.into())
+      .await
+      .into_iter()
+      .map(Into::into)
+      .collect()
+  }
+
+  /// Resolve the NetEase Cloud Music playlist `id` to the tracks.
+  ///
+  /// If `searchEngines` is specified, the unavailable tracks
+  /// are searched with these engines.
+  #[napi]
+  pub async fn resolve_playlist(
+    &self,
+    id: String,
+    ctx: Context,
+    search_engines: Option<Vec<String>>,
+  ) -> Result<TrackList> {
+    let ctx = ctx.into();
+    let list = unm_ncm::get_playlist(&id, &ctx).await.map_err(|e| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Unable to resolve the playlist: {e:?}"),
+      )
+    })?;
+
+    Ok(self.search_unavailable(list, &ctx, search_engines).await)
+  }
+
+  /// Resolve the NetEase Cloud Music album `id` to the tracks.
+  ///
+  /// If `searchEngines` is specified, the unavailable tracks
+  /// are searched with these engines.
+  #[napi]
+  pub async fn resolve_album(
+    &self,
+    id: String,
+    ctx: Context,
+    search_engines: Option<Vec<String>>,
+  ) -> Result<TrackList> {
+    let ctx = ctx.into();
+    let list = unm_ncm::get_album(&id, &ctx).await.map_err(|e| {
+      Error::new(
+        Status::GenericFailure,
+        format!("Unable to resolve the album: {e:?}"),
+      )
+    })?;
+
+    Ok(self.search_unavailable(list, &ctx, search_engines).await)
+  }
+
   #[napi]
   pub async fn retrieve(
     &self,
@@ -57,6 +160,46 @@ impl JsExecutor {
   }
 }
 
+impl JsExecutor {
+  /// Convert the `list`, and search its unavailable tracks with `engines`.
+  async fn search_unavailable(
+    &self,
+    list: unm_ncm::TrackList,
+    ctx: &unm_types::Context,
+    engines: Option<Vec<String>>,
+  ) -> TrackList {
+    let unavailable = list
+      .tracks
+      .iter()
+      .filter(|t| t.unavailable)
+      .map(|t| t.song.clone())
+      .collect::<Vec<_>>();
+    let mut list = TrackList::from(list);
+
+    let Some(engines) = engines else {
+      return list;
+    };
+
+    let results = self
+      .executor
+      .search_batch(&to_engine_ids(engines), &unavailable, ctx)
+      .await;
+
+    let tracks = list.tracks.iter_mut().filter(|t| t.unavailable);
+    for (track, result) in tracks.zip(results) {
+      let BatchSearchResult { result, error } = result.into();
+      track.search_result = result;
+      track.search_error = error;
+    }
+
+    list
+  }
+}
+
+fn to_engine_ids(engines: Vec<String>) -> Vec<Cow<'static, str>> {
+  engines.into_iter().map(Into::into).collect()
+}
+
 impl Default for JsExecutor {
   fn default() -> Self {
     Self {
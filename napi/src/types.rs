@@ -22,6 +22,17 @@ pub enum SearchMode {
   OrderFirst,
 }
 
+/// The HTTP version preference of a client.
+#[napi]
+pub enum HttpVersion {
+  /// Negotiate with the upstream. HTTP/2 is preferred in ALPN.
+  Auto,
+  /// Use HTTP/1.1 only.
+  Http1,
+  /// Use HTTP/2 directly without negotiating. The upstream must support it.
+  Http2PriorKnowledge,
+}
+
 /// [napi-rs] The metadata of the artist of a song.
 #[napi(object)]
 pub struct Artist {
@@ -72,6 +83,40 @@ pub struct SongSearchInformation {
   pub pre_retrieve_result: Option<RetrievedSongInfo>,
 }
 
+/// [napi-rs] The playlist or album of NetEase Cloud Music.
+#[napi(object)]
+pub struct TrackList {
+  /// The NetEase Cloud Music ID of this playlist or album.
+  pub id: String,
+  /// The name of this playlist or album.
+  pub name: String,
+  /// The tracks, in the order of this playlist or album.
+  pub tracks: Vec<Track>,
+}
+
+/// [napi-rs] A track in a playlist or an album, with its availability.
+#[napi(object)]
+pub struct Track {
+  /// The metadata of this track.
+  pub song: Song,
+  /// Whether this track is unavailable on NetEase Cloud Music,
+  /// for example, greyed out or for VIP only.
+  pub unavailable: bool,
+  /// The search result of this track, if it has been searched.
+  pub search_result: Option<SongSearchInformation>,
+  /// Why the search of this track failed, if it has been searched.
+  pub search_error: Option<String>,
+}
+
+/// [napi-rs] The result of searching a song in `searchBatch()`.
+#[napi(object)]
+pub struct BatchSearchResult {
+  /// The search result, if the song is found.
+  pub result: Option<SongSearchInformation>,
+  /// Why the search failed, if the song is not found.
+  pub error: Option<String>,
+}
+
 /// [napi-rs] The information of the song retrieved with `retrieve()`.
 #[napi(object)]
 pub struct RetrievedSongInfo {
@@ -79,6 +124,13 @@ pub struct RetrievedSongInfo {
   pub source: String,
   /// The URL of this song.
   pub url: String,
+  /// The headers required to request the URL, for example, the `Referer`.
+  pub headers: Option<HashMap<String, String>>,
+  /// The bitrate (bps) of this song, if the engine knows it.
+  pub bitrate: Option<u32>,
+  /// The format (file extension) of this song, for example: `flac`,
+  /// if the engine knows it.
+  pub format: Option<String>,
 }
 
 /// [napi-rs] The context.
@@ -95,6 +147,132 @@ pub struct Context {
 
   /// The config for engines.
   pub config: Option<HashMap<String, String>>,
+
+  /// The host mapping applied to the requests of engines,
+  /// for example: `{ "www.kuwo.cn": "203.0.113.10" }`.
+  pub host_mapping: Option<HashMap<String, String>>,
+
+  /// The DNS settings applied to the requests of engines.
+  pub dns: Option<DnsConfig>,
+
+  /// The per-engine and per-host proxy settings.
+  pub proxy: Option<ProxyConfig>,
+
+  /// The rate limits and concurrency caps of the upstream hosts.
+  pub throttle: Option<ThrottleConfig>,
+
+  /// The directory to persist the cookies of engines, for example: `./cookies`.
+  /// The cookies are kept in memory only if it is not set.
+  pub cookie_dir: Option<String>,
+
+  /// The HTTP client profiles (timeouts, user agents, headers) of engines.
+  pub client_profiles: Option<ClientProfiles>,
+}
+
+/// [napi-rs] The HTTP client profiles of engines.
+///
+/// The profile of an engine overrides the fields set in the `default` one.
+#[napi(object)]
+pub struct ClientProfiles {
+  /// The profile for every engine.
+  pub default: Option<ClientProfile>,
+
+  /// The profile of each engine, for example: `kuwo`.
+  pub engines: Option<HashMap<String, ClientProfile>>,
+}
+
+/// [napi-rs] The settings of an HTTP client.
+#[napi(object)]
+pub struct ClientProfile {
+  /// The timeout (in milliseconds) of connecting to the upstream.
+  pub connect_timeout_ms: Option<u32>,
+
+  /// The timeout (in milliseconds) of a whole request. By default, it is `10000`.
+  pub timeout_ms: Option<u32>,
+
+  /// The longest time (in milliseconds) without receiving anything
+  /// from the upstream, when waiting for the response or reading its body.
+  pub read_timeout_ms: Option<u32>,
+
+  /// The user agents to send. If there are several,
+  /// they are used in rotation for each request.
+  /// They are ignored if `User-Agent` is set in `headers`.
+  pub user_agents: Option<Vec<String>>,
+
+  /// The extra default headers, which override the built-in ones.
+  /// `User-Agent` here takes precedence over `user_agents`.
+  pub headers: Option<HashMap<String, String>>,
+
+  /// Which HTTP version to use.
+  pub http_version: Option<HttpVersion>,
+
+  /// How many idle connections to keep for each host.
+  pub pool_max_idle_per_host: Option<u32>,
+}
+
+/// [napi-rs] The rate limits and concurrency caps of the upstream hosts.
+///
+/// The limit of a host takes precedence over the limit of an engine.
+#[napi(object)]
+pub struct ThrottleConfig {
+  /// The limit of each host pattern, for example: `*.kuwo.cn`.
+  pub hosts: Option<HashMap<String, UpstreamLimit>>,
+
+  /// The limit of the hosts requested by each engine, for example: `qq`.
+  pub engines: Option<HashMap<String, UpstreamLimit>>,
+}
+
+/// [napi-rs] The limit of an upstream host.
+#[napi(object)]
+pub struct UpstreamLimit {
+  /// How many requests can be sent per second on average.
+  pub requests_per_second: Option<f64>,
+
+  /// How many requests can be sent at once after idling. By default, it is `1`.
+  pub burst: Option<u32>,
+
+  /// How many requests can be in flight at the same time.
+  pub max_concurrency: Option<u32>,
+}
+
+/// [napi-rs] The per-engine and per-host proxy settings.
+///
+/// An empty proxy URI means connecting directly.
+#[napi(object)]
+pub struct ProxyConfig {
+  /// The proxy URI of each engine, for example:
+  /// `{ "ytdl": "socks5://127.0.0.1:1080", "kuwo": "" }`.
+  pub engines: Option<HashMap<String, String>>,
+
+  /// The proxy rules matched with the host of requests, in order.
+  pub rules: Option<Vec<ProxyRule>>,
+
+  /// The host patterns to connect directly, for example: `["*.kuwo.cn"]`.
+  pub no_proxy: Option<Vec<String>>,
+}
+
+/// [napi-rs] A rule to use `uri` as the proxy of `hosts`.
+#[napi(object)]
+pub struct ProxyRule {
+  /// The host patterns this rule applies to, for example: `["*.googlevideo.com"]`.
+  pub hosts: Vec<String>,
+
+  /// The proxy URI. Connect directly if it is empty.
+  pub uri: String,
+}
+
+/// [napi-rs] The DNS settings.
+#[napi(object)]
+pub struct DnsConfig {
+  /// The static overrides of hosts to IP addresses,
+  /// for example: `{ "www.kuwo.cn": "203.0.113.10" }`.
+  ///
+  /// The invalid IP addresses are ignored.
+  pub hosts: Option<HashMap<String, String>>,
+
+  /// The DNS server, for example: `8.8.8.8:53` (UDP)
+  /// or `https://1.1.1.1/dns-query` (DNS-over-HTTPS).
+  pub server: Option<String>,
 }
 
 impl From<SearchMode> for unm_types::SearchMode {
@@ -106,6 +284,16 @@ impl From<SearchMode> for unm_types::SearchMode {
   }
 }
 
+impl From<HttpVersion> for unm_types::HttpVersion {
+  fn from(version: HttpVersion) -> Self {
+    match version {
+      HttpVersion::Auto => Self::Auto,
+      HttpVersion::Http1 => Self::Http1,
+      HttpVersion::Http2PriorKnowledge => Self::Http2PriorKnowledge,
+    }
+  }
+}
+
 impl From<Artist> for unm_types::Artist {
   fn from(artist: Artist) -> Self {
     Self::builder().id(artist.id).name(artist.name).build()
@@ -184,11 +372,52 @@ impl From<SongSearchInformation> for unm_types::SongSearchInformation {
   }
 }
 
+impl From<unm_ncm::TrackList> for TrackList {
+  fn from(list: unm_ncm::TrackList) -> Self {
+    Self {
+      id: list.id,
+      name: list.name,
+      tracks: list.tracks.into_iter().map(Into::into).collect(),
+    }
+  }
+}
+
+impl From<unm_ncm::Track> for Track {
+  fn from(track: unm_ncm::Track) -> Self {
+    Self {
+      song: track.song.into(),
+      unavailable: track.unavailable,
+      search_result: None,
+      search_error: None,
+    }
+  }
+}
+
+impl<E: std::fmt::Display> From<std::result::Result<unm_types::SongSearchInformation, E>>
+  for BatchSearchResult
+{
+  fn from(result: std::result::Result<unm_types::SongSearchInformation, E>) -> Self {
+    match result {
+      Ok(result) => Self {
+        result: Some(result.into()),
+        error: None,
+      },
+      Err(e) => Self {
+        result: None,
+        error: Some(e.to_string()),
+      },
+    }
+  }
+}
+
 impl From<unm_types::RetrievedSongInfo> for RetrievedSongInfo {
   fn from(song_information: unm_types::RetrievedSongInfo) -> Self {
     Self {
       source: song_information.source.to_string(),
       url: song_information.url,
+      headers: Some(song_information.headers.into_iter().collect()),
+      bitrate: song_information.bitrate,
+      format: song_information.format,
     }
   }
 }
@@ -198,6 +427,106 @@ impl From<RetrievedSongInfo> for unm_types::RetrievedSongInfo {
     Self::builder()
       .source(song_information.source.into())
       .url(song_information.url)
+      .headers(
+        song_information
+          .headers
+          .map(|headers| headers.into_iter().collect())
+          .unwrap_or_default(),
+      )
+      .bitrate(song_information.bitrate)
+      .format(song_information.format)
+      .build()
+  }
+}
+
+impl From<DnsConfig> for unm_types::DnsConfig {
+  fn from(dns: DnsConfig) -> Self {
+    let hosts = dns
+      .hosts
+      .unwrap_or_default()
+      .into_iter()
+      .filter_map(|(host, ip)| Some((host, ip.parse().ok()?)))
+      .collect();
+
+    Self::builder().hosts(hosts).server(dns.server).build()
+  }
+}
+
+impl From<ProxyConfig> for unm_types::ProxyConfig {
+  fn from(proxy: ProxyConfig) -> Self {
+    let rules = proxy
+      .rules
+      .unwrap_or_default()
+      .into_iter()
+      .map(|rule| {
+        unm_types::ProxyRule::builder()
+          .hosts(rule.hosts)
+          .uri(rule.uri)
+          .build()
+      })
+      .collect();
+
+    Self::builder()
+      .engines(proxy.engines.unwrap_or_default())
+      .rules(rules)
+      .no_proxy(proxy.no_proxy.unwrap_or_default())
+      .build()
+  }
+}
+
+impl From<UpstreamLimit> for unm_types::UpstreamLimit {
+  fn from(limit: UpstreamLimit) -> Self {
+    Self::builder()
+      .requests_per_second(limit.requests_per_second)
+      .burst(limit.burst)
+      .max_concurrency(limit.max_concurrency.map(|v| v as usize))
+      .build()
+  }
+}
+
+impl From<ThrottleConfig> for unm_types::ThrottleConfig {
+  fn from(throttle: ThrottleConfig) -> Self {
+    let convert = |limits: Option<HashMap<String, UpstreamLimit>>| {
+      limits
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(k, v)| (k, v.into()))
+        .collect()
+    };
+
+    Self::builder()
+      .hosts(convert(throttle.hosts))
+      .engines(convert(throttle.engines))
+      .build()
+  }
+}
+
+impl From<ClientProfile> for unm_types::ClientProfile {
+  fn from(profile: ClientProfile) -> Self {
+    Self::builder()
+      .connect_timeout_ms(profile.connect_timeout_ms.map(u64::from))
+      .timeout_ms(profile.timeout_ms.map(u64::from))
+      .read_timeout_ms(profile.read_timeout_ms.map(u64::from))
+      .user_agents(profile.user_agents.unwrap_or_default())
+      .headers(profile.headers.unwrap_or_default().into_iter().collect())
+      .http_version(profile.http_version.map(Into::into))
+      .pool_max_idle_per_host(profile.pool_max_idle_per_host.map(|v| v as usize))
+      .build()
+  }
+}
+
+impl From<ClientProfiles> for unm_types::ClientProfiles {
+  fn from(profiles: ClientProfiles) -> Self {
+    Self::builder()
+      .default(profiles.default.map(Into::into).unwrap_or_default())
+      .engines(
+        profiles
+          .engines
+          .unwrap_or_default()
+          .into_iter()
+          .map(|(k, v)| (k, v.into()))
+          .collect(),
+      )
       .build()
   }
 }
@@ -218,6 +547,32 @@ impl From<Context> for unm_types::Context {
           .unwrap_or(unm_types::SearchMode::FastFirst),
       )
       .config(config.map(ConfigManager::new))
+      .host_mapping(context.host_mapping.unwrap_or_default())
+      .dns(
+        context
+          .dns
+          .map(unm_types::DnsConfig::from)
+          .unwrap_or_default(),
+      )
+      .proxy(
+        context
+          .proxy
+          .map(unm_types::ProxyConfig::from)
+          .unwrap_or_default(),
+      )
+      .throttle(
+        context
+          .throttle
+          .map(unm_types::ThrottleConfig::from)
+          .unwrap_or_default(),
+      )
+      .cookie_dir(context.cookie_dir.map(std::path::PathBuf::from))
+      .client_profiles(
+        context
+          .client_profiles
+          .map(unm_types::ClientProfiles::from)
+          .unwrap_or_default(),
+      )
       .build()
       .unwrap()
   }
@@ -0,0 +1,82 @@
+//! The album API of NetEase Cloud Music.
+
+use log::debug;
+use serde::Deserialize;
+use serde_json::json;
+use unm_types::Context;
+
+use crate::api::{request, ApiFlavor, NcmApiModuleError, NcmApiModuleResult};
+use crate::song::{get_tracks, TrackList};
+
+/// The response of `/api/v1/album/{id}`.
+#[derive(Debug, Deserialize)]
+struct AlbumResponse {
+    album: AlbumDetail,
+    #[serde(default)]
+    songs: Vec<AlbumSong>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlbumDetail {
+    id: i64,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlbumSong {
+    id: i64,
+}
+
+/// Get the tracks of the album with the NCM album `id`.
+pub async fn get_album(id: &str, ctx: &Context) -> NcmApiModuleResult<TrackList> {
+    debug!("Getting the NCM album {id}…");
+
+    let id = id
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| NcmApiModuleError::InvalidAlbumId(id.to_string()))?;
+
+    let response = request::<_, AlbumResponse>(
+        &format!("/api/v1/album/{id}"),
+        &json!({}),
+        ApiFlavor::Weapi,
+        ctx,
+    )
+    .await?;
+
+    // The songs in the album response have no privileges,
+    // so we get them with the song detail API.
+    let ids = response.songs.iter().map(|s| s.id).collect::<Vec<_>>();
+    let tracks = get_tracks(&ids, ctx).await?;
+
+    Ok(TrackList {
+        id: response.album.id.to_string(),
+        name: response.album.name,
+        tracks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use unm_test_utils::with_cassette;
+    use unm_types::Context;
+
+    use super::get_album;
+
+    #[tokio::test]
+    async fn test_get_album() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/album.json");
+
+        with_cassette(fixture, async {
+            let album = get_album("18905", &Context::default()).await.unwrap();
+
+            assert_eq!(album.id, "18905");
+            assert_eq!(album.name, "我很忙");
+            assert_eq!(album.tracks.len(), 2);
+            assert_eq!(album.tracks[0].song.artists[0].name, "周杰伦");
+            assert!(!album.tracks[0].unavailable);
+            assert!(album.tracks[1].unavailable);
+        })
+        .await;
+    }
+}
@@ -0,0 +1,216 @@
+//! The encrypted requests to the NetEase Cloud Music API.
+
+use std::borrow::Cow;
+
+use http::header::{CONTENT_TYPE, COOKIE, REFERER, USER_AGENT};
+use http::{HeaderMap, HeaderValue};
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+use unm_crypto::error::CryptoError;
+use unm_crypto::{eapi, linux, weapi};
+use unm_request::json::Json;
+use unm_request::{build_client, RequestModuleError};
+use unm_types::Context;
+
+use crate::CLIENT_ID;
+
+/// The host of the WEAPI and the Linux API.
+const NCM_HOST: &str = "https://music.163.com";
+
+/// The host of the EAPI.
+const EAPI_HOST: &str = "https://interface3.music.163.com";
+
+/// The user agent of the Linux client, which the Linux API requires.
+const LINUX_USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/60.0.3112.90 Safari/537.36";
+
+/// The encryption of the NCM API requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ApiFlavor {
+    /// The API of the web client: `/weapi/…`.
+    #[default]
+    Weapi,
+    /// The API of the PC and mobile clients: `/eapi/…`.
+    Eapi,
+    /// The API of the Linux client: `/api/linux/forward`.
+    Linux,
+}
+
+impl ApiFlavor {
+    /// Get the flavor specified in the `ncm:api` config.
+    ///
+    /// It can be `weapi`, `eapi` or `linux`. By default, it is `weapi`.
+    pub fn from_context(ctx: &Context) -> Self {
+        let flavor = ctx
+            .config
+            .as_ref()
+            .and_then(|config| config.get_deref(Cow::Borrowed("ncm:api")));
+
+        match flavor {
+            Some("eapi") => Self::Eapi,
+            Some("linux") => Self::Linux,
+            Some("weapi") | None => Self::Weapi,
+            Some(v) => {
+                log::warn!("Unknown NCM API flavor “{v}”. Use “weapi” instead.");
+                Self::Weapi
+            }
+        }
+    }
+}
+
+/// Request the NCM API at `path`, for example, `/api/v3/song/detail`,
+/// with the `params` encrypted in the `flavor`.
+///
+/// The response should have `code` 200; otherwise, it returns
+/// [`NcmApiModuleError::UnexpectedCode`].
+pub async fn request<P: Serialize, R: DeserializeOwned>(
+    path: &str,
+    params: &P,
+    flavor: ApiFlavor,
+    ctx: &Context,
+) -> NcmApiModuleResult<R> {
+    debug!("Requesting the NCM API “{path}” with {flavor:?}…");
+
+    let (url, body, headers) = encrypt_request(path, params, flavor)?;
+
+    let client = build_client(ctx, CLIENT_ID)?;
+    let response = client.post(url).headers(headers).body(body).send().await?;
+    let json = response
+        .json::<Json>()
+        .await
+        .map_err(NcmApiModuleError::ResponseJsonDeserializeFailed)?;
+
+    match json["code"].as_i64() {
+        Some(200) => {}
+        code => return Err(NcmApiModuleError::UnexpectedCode(code)),
+    }
+
+    serde_json::from_value(json).map_err(NcmApiModuleError::JsonDeserializeFailed)
+}
+
+/// Construct the URL, body and headers of the request.
+fn encrypt_request<P: Serialize>(
+    path: &str,
+    params: &P,
+    flavor: ApiFlavor,
+) -> NcmApiModuleResult<(String, String, HeaderMap)> {
+    let mut headers = HeaderMap::with_capacity(4);
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    headers.insert(REFERER, HeaderValue::from_static("https://music.163.com/"));
+
+    let api_path = path
+        .strip_prefix("/api/")
+        .ok_or_else(|| NcmApiModuleError::InvalidPath(path.to_string()))?;
+
+    let (url, body) = match flavor {
+        ApiFlavor::Weapi => {
+            let payload = weapi::construct_weapi_payload(params)?;
+            let field = |key: &str| payload[key].as_str().unwrap_or_default().to_string();
+            let body = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("params", &field("params"))
+                .append_pair("encSecKey", &field("encSecKey"))
+                .finish();
+
+            (format!("{NCM_HOST}/weapi/{api_path}"), body)
+        }
+        ApiFlavor::Eapi => {
+            headers.insert(COOKIE, HeaderValue::from_static("os=pc; appver=2.10.6"));
+            let request = eapi::encrypt_request(path, params)?;
+            (format!("{EAPI_HOST}{}", request.url), request.body)
+        }
+        ApiFlavor::Linux => {
+            headers.insert(USER_AGENT, HeaderValue::from_static(LINUX_USER_AGENT));
+            let request = linux::encrypt_request(&format!("{NCM_HOST}{path}"), params)?;
+            (request.url, request.body)
+        }
+    };
+
+    Ok((url, body, headers))
+}
+
+#[derive(Debug, Error)]
+pub enum NcmApiModuleError {
+    #[error("the path should start with “/api/”: {0}")]
+    InvalidPath(String),
+
+    #[error("failed to encrypt the request: {0}")]
+    EncryptFailed(#[from] CryptoError),
+
+    #[error("failed to deserialize the response JSON: {0}")]
+    ResponseJsonDeserializeFailed(RequestModuleError),
+
+    #[error("failed to deserialize to a structured data: {0}")]
+    JsonDeserializeFailed(serde_json::Error),
+
+    #[error("unexpected code of the response: {0:?}")]
+    UnexpectedCode(Option<i64>),
+
+    #[error("invalid song ID: {0}")]
+    InvalidSongId(String),
+
+    #[error("invalid playlist ID: {0}")]
+    InvalidPlaylistId(String),
+
+    #[error("invalid album ID: {0}")]
+    InvalidAlbumId(String),
+
+    #[error("no such a song: {0}")]
+    SongNotFound(String),
+
+    #[error("something wrong in request module: {0}")]
+    RequestModuleError(#[from] RequestModuleError),
+}
+
+pub type NcmApiModuleResult<T> = Result<T, NcmApiModuleError>;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use unm_types::config::ConfigManagerBuilder;
+    use unm_types::ContextBuilder;
+
+    use super::{encrypt_request, ApiFlavor};
+
+    #[test]
+    fn test_flavor_from_context() {
+        let ctx = |flavor: &str| {
+            ContextBuilder::default()
+                .config(ConfigManagerBuilder::new().set("ncm:api", flavor).build())
+                .build()
+                .unwrap()
+        };
+
+        assert_eq!(ApiFlavor::from_context(&ctx("eapi")), ApiFlavor::Eapi);
+        assert_eq!(ApiFlavor::from_context(&ctx("linux")), ApiFlavor::Linux);
+        assert_eq!(ApiFlavor::from_context(&ctx("unknown")), ApiFlavor::Weapi);
+    }
+
+    #[test]
+    fn test_encrypt_request() {
+        let params = HashMap::from([("ids", "[1]")]);
+
+        let (url, body, _) =
+            encrypt_request("/api/v3/song/detail", &params, ApiFlavor::Weapi).unwrap();
+        assert_eq!(url, "https://music.163.com/weapi/v3/song/detail");
+        assert!(body.starts_with("params=") && body.contains("&encSecKey="));
+
+        let (url, body, _) =
+            encrypt_request("/api/v3/song/detail", &params, ApiFlavor::Eapi).unwrap();
+        assert_eq!(url, "https://interface3.music.163.com/eapi/v3/song/detail");
+        assert!(body.starts_with("params="));
+
+        let (url, body, headers) =
+            encrypt_request("/api/v3/song/detail", &params, ApiFlavor::Linux).unwrap();
+        assert_eq!(url, "https://music.163.com/api/linux/forward");
+        assert!(body.starts_with("eparams="));
+        assert!(headers["user-agent"].to_str().unwrap().contains("Linux"));
+
+        assert!(encrypt_request("/weapi/song", &params, ApiFlavor::Weapi).is_err());
+    }
+}
@@ -0,0 +1,478 @@
+//! Decrypt the `.ncm` files downloaded with the NCM client.
+//!
+//! An `.ncm` file is a container of an MP3 or FLAC stream:
+//!
+//! | Field          | Size                | Description                                       |
+//! | -------------- | ------------------- | ------------------------------------------------- |
+//! | Magic header   | 8 + 2               | `CTENFDAM`, and 2 unused bytes.                   |
+//! | Key            | 4 (LE) + key length | The RC4 key, encrypted with AES-128-ECB.          |
+//! | Metadata       | 4 (LE) + length     | The metadata JSON, encrypted with AES-128-ECB.    |
+//! | CRC32 and gap  | 4 + 5               | Unused.                                           |
+//! | Cover image    | 4 (LE) + size       | The cover image, as is.                           |
+//! | Audio          | The rest            | The audio stream, encrypted with a modified RC4.  |
+//!
+//! [`NcmFile`] parses the header, and decrypts the audio stream
+//! while reading, so large files don't need to fit in memory:
+//!
+//! ```no_run
+//! # fn run() -> unm_ncm::file::NcmFileModuleResult<()> {
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! use unm_ncm::file::NcmFile;
+//!
+//! let ncm = NcmFile::open(BufReader::new(File::open("song.ncm")?))?;
+//! let mut output = File::create(format!("song.{}", ncm.metadata().format))?;
+//! println!("{}", unm_types::Song::from(ncm.metadata().clone()));
+//!
+//! std::io::copy(&mut ncm.into_audio(), &mut output)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, Read};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use thiserror::Error;
+use unm_crypto::aes_128;
+use unm_crypto::error::CryptoError;
+use unm_types::{Album, Artist, Song};
+
+/// The magic header of the `.ncm` files.
+const MAGIC_HEADER: &[u8; 8] = b"CTENFDAM";
+
+/// The AES key of the RC4 key.
+const CORE_KEY: &[u8; 16] = b"hzHRAmso5kInbaxW";
+
+/// The AES key of the metadata.
+const META_KEY: &[u8; 16] = b"#14ljk_!\\]&0U<'(";
+
+/// The prefix of the decrypted RC4 key.
+const KEY_PREFIX: &[u8] = b"neteasecloudmusic";
+
+/// The prefix of the encrypted metadata.
+const META_PREFIX: &[u8] = b"163 key(Don't modify):";
+
+/// A track in an `.ncm` file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[non_exhaustive]
+pub struct NcmFileMetadata {
+    pub music_id: Json,
+    pub music_name: String,
+    /// The artists of this track, in `[name, id]`.
+    pub artist: Vec<(String, Json)>,
+    pub album_id: Json,
+    pub album: String,
+    /// The URL of the album cover.
+    pub album_pic: String,
+    /// The bitrate of the audio (bps).
+    pub bitrate: i64,
+    /// The duration of the audio (ms).
+    pub duration: i64,
+    /// The format of the audio, which is `mp3` or `flac`.
+    pub format: String,
+}
+
+/// The metadata of the DJ program, which wraps the track in `mainMusic`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DjMetadata {
+    main_music: NcmFileMetadata,
+}
+
+/// Convert the ID, which can be a number or a string, to a string.
+fn id_to_string(id: &Json) -> String {
+    match id {
+        Json::String(id) => id.clone(),
+        Json::Null => String::new(),
+        id => id.to_string(),
+    }
+}
+
+impl From<NcmFileMetadata> for Song {
+    fn from(metadata: NcmFileMetadata) -> Self {
+        Song::builder()
+            .id(id_to_string(&metadata.music_id))
+            .name(metadata.music_name)
+            .duration(Some(metadata.duration).filter(|d| *d > 0))
+            .artists(
+                metadata
+                    .artist
+                    .into_iter()
+                    .map(|(name, id)| Artist::builder().id(id_to_string(&id)).name(name).build())
+                    .collect(),
+            )
+            .album(Some(
+                Album::builder()
+                    .id(id_to_string(&metadata.album_id))
+                    .name(metadata.album)
+                    .build(),
+            ))
+            .build()
+    }
+}
+
+/// An `.ncm` file, whose header has been parsed.
+pub struct NcmFile<R> {
+    metadata: NcmFileMetadata,
+    cover: Option<Vec<u8>>,
+    audio: NcmAudioReader<R>,
+}
+
+impl<R: Read> NcmFile<R> {
+    /// Parse the header of the `.ncm` file in `reader`.
+    ///
+    /// The `reader` is left at the start of the audio stream,
+    /// which is decrypted while reading [`NcmFile::into_audio`].
+    pub fn open(mut reader: R) -> NcmFileModuleResult<Self> {
+        let mut magic = [0; 10];
+        reader.read_exact(&mut magic)?;
+        if &magic[..8] != MAGIC_HEADER {
+            return Err(NcmFileModuleError::InvalidMagicHeader);
+        }
+
+        let mut key = read_chunk(&mut reader)?;
+        key.iter_mut().for_each(|b| *b ^= 0x64);
+        let key = aes_128::decrypt_ecb(&key, CORE_KEY)?;
+        let key = key
+            .strip_prefix(KEY_PREFIX)
+            .filter(|key| !key.is_empty())
+            .ok_or(NcmFileModuleError::InvalidKey)?;
+
+        let metadata = read_chunk(&mut reader)?;
+        let metadata = if metadata.is_empty() {
+            // Some files have no metadata.
+            NcmFileMetadata::default()
+        } else {
+            decrypt_metadata(metadata)?
+        };
+
+        // CRC32 and the gap.
+        io::copy(&mut (&mut reader).take(9), &mut io::sink())?;
+
+        let cover = read_chunk(&mut reader)?;
+        let cover = Some(cover).filter(|cover| !cover.is_empty());
+
+        Ok(Self {
+            metadata,
+            cover,
+            audio: NcmAudioReader::new(reader, key),
+        })
+    }
+
+    /// The metadata of the track.
+    pub fn metadata(&self) -> &NcmFileMetadata {
+        &self.metadata
+    }
+
+    /// The cover image embedded in the file, if any.
+    pub fn cover(&self) -> Option<&[u8]> {
+        self.cover.as_deref()
+    }
+
+    /// The reader of the decrypted audio stream.
+    pub fn into_audio(self) -> NcmAudioReader<R> {
+        self.audio
+    }
+}
+
+/// Read a chunk prefixed with its length in u32 (LE).
+fn read_chunk<R: Read>(reader: &mut R) -> NcmFileModuleResult<Vec<u8>> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as u64;
+
+    let mut chunk = Vec::new();
+    reader.take(length).read_to_end(&mut chunk)?;
+    if chunk.len() as u64 != length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    Ok(chunk)
+}
+
+/// Decrypt the metadata, which is `music:{…}`, or `dj:{…}` for the DJ programs.
+fn decrypt_metadata(mut metadata: Vec<u8>) -> NcmFileModuleResult<NcmFileMetadata> {
+    metadata.iter_mut().for_each(|b| *b ^= 0x63);
+
+    let encoded = metadata
+        .strip_prefix(META_PREFIX)
+        .ok_or(NcmFileModuleError::InvalidMetadata)?;
+    let encoded = std::str::from_utf8(encoded).map_err(|_| NcmFileModuleError::InvalidMetadata)?;
+    let decrypted = aes_128::decrypt_ecb(&unm_crypto::base64::decode(encoded)?, META_KEY)?;
+
+    if let Some(json) = decrypted.strip_prefix(b"music:") {
+        Ok(serde_json::from_slice(json)?)
+    } else if let Some(json) = decrypted.strip_prefix(b"dj:") {
+        Ok(serde_json::from_slice::<DjMetadata>(json)?.main_music)
+    } else {
+        Err(NcmFileModuleError::InvalidMetadata)
+    }
+}
+
+/// The reader decrypting the audio stream of an `.ncm` file.
+pub struct NcmAudioReader<R> {
+    inner: R,
+    /// The key stream, which repeats every 256 bytes.
+    key_stream: [u8; 256],
+    /// The offset of the next byte in the audio stream.
+    offset: usize,
+}
+
+impl<R> NcmAudioReader<R> {
+    fn new(inner: R, key: &[u8]) -> Self {
+        // The key-scheduling algorithm of RC4.
+        let mut key_box = [0u8; 256];
+        key_box
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8);
+
+        let mut last = 0u8;
+        for i in 0..256 {
+            let c = key_box[i]
+                .wrapping_add(last)
+                .wrapping_add(key[i % key.len()]);
+            key_box.swap(i, c as usize);
+            last = c;
+        }
+
+        // Unlike RC4, the key box is not shuffled while generating the
+        // key stream, so the key stream repeats every 256 bytes.
+        let mut key_stream = [0u8; 256];
+        for (i, k) in key_stream.iter_mut().enumerate() {
+            let j = (i + 1) & 0xff;
+            let a = key_box[j];
+            let b = key_box[(a as usize + j) & 0xff];
+            *k = key_box[(a.wrapping_add(b)) as usize];
+        }
+
+        Self {
+            inner,
+            key_stream,
+            offset: 0,
+        }
+    }
+
+    /// Get the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for NcmAudioReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        for b in &mut buf[..n] {
+            *b ^= self.key_stream[self.offset & 0xff];
+            self.offset = self.offset.wrapping_add(1);
+        }
+
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum NcmFileModuleError {
+    #[error("failed to read the file: {0}")]
+    IoFailed(#[from] io::Error),
+
+    #[error("this is not an .ncm file")]
+    InvalidMagicHeader,
+
+    #[error("the key of the audio stream is invalid")]
+    InvalidKey,
+
+    #[error("the metadata is invalid")]
+    InvalidMetadata,
+
+    #[error("failed to decrypt: {0}")]
+    DecryptFailed(#[from] CryptoError),
+
+    #[error("failed to deserialize the metadata: {0}")]
+    MetadataDeserializeFailed(#[from] serde_json::Error),
+}
+
+pub type NcmFileModuleResult<T> = Result<T, NcmFileModuleError>;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use serde_json::json;
+    use unm_crypto::aes_128;
+    use unm_types::Song;
+
+    use super::{
+        NcmAudioReader,
+        NcmFile,
+        NcmFileModuleError,
+        CORE_KEY,
+        KEY_PREFIX,
+        MAGIC_HEADER,
+        META_KEY,
+        META_PREFIX,
+    };
+
+    const RC4_KEY: &[u8] = b"1234567890123456789012345678901234567890E7fT49x7dof9OKCgg9cdvhEuezy3iZCL1nFvBFd1T4uSktAJKmwZXsijPbijliionVUXXg9plTbXEclAE9Lb";
+
+    fn chunk(data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// Construct an `.ncm` file with the `metadata` and the `audio`.
+    fn build_ncm(metadata: &str, cover: &[u8], audio: &[u8]) -> Vec<u8> {
+        let mut file = MAGIC_HEADER.to_vec();
+        file.extend_from_slice(&[0, 0]);
+
+        let mut key = aes_128::encrypt_ecb(&[KEY_PREFIX, RC4_KEY].concat(), CORE_KEY).unwrap();
+        key.iter_mut().for_each(|b| *b ^= 0x64);
+        file.extend(chunk(&key));
+
+        let encrypted = aes_128::encrypt_ecb(metadata.as_bytes(), META_KEY).unwrap();
+        let mut meta = [
+            META_PREFIX,
+            unm_crypto::base64::encode(&encrypted).as_bytes(),
+        ]
+        .concat();
+        meta.iter_mut().for_each(|b| *b ^= 0x63);
+        file.extend(chunk(&meta));
+
+        file.extend_from_slice(&[0; 9]);
+        file.extend(chunk(cover));
+
+        // The cipher is XOR with the key stream, so encrypting is decrypting.
+        let mut encrypted = Vec::new();
+        NcmAudioReader::new(Cursor::new(audio), RC4_KEY)
+            .read_to_end(&mut encrypted)
+            .unwrap();
+        file.extend(encrypted);
+
+        file
+    }
+
+    #[test]
+    fn test_decrypt_ncm() {
+        let metadata = json!({
+            "musicId": 185809,
+            "musicName": "青花瓷",
+            "artist": [["周杰伦", 6452]],
+            "albumId": 18905,
+            "album": "我很忙",
+            "albumPic": "http://p4.music.126.net/cover.jpg",
+            "bitrate": 320000,
+            "duration": 239882,
+            "format": "mp3",
+        });
+        let audio = (0..100_000u32).map(|n| (n % 251) as u8).collect::<Vec<_>>();
+        let file = build_ncm(&format!("music:{metadata}"), b"cover", &audio);
+
+        let ncm = NcmFile::open(Cursor::new(file)).unwrap();
+        assert_eq!(ncm.metadata().format, "mp3");
+        assert_eq!(ncm.cover(), Some(&b"cover"[..]));
+
+        let song = Song::from(ncm.metadata().clone());
+        assert_eq!(song.id, "185809");
+        assert_eq!(song.name, "青花瓷");
+        assert_eq!(song.duration, Some(239882));
+        assert_eq!(song.artists[0].id, "6452");
+        assert_eq!(song.artists[0].name, "周杰伦");
+        assert_eq!(song.album.unwrap().name, "我很忙");
+
+        // Read in odd-sized chunks to check the offset of the key stream.
+        let mut reader = ncm.into_audio();
+        let mut decrypted = Vec::new();
+        let mut buf = [0; 333];
+        loop {
+            let n = reader.read(&mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            decrypted.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(decrypted, audio);
+    }
+
+    #[test]
+    fn test_decrypt_dj_ncm() {
+        let metadata = json!({
+            "programId": 1,
+            "mainMusic": { "musicId": "1", "musicName": "DJ", "artist": [], "format": "flac" },
+        });
+        let file = build_ncm(&format!("dj:{metadata}"), b"", b"fLaC");
+
+        let ncm = NcmFile::open(Cursor::new(file)).unwrap();
+        assert_eq!(ncm.metadata().music_name, "DJ");
+        assert_eq!(ncm.metadata().format, "flac");
+        assert!(ncm.cover().is_none());
+
+        let mut audio = Vec::new();
+        ncm.into_audio().read_to_end(&mut audio).unwrap();
+        assert_eq!(audio, b"fLaC");
+    }
+
+    #[test]
+    fn test_invalid_ncm() {
+        assert!(matches!(
+            NcmFile::open(Cursor::new(b"ID3\x03\x00\x00\x00\x00\x00\x00".to_vec())),
+            Err(NcmFileModuleError::InvalidMagicHeader)
+        ));
+
+        let mut file = build_ncm("music:{}", b"", b"");
+        file.truncate(20);
+        assert!(matches!(
+            NcmFile::open(Cursor::new(file)),
+            Err(NcmFileModuleError::IoFailed(_))
+        ));
+    }
+
+    /// `fixtures/sample.ncm` is not produced by this module: it is
+    /// built with a separate port of the ncmdump decoder, so the expected
+    /// values below are known answers rather than a round trip.
+    #[test]
+    fn test_decrypt_sample_ncm() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/sample.ncm");
+        let file = std::fs::read(fixture).unwrap();
+
+        let ncm = NcmFile::open(Cursor::new(file)).unwrap();
+        assert_eq!(ncm.metadata().music_name, "起风了");
+        assert_eq!(ncm.metadata().album, "起风了");
+        assert_eq!(ncm.metadata().bitrate, 320000);
+        assert_eq!(ncm.metadata().format, "mp3");
+        assert_eq!(
+            ncm.cover(),
+            Some(&b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"[..])
+        );
+
+        let song = Song::from(ncm.metadata().clone());
+        assert_eq!(song.id, "1330348068");
+        assert_eq!(song.duration, Some(325868));
+        assert_eq!(song.artists[0].id, "12085016");
+        assert_eq!(song.artists[0].name, "买辣椒也用券");
+
+        let reader = ncm.into_audio();
+        assert_eq!(
+            reader.key_stream[..16],
+            [
+                0xb9, 0xdd, 0x42, 0xc9, 0x26, 0xd0, 0xa6, 0xe5, 0xf4, 0x78, 0xcb, 0xc5, 0x00, 0xa5,
+                0x23, 0x1f,
+            ]
+        );
+
+        let mut audio = Vec::new();
+        reader.take(640).read_to_end(&mut audio).unwrap();
+        assert_eq!(audio.len(), 640);
+        assert_eq!(
+            audio[..14],
+            *b"ID3\x04\x00\x00\x00\x00\x00\x00\xff\xfb\x90\x64"
+        );
+        // The key stream repeats every 256 bytes.
+        assert_eq!(audio[14 + 256], ((256 * 7 + 3) & 0xff) as u8);
+        assert_eq!(audio[639], ((625 * 7 + 3) & 0xff) as u8);
+    }
+}
@@ -0,0 +1,35 @@
+//! UnblockNeteaseMusic (Rust)'s NetEase Cloud Music API client.
+//!
+//! It requests the NetEase Cloud Music (NCM) API with the
+//! encryption in [`unm_crypto`], for example, to look up the
+//! metadata of a song with its track ID:
+//!
+//! ```no_run
+//! # async fn run() -> unm_ncm::api::NcmApiModuleResult<()> {
+//! use unm_types::Context;
+//!
+//! let song = unm_ncm::song::get_song_detail("1436709403", &Context::default()).await?;
+//! println!("{song}");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The playlists and albums can be resolved to the [`Track`]s,
+//! with the unavailable ones marked, by [`get_playlist`] and [`get_album`].
+//!
+//! The `.ncm` files downloaded with the NCM client can be decrypted
+//! to the plain audio, with the metadata, by [`file::NcmFile`].
+
+pub mod album;
+pub mod api;
+pub mod file;
+pub mod playlist;
+pub mod song;
+
+pub use album::get_album;
+pub use playlist::get_playlist;
+pub use song::{NcmMetadataResolver, Track, TrackList};
+
+/// The identifier of the NCM client, which is used as the "engine"
+/// in the [`unm_types::Context`] settings, for example, the proxy.
+pub const CLIENT_ID: &str = "ncm";
@@ -0,0 +1,95 @@
+//! The playlist API of NetEase Cloud Music.
+
+use log::debug;
+use serde::Deserialize;
+use serde_json::json;
+use unm_types::Context;
+
+use crate::api::{request, ApiFlavor, NcmApiModuleError, NcmApiModuleResult};
+use crate::song::{get_tracks, TrackList};
+
+/// The response of `/api/v6/playlist/detail`.
+#[derive(Debug, Deserialize)]
+struct PlaylistDetailResponse {
+    playlist: PlaylistDetail,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistDetail {
+    id: i64,
+    name: String,
+    /// The IDs of all the tracks. Unlike `tracks`, it is never truncated.
+    #[serde(default)]
+    track_ids: Vec<TrackId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackId {
+    id: i64,
+}
+
+/// Get the tracks of the playlist with the NCM playlist `id`.
+pub async fn get_playlist(id: &str, ctx: &Context) -> NcmApiModuleResult<TrackList> {
+    debug!("Getting the NCM playlist {id}…");
+
+    let id = id
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| NcmApiModuleError::InvalidPlaylistId(id.to_string()))?;
+
+    let response = request::<_, PlaylistDetailResponse>(
+        "/api/v6/playlist/detail",
+        &json!({ "id": id, "n": 100000, "s": 8 }),
+        ApiFlavor::Weapi,
+        ctx,
+    )
+    .await?;
+    let playlist = response.playlist;
+
+    let ids = playlist.track_ids.iter().map(|t| t.id).collect::<Vec<_>>();
+    let tracks = get_tracks(&ids, ctx).await?;
+
+    Ok(TrackList {
+        id: playlist.id.to_string(),
+        name: playlist.name,
+        tracks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use unm_test_utils::with_cassette;
+    use unm_types::Context;
+
+    use super::get_playlist;
+
+    #[tokio::test]
+    async fn test_get_playlist() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/playlist.json");
+
+        with_cassette(fixture, async {
+            let playlist = get_playlist("2829883282", &Context::default())
+                .await
+                .unwrap();
+
+            assert_eq!(playlist.name, "周杰伦精选");
+            assert_eq!(
+                playlist
+                    .tracks
+                    .iter()
+                    .map(|t| (t.song.name.as_str(), t.unavailable))
+                    .collect::<Vec<_>>(),
+                [("青花瓷", false), ("晴天", true), ("彩虹", true)]
+            );
+
+            // For VIP only.
+            let privilege = playlist.tracks[1].privilege.as_ref().unwrap();
+            assert_eq!((privilege.fee, privilege.pl), (1, 0));
+            // Greyed out.
+            let privilege = playlist.tracks[2].privilege.as_ref().unwrap();
+            assert!(privilege.st < 0);
+        })
+        .await;
+    }
+}
@@ -0,0 +1,285 @@
+//! The song API of NetEase Cloud Music.
+
+use std::collections::HashMap;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as Json};
+use unm_engine::interface::MetadataResolver;
+use unm_types::{Album, Artist, Context, Song};
+
+use crate::api::{request, ApiFlavor, NcmApiModuleError, NcmApiModuleResult};
+
+/// How many tracks to request in a `/api/v3/song/detail` request.
+const SONG_DETAIL_CHUNK_SIZE: usize = 500;
+
+/// The response of `/api/v3/song/detail`.
+#[derive(Debug, Deserialize)]
+#[non_exhaustive]
+pub struct SongDetailResponse {
+    #[serde(default)]
+    pub songs: Vec<NcmSong>,
+    #[serde(default)]
+    pub privileges: Vec<NcmPrivilege>,
+}
+
+/// The song in the NCM responses.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NcmSong {
+    pub id: i64,
+    pub name: String,
+    /// The artists of this song.
+    #[serde(default)]
+    pub ar: Vec<NcmArtist>,
+    /// The album of this song.
+    pub al: Option<NcmAlbum>,
+    /// The duration of this song (ms).
+    pub dt: Option<i64>,
+}
+
+/// The artist in the NCM responses.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NcmArtist {
+    pub id: i64,
+    /// The name of this artist, which is `null` for the unknown artists.
+    pub name: Option<String>,
+}
+
+/// The album in the NCM responses.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct NcmAlbum {
+    pub id: i64,
+    pub name: Option<String>,
+}
+
+/// The privilege of a song for the current user.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct NcmPrivilege {
+    /// The ID of the song.
+    pub id: i64,
+    /// The fee type: `0`/`8` for free, `1` for VIP, `4` for the paid album.
+    pub fee: i64,
+    /// The status, which is negative if the song is greyed out.
+    pub st: i64,
+    /// The bitrate the current user can play, which is `0` if unplayable.
+    pub pl: i64,
+    /// The bitrate the current user can download.
+    pub dl: i64,
+    /// The bitrate of the free trial.
+    pub fl: i64,
+}
+
+impl NcmPrivilege {
+    /// Whether the song is playable for the current user.
+    pub fn is_playable(&self) -> bool {
+        self.st >= 0 && self.pl > 0
+    }
+}
+
+/// A track in a playlist or an album, with its availability.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Track {
+    /// The metadata of this track.
+    pub song: Song,
+    /// Whether this track is unavailable on NCM,
+    /// for example, greyed out or for VIP only.
+    pub unavailable: bool,
+    /// The privilege of this track, if NCM returns it.
+    pub privilege: Option<NcmPrivilege>,
+}
+
+/// The tracks of a playlist or an album.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TrackList {
+    /// The ID of the playlist or album.
+    pub id: String,
+    /// The name of the playlist or album.
+    pub name: String,
+    /// The tracks, in the order of the playlist or album.
+    pub tracks: Vec<Track>,
+}
+
+impl From<NcmSong> for Song {
+    fn from(song: NcmSong) -> Self {
+        Song::builder()
+            .id(song.id.to_string())
+            .name(song.name)
+            .duration(song.dt)
+            .artists(
+                song.ar
+                    .into_iter()
+                    .filter_map(|artist| {
+                        Some(
+                            Artist::builder()
+                                .id(artist.id.to_string())
+                                .name(artist.name?)
+                                .build(),
+                        )
+                    })
+                    .collect(),
+            )
+            .album(song.al.and_then(|album| {
+                Some(
+                    Album::builder()
+                        .id(album.id.to_string())
+                        .name(album.name?)
+                        .build(),
+                )
+            }))
+            .build()
+    }
+}
+
+/// Get the details of the songs with the NCM track `ids`.
+///
+/// The songs which are not found are omitted.
+pub async fn get_song_details(ids: &[&str], ctx: &Context) -> NcmApiModuleResult<Vec<Song>> {
+    debug!("Getting the details of the NCM songs {ids:?}…");
+
+    let ids = ids
+        .iter()
+        .map(|id| {
+            id.trim()
+                .parse::<i64>()
+                .map_err(|_| NcmApiModuleError::InvalidSongId(id.to_string()))
+        })
+        .collect::<NcmApiModuleResult<Vec<_>>>()?;
+
+    let response = request::<_, SongDetailResponse>(
+        "/api/v3/song/detail",
+        &song_detail_params(&ids),
+        ApiFlavor::from_context(ctx),
+        ctx,
+    )
+    .await?;
+
+    Ok(response.songs.into_iter().map(Song::from).collect())
+}
+
+/// The parameters of `/api/v3/song/detail` to get the songs of `ids`.
+fn song_detail_params(ids: &[i64]) -> Json {
+    json!({
+        "c": json!(ids.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>()).to_string(),
+        "ids": json!(ids).to_string(),
+    })
+}
+
+/// Get the tracks with the NCM track `ids`, requested in chunks
+/// with WEAPI, and mark the unavailable ones.
+///
+/// The tracks which are not found are omitted.
+pub async fn get_tracks(ids: &[i64], ctx: &Context) -> NcmApiModuleResult<Vec<Track>> {
+    let mut tracks = Vec::with_capacity(ids.len());
+
+    for chunk in ids.chunks(SONG_DETAIL_CHUNK_SIZE) {
+        debug!("Getting the details of {} NCM tracks…", chunk.len());
+
+        let response = request::<_, SongDetailResponse>(
+            "/api/v3/song/detail",
+            &song_detail_params(chunk),
+            ApiFlavor::Weapi,
+            ctx,
+        )
+        .await?;
+
+        let mut privileges = response
+            .privileges
+            .into_iter()
+            .map(|privilege| (privilege.id, privilege))
+            .collect::<HashMap<_, _>>();
+
+        tracks.extend(response.songs.into_iter().map(|song| {
+            let privilege = privileges.remove(&song.id);
+
+            Track {
+                unavailable: !privilege.as_ref().map_or(false, NcmPrivilege::is_playable),
+                privilege,
+                song: song.into(),
+            }
+        }));
+    }
+
+    Ok(tracks)
+}
+
+/// Get the details of the song with the NCM track `id`.
+pub async fn get_song_detail(id: &str, ctx: &Context) -> NcmApiModuleResult<Song> {
+    get_song_details(&[id], ctx)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| NcmApiModuleError::SongNotFound(id.to_string()))
+}
+
+/// The [`MetadataResolver`] looking up the song detail API of NCM.
+///
+/// The encryption of the requests can be configured with
+/// the `ncm:api` config. See [`ApiFlavor::from_context`].
+pub struct NcmMetadataResolver;
+
+#[async_trait::async_trait]
+impl MetadataResolver for NcmMetadataResolver {
+    async fn resolve<'a>(&self, id: &'a str, ctx: &'a Context) -> anyhow::Result<Song> {
+        Ok(get_song_detail(id, ctx).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unm_engine::interface::MetadataResolver;
+    use unm_test_utils::with_cassette;
+    use unm_types::config::ConfigManagerBuilder;
+    use unm_types::{Context, ContextBuilder};
+
+    use super::{get_song_detail, NcmMetadataResolver};
+    use crate::api::NcmApiModuleError;
+
+    #[tokio::test]
+    async fn test_resolve_metadata() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/song_detail.json");
+
+        with_cassette(fixture, async {
+            for flavor in ["weapi", "eapi", "linux"] {
+                let ctx = ContextBuilder::default()
+                    .config(ConfigManagerBuilder::new().set("ncm:api", flavor).build())
+                    .build()
+                    .unwrap();
+                let song = NcmMetadataResolver.resolve("185809", &ctx).await.unwrap();
+
+                assert_eq!(song.id, "185809");
+                assert_eq!(song.name, "青花瓷");
+                assert_eq!(song.duration, Some(239882));
+                assert_eq!(song.artists[0].id, "6452");
+                assert_eq!(song.artists[0].name, "周杰伦");
+                assert_eq!(song.album.unwrap().name, "我很忙");
+            }
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_song_not_found() {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/song_not_found.json");
+
+        with_cassette(fixture, async {
+            let ctx = Context::default();
+
+            assert!(matches!(
+                get_song_detail("1", &ctx).await,
+                Err(NcmApiModuleError::SongNotFound(_))
+            ));
+            assert!(matches!(
+                get_song_detail("abc", &ctx).await,
+                Err(NcmApiModuleError::InvalidSongId(_))
+            ));
+        })
+        .await;
+    }
+}
@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::fs;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{info, instrument};
+use unm_types::Context;
+
+pub trait ExternalConfigReader: DeserializeOwned {
+    fn read_toml(file_path: Cow<'static, str>) -> anyhow::Result<Self>;
+}
+
+#[derive(Deserialize)]
+#[non_exhaustive]
+pub struct ProxyConfigTomlStructure {
+    pub context: Context,
+    /// The engines to search the unavailable songs with.
+    ///
+    /// If not specified, we use all the supported engines.
+    #[serde(default)]
+    pub engines: Option<Vec<String>>,
+    /// The hosts besides the NCM API to forward the requests
+    /// and tunnel the connections to, for example, `*.music.126.net`.
+    ///
+    /// `*` allows every host, which makes this an open proxy.
+    /// By default, only the NCM API hosts are allowed.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    #[serde(default)]
+    pub mitm: MitmConfig,
+    #[serde(default)]
+    pub ungrey: UngreyConfig,
+}
+
+/// The configuration of un-greying the unavailable songs
+/// in the playlists, albums and song details.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UngreyConfig {
+    /// Whether to mark the unavailable songs as playable.
+    ///
+    /// By default, it is `true`.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Whether to search the unavailable songs with the engines
+    /// first, and only un-grey the ones found.
+    ///
+    /// By default, it is `false`.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for UngreyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            confirm: false,
+        }
+    }
+}
+
+/// The configuration of intercepting the HTTPS connections.
+#[derive(Debug, Deserialize)]
+pub struct MitmConfig {
+    /// Whether to decrypt the HTTPS connections to the NCM API.
+    ///
+    /// By default, it is `false`.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The directory to persist the root CA and the leaf certificates.
+    ///
+    /// By default, it is `./ca`.
+    #[serde(default = "default_ca_dir")]
+    pub ca_dir: String,
+}
+
+fn default_ca_dir() -> String {
+    "./ca".to_string()
+}
+
+impl Default for MitmConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ca_dir: default_ca_dir(),
+        }
+    }
+}
+
+impl ExternalConfigReader for ProxyConfigTomlStructure {
+    #[instrument]
+    fn read_toml(file_path: Cow<'static, str>) -> anyhow::Result<Self> {
+        info!("Reading configuration from TOML file: {}", file_path);
+
+        let file_content = fs::read_to_string(&*file_path)?;
+        let config = toml::from_str::<'_, Self>(&file_content)?;
+
+        Ok(config)
+    }
+}
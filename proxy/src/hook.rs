@@ -0,0 +1,297 @@
+//! Patch the NCM API responses with the songs found by the [`Executor`].
+
+use std::borrow::Cow;
+
+use futures::StreamExt;
+use http::header::CONTENT_LENGTH;
+use serde_json::{json, Value as Json};
+use tracing::{debug, info, warn};
+use unm_api_utils::retrieve::determine_header;
+use unm_engine::executor::Executor;
+use unm_types::{Context, RetrievedSongInfo};
+
+use crate::config_reader::UngreyConfig;
+use crate::packet::NcmResponse;
+use crate::ungrey;
+
+/// How many unavailable songs to resolve at the same time.
+const RESOLVE_CONCURRENCY: usize = 4;
+
+/// The state shared with the hooks.
+pub struct HookState {
+    pub executor: Executor,
+    pub context: Context,
+    /// The engines to search with.
+    pub engines: Vec<Cow<'static, str>>,
+    pub ungrey: UngreyConfig,
+}
+
+/// Whether the API `path` is the one to get the player URLs.
+fn is_player_url(path: &str) -> bool {
+    path.starts_with("/api/song/enhance/player/url")
+}
+
+/// Whether we should hook the response of the API `path`.
+pub fn should_hook(path: &str, state: &HookState) -> bool {
+    is_player_url(path) || (state.ungrey.enabled && ungrey::should_ungrey(path))
+}
+
+/// Patch the response of the API `path`.
+pub async fn patch(path: &str, response: &mut NcmResponse, state: &HookState) {
+    if is_player_url(path) {
+        patch_player_url(response, state).await;
+    } else if state.ungrey.enabled && ungrey::should_ungrey(path) {
+        ungrey::patch(response, state).await;
+    }
+}
+
+/// Replace the unavailable songs in the response of
+/// `/api/song/enhance/player/url` with the ones found by the executor.
+pub async fn patch_player_url(response: &mut NcmResponse, state: &HookState) {
+    let Some(data) = response.body["data"].as_array_mut() else {
+        return;
+    };
+
+    let unavailable = data
+        .iter_mut()
+        .filter(|item| is_unavailable(item))
+        .collect::<Vec<_>>();
+    info!("Resolving {} unavailable songs…", unavailable.len());
+
+    futures::stream::iter(unavailable)
+        .for_each_concurrent(RESOLVE_CONCURRENCY, |item| async move {
+            let Some(id) = item["id"].as_i64() else {
+                return;
+            };
+
+            match resolve(&id.to_string(), state).await {
+                Ok(song) => patch_item(item, &song),
+                Err(e) => warn!("Failed to resolve the song {id}: {e}"),
+            }
+        })
+        .await;
+}
+
+/// Whether the `item` in the `data` of the player URL response is unavailable,
+/// which is not playable or only a free trial.
+fn is_unavailable(item: &Json) -> bool {
+    item["code"].as_i64() != Some(200) || item["url"].is_null() || !item["freeTrialInfo"].is_null()
+}
+
+/// The formats of the lossless audio.
+const LOSSLESS_FORMATS: &[&str] = &["flac", "ape", "wav"];
+
+/// The formats of the audio we recognize from the URL extension.
+const AUDIO_FORMATS: &[&str] = &["mp3", "flac", "ape", "wav", "m4a", "aac", "ogg", "webm"];
+
+/// The bitrate NCM uses for the lossless audio.
+const LOSSLESS_BITRATE: u32 = 999000;
+
+/// The bitrate we report if neither the engine nor the URL tells.
+const DEFAULT_BITRATE: u32 = 128000;
+
+/// The song resolved by the executor.
+struct ResolvedSong {
+    url: String,
+    size: Option<u64>,
+    /// The bitrate (bps).
+    bitrate: u32,
+    /// The format (file extension), for example: `mp3`.
+    format: String,
+}
+
+/// Search the song with the NCM track `id`, and retrieve its URL.
+async fn resolve(id: &str, state: &HookState) -> anyhow::Result<ResolvedSong> {
+    let search_result = state
+        .executor
+        .search_by_id(&state.engines, id, &state.context)
+        .await?;
+    let retrieved = state
+        .executor
+        .retrieve(&search_result, &state.context)
+        .await?;
+    debug!(
+        "Resolved the song {id} from {}: {}",
+        retrieved.source, retrieved.url
+    );
+
+    let size = content_length(&retrieved, &state.context).await;
+    let (bitrate, format) = bitrate_and_format(&retrieved);
+
+    Ok(ResolvedSong {
+        url: retrieved.url,
+        size,
+        bitrate,
+        format,
+    })
+}
+
+/// Get the bitrate and format of the `retrieved` song from the engine,
+/// or guess them with the extension of its URL.
+fn bitrate_and_format(retrieved: &RetrievedSongInfo) -> (u32, String) {
+    let format = retrieved
+        .format
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .or_else(|| {
+            let url = url::Url::parse(&retrieved.url).ok()?;
+            let (_, extension) = url.path().rsplit_once('.')?;
+            let extension = extension.to_ascii_lowercase();
+
+            AUDIO_FORMATS
+                .contains(&extension.as_str())
+                .then_some(extension)
+        })
+        .unwrap_or_else(|| "mp3".to_string());
+
+    let bitrate = retrieved
+        .bitrate
+        .unwrap_or(if LOSSLESS_FORMATS.contains(&format.as_str()) {
+            LOSSLESS_BITRATE
+        } else {
+            DEFAULT_BITRATE
+        });
+
+    (bitrate, format)
+}
+
+/// Get the size of the `retrieved` song with a `HEAD` request,
+/// with the headers that its engine requires.
+async fn content_length(retrieved: &RetrievedSongInfo, ctx: &Context) -> Option<u64> {
+    let client = unm_request::build_client(ctx, &retrieved.source).ok()?;
+    let response = client
+        .request(http::Method::HEAD, &retrieved.url)
+        .headers(determine_header(retrieved))
+        .send()
+        .await
+        .ok()?;
+
+    response
+        .headers()
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Rewrite the `item` with the resolved `song`.
+fn patch_item(item: &mut Json, song: &ResolvedSong) {
+    item["url"] = json!(song.url);
+    // Keep the original size if we don't know it, rather than
+    // claiming it is empty.
+    if let Some(size) = song.size {
+        item["size"] = json!(size);
+    }
+    item["br"] = json!(song.bitrate);
+    item["type"] = json!(song.format);
+    item["md5"] = json!(format!("{:x}", md5::compute(&song.url)));
+    item["code"] = json!(200);
+    item["freeTrialInfo"] = Json::Null;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use unm_types::RetrievedSongInfo;
+
+    use super::{bitrate_and_format, is_unavailable, patch_item, ResolvedSong};
+
+    #[test]
+    fn test_patch_item() {
+        let mut item = json!({
+            "id": 185809,
+            "url": null,
+            "br": 0,
+            "size": 0,
+            "md5": null,
+            "code": 404,
+            "type": null,
+            "freeTrialInfo": null,
+        });
+        assert!(is_unavailable(&item));
+
+        patch_item(
+            &mut item,
+            &ResolvedSong {
+                url: "http://example.com/185809.flac".to_string(),
+                size: Some(1024),
+                bitrate: 999000,
+                format: "flac".to_string(),
+            },
+        );
+        assert!(!is_unavailable(&item));
+        assert_eq!(item["url"], "http://example.com/185809.flac");
+        assert_eq!(item["size"], 1024);
+        assert_eq!(item["br"], 999000);
+        assert_eq!(item["type"], "flac");
+        assert_eq!(item["md5"].as_str().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_patch_item_unknown_size() {
+        let mut item = json!({
+            "id": 185809,
+            "url": "http://example.com/trial.mp3",
+            "size": 480000,
+            "code": 200,
+            "freeTrialInfo": { "start": 0, "end": 30 },
+        });
+
+        patch_item(
+            &mut item,
+            &ResolvedSong {
+                url: "http://example.com/185809.mp3".to_string(),
+                size: None,
+                bitrate: 128000,
+                format: "mp3".to_string(),
+            },
+        );
+        assert!(!is_unavailable(&item));
+        assert_eq!(item["size"], 480000);
+    }
+
+    #[test]
+    fn test_bitrate_and_format() {
+        let retrieved = |url: &str, bitrate: Option<u32>, format: Option<&str>| {
+            RetrievedSongInfo::builder()
+                .source("kuwo".into())
+                .url(url.to_string())
+                .bitrate(bitrate)
+                .format(format.map(ToString::to_string))
+                .build()
+        };
+
+        // From the engine.
+        assert_eq!(
+            bitrate_and_format(&retrieved(
+                "https://rr2---sn-oguelnzz.googlevideo.com/videoplayback",
+                Some(141232),
+                Some("webm")
+            )),
+            (141232, "webm".to_string())
+        );
+        assert_eq!(
+            bitrate_and_format(&retrieved(
+                "http://example.com/3866545620.flac",
+                Some(2000000),
+                Some("flac")
+            )),
+            (2000000, "flac".to_string())
+        );
+
+        // From the URL extension.
+        assert_eq!(
+            bitrate_and_format(&retrieved("http://example.com/a.FLAC?sig=1", None, None)),
+            (999000, "flac".to_string())
+        );
+        assert_eq!(
+            bitrate_and_format(&retrieved("http://example.com/a.m4a", None, None)),
+            (128000, "m4a".to_string())
+        );
+        assert_eq!(
+            bitrate_and_format(&retrieved("http://example.com/play?id=1", None, None)),
+            (128000, "mp3".to_string())
+        );
+    }
+}
@@ -0,0 +1,117 @@
+pub(crate) mod config_reader;
+pub(crate) mod hook;
+pub(crate) mod packet;
+pub(crate) mod proxy;
+pub(crate) mod ungrey;
+
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::Server;
+use mimalloc::MiMalloc;
+use tracing::{debug, info, warn};
+use unm_api_utils::cert::{CertificateAuthority, CA_CERT_FILE};
+use unm_types::ContextBuilder;
+
+use crate::config_reader::{ExternalConfigReader, ProxyConfigTomlStructure};
+use crate::hook::HookState;
+use crate::proxy::Proxy;
+
+#[global_allocator]
+static GLOBAL: MiMalloc = MiMalloc;
+
+#[tokio::main]
+async fn main() {
+    // initialize tracing
+    tracing_subscriber::fmt::init();
+
+    info!("Reading configuration…");
+    let config = ProxyConfigTomlStructure::read_toml("./config.toml".into())
+        .map_err(|e| {
+            warn!("Failed to read `config.toml` because of {e}");
+            warn!("Use default context built in this proxy.");
+        })
+        .ok();
+
+    let executor = unm_api_utils::executor::build_full_executor();
+    let engines = config
+        .as_ref()
+        .and_then(|v| v.engines.clone())
+        .map(|engines| engines.into_iter().map(Cow::from).collect())
+        .unwrap_or_else(|| {
+            executor
+                .list()
+                .into_iter()
+                .map(|e| e.to_string().into())
+                .collect()
+        });
+    debug!("Will search with the engines: {engines:?}");
+
+    let mitm = config.as_ref().map(|v| &v.mitm);
+    let ca = match mitm {
+        Some(mitm) if mitm.enabled => {
+            info!("Loading the root CA for intercepting HTTPS connections…");
+            let ca = CertificateAuthority::load_or_generate(&mitm.ca_dir)
+                .expect("Failed to load or generate the root CA");
+            info!("Trust the root CA at `GET /{CA_CERT_FILE}` of this proxy on your clients.");
+            Some(ca)
+        }
+        _ => None,
+    };
+
+    let ungrey = config
+        .as_ref()
+        .map(|v| v.ungrey.clone())
+        .unwrap_or_default();
+    debug!("UngreyConfig: {ungrey:?}");
+
+    let allowed_hosts = config
+        .as_ref()
+        .map(|v| v.allowed_hosts.clone())
+        .unwrap_or_default();
+    debug!("Will forward the requests to the NCM API and: {allowed_hosts:?}");
+
+    let context = config.map(|v| v.context).unwrap_or_else(|| {
+        ContextBuilder::default()
+            .build()
+            .expect("Failed to build default context")
+    });
+
+    let proxy = Arc::new(
+        Proxy::new(
+            HookState {
+                executor,
+                context,
+                engines,
+                ungrey,
+            },
+            ca,
+            allowed_hosts,
+        )
+        .expect("Failed to build the client to the upstream"),
+    );
+
+    let serve_address =
+        std::env::var("SERVE_ADDRESS").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
+    debug!("Will listen on: {serve_address}");
+
+    let make_service = make_service_fn(move |_| {
+        let proxy = proxy.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| proxy.clone().handle(request))) }
+    });
+
+    let addr: SocketAddr = serve_address.parse().expect("failed to parse address");
+    info!("listening on {}", addr);
+    Server::bind(&addr)
+        .http1_preserve_header_case(true)
+        .http1_title_case_headers(true)
+        .serve(make_service)
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await
+        .unwrap();
+}
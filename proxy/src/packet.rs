@@ -0,0 +1,218 @@
+//! Decode and encode the packets between the NCM client and the NCM API.
+
+use serde_json::Value as Json;
+use thiserror::Error;
+use unm_crypto::error::CryptoError;
+use unm_crypto::{eapi, linux};
+
+/// The domain of the NCM API.
+const NCM_DOMAIN: &str = "music.163.com";
+
+/// Whether the `host` is one of the NCM API hosts which we should
+/// intercept: `music.163.com` and `interface*.music.163.com`,
+/// for example, `interface3.music.163.com`.
+pub fn is_ncm_host(host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    if host == NCM_DOMAIN {
+        return true;
+    }
+
+    host.strip_suffix(NCM_DOMAIN)
+        .and_then(|subdomain| subdomain.strip_suffix('.'))
+        .and_then(|subdomain| subdomain.strip_prefix("interface"))
+        .is_some_and(|suffix| {
+            suffix
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// The encryption of a request from the NCM client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encryption {
+    /// The EAPI, which is used by the PC and mobile clients.
+    Eapi,
+    /// The Linux API, which wraps the request in `/api/linux/forward`.
+    Linux,
+    /// The WEAPI or the plain API, which we can't (or don't need to) decrypt.
+    None,
+}
+
+/// The request to the NCM API.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NcmRequest {
+    /// The path of the API, for example, `/api/song/enhance/player/url`.
+    pub path: String,
+    /// The decrypted parameters, or `Json::Null` if it can't be decrypted.
+    pub params: Json,
+    pub encryption: Encryption,
+}
+
+impl NcmRequest {
+    /// Decode the request to the `uri_path` with the form `body`.
+    pub fn decode(uri_path: &str, body: &[u8]) -> PacketModuleResult<Self> {
+        if uri_path.starts_with("/eapi/") {
+            let request = eapi::decrypt_request(&String::from_utf8_lossy(body))?;
+
+            Ok(Self {
+                path: request.url,
+                params: request.params,
+                encryption: Encryption::Eapi,
+            })
+        } else if uri_path == "/api/linux/forward" {
+            let request = linux::decrypt_request(&String::from_utf8_lossy(body))?;
+            let path = url::Url::parse(&request.url)
+                .map(|url| url.path().to_string())
+                .map_err(|_| PacketModuleError::InvalidPacket)?;
+
+            Ok(Self {
+                path,
+                params: request.params,
+                encryption: Encryption::Linux,
+            })
+        } else {
+            let path = match uri_path.strip_prefix("/weapi/") {
+                Some(path) => format!("/api/{path}"),
+                None => uri_path.to_string(),
+            };
+
+            Ok(Self {
+                path,
+                params: Json::Null,
+                encryption: Encryption::None,
+            })
+        }
+    }
+}
+
+/// The response from the NCM API.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NcmResponse {
+    pub body: Json,
+    /// Whether the response is encrypted with EAPI,
+    /// which the client requests with the `e_r` parameter.
+    pub encrypted: bool,
+}
+
+impl NcmResponse {
+    /// Decode the response `body` to the `request`.
+    pub fn decode(request: &NcmRequest, body: &[u8]) -> PacketModuleResult<Self> {
+        if let Ok(body) = serde_json::from_slice(body) {
+            return Ok(Self {
+                body,
+                encrypted: false,
+            });
+        }
+
+        if request.encryption != Encryption::Eapi {
+            return Err(PacketModuleError::InvalidPacket);
+        }
+
+        Ok(Self {
+            body: eapi::decrypt_response(body)?,
+            encrypted: true,
+        })
+    }
+
+    /// Encode the response to the bytes to send to the client.
+    pub fn encode(&self) -> PacketModuleResult<Vec<u8>> {
+        if self.encrypted {
+            Ok(eapi::encrypt_response(&self.body)?)
+        } else {
+            Ok(serde_json::to_vec(&self.body)?)
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PacketModuleError {
+    #[error("failed to decrypt or encrypt the packet: {0}")]
+    CryptoFailed(#[from] CryptoError),
+
+    #[error("failed to parse the JSON in the packet: {0}")]
+    JsonFailed(#[from] serde_json::Error),
+
+    #[error("the packet is not in the expected format")]
+    InvalidPacket,
+}
+
+pub type PacketModuleResult<T> = Result<T, PacketModuleError>;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use unm_crypto::{eapi, linux};
+
+    use super::{is_ncm_host, Encryption, NcmRequest, NcmResponse};
+
+    #[test]
+    fn test_is_ncm_host() {
+        for host in [
+            "music.163.com",
+            "interface.music.163.com",
+            "interface3.music.163.com",
+            "Interface3.Music.163.com",
+            "interfacepc.music.163.com",
+        ] {
+            assert!(is_ncm_host(host), "{host}");
+        }
+
+        for host in [
+            "163.com",
+            "m701.music.126.net",
+            "y.music.163.com",
+            "interface.music.163.com.invalid",
+            "evilmusic.163.com",
+            "a.interface.music.163.com",
+        ] {
+            assert!(!is_ncm_host(host), "{host}");
+        }
+    }
+
+    #[test]
+    fn test_decode_eapi_request() {
+        let params = json!({ "ids": "[185809]", "br": 999000 });
+        let request = eapi::encrypt_request("/api/song/enhance/player/url", &params).unwrap();
+
+        let decoded = NcmRequest::decode(&request.url, request.body.as_bytes()).unwrap();
+        assert_eq!(decoded.path, "/api/song/enhance/player/url");
+        assert_eq!(decoded.params, params);
+        assert_eq!(decoded.encryption, Encryption::Eapi);
+    }
+
+    #[test]
+    fn test_decode_linux_request() {
+        let params = json!({ "ids": "[185809]" });
+        let request =
+            linux::encrypt_request("https://music.163.com/api/song/enhance/player/url", &params)
+                .unwrap();
+
+        let decoded = NcmRequest::decode("/api/linux/forward", request.body.as_bytes()).unwrap();
+        assert_eq!(decoded.path, "/api/song/enhance/player/url");
+        assert_eq!(decoded.params, params);
+        assert_eq!(decoded.encryption, Encryption::Linux);
+    }
+
+    #[test]
+    fn test_decode_weapi_request() {
+        let decoded = NcmRequest::decode("/weapi/song/enhance/player/url", b"params=").unwrap();
+        assert_eq!(decoded.path, "/api/song/enhance/player/url");
+        assert_eq!(decoded.encryption, Encryption::None);
+    }
+
+    #[test]
+    fn test_encrypted_response_roundtrip() {
+        let request = NcmRequest {
+            path: "/api/song/enhance/player/url".to_string(),
+            params: json!({ "e_r": true }),
+            encryption: Encryption::Eapi,
+        };
+        let body = json!({ "code": 200, "data": [] });
+        let encrypted = eapi::encrypt(body.to_string().as_bytes()).unwrap();
+
+        let response = NcmResponse::decode(&request, &encrypted).unwrap();
+        assert!(response.encrypted);
+        assert_eq!(response.body, body);
+        assert_eq!(response.encode().unwrap(), encrypted);
+    }
+}
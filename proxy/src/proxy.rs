@@ -0,0 +1,411 @@
+//! The HTTP proxy server forwarding the requests of the NCM client.
+
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use http::header::{
+    HeaderName,
+    ACCEPT_ENCODING,
+    CONNECTION,
+    CONTENT_ENCODING,
+    CONTENT_LENGTH,
+    CONTENT_TYPE,
+    HOST,
+    PROXY_AUTHORIZATION,
+    TE,
+    TRAILER,
+    TRANSFER_ENCODING,
+    UPGRADE,
+};
+use http::uri::{Authority, Scheme};
+use http::{HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use hyper::body::Bytes;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::upgrade::Upgraded;
+use hyper::Body;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+use unm_api_utils::cert::{CertificateAuthority, CA_CERT_FILE};
+use unm_request::proxy::proxy_uri_for;
+use unm_request::{build_forward_client, dns, translate_host, Client, RequestModuleError};
+use unm_types::Context;
+
+use crate::hook::{self, HookState};
+use crate::packet::{is_ncm_host, NcmRequest, NcmResponse};
+
+/// The headers which are only meaningful for a single connection.
+const HOP_BY_HOP_HEADERS: &[HeaderName] = &[
+    CONNECTION,
+    PROXY_AUTHORIZATION,
+    TE,
+    TRAILER,
+    TRANSFER_ENCODING,
+    UPGRADE,
+];
+
+/// The engine whose proxy, client profile and limits
+/// in the context apply to the requests to the upstream.
+const UPSTREAM_ENGINE: &str = "ncm";
+
+/// The longest response head we accept from the upstream proxy of a tunnel.
+const MAX_TUNNEL_HEAD: usize = 8192;
+
+/// The proxy forwarding the requests to the upstream.
+pub struct Proxy {
+    client: Client,
+    state: HookState,
+    /// The root CA to intercept the HTTPS connections to the NCM API.
+    ///
+    /// If it is `None`, the HTTPS connections are tunneled as is.
+    ca: Option<Arc<CertificateAuthority>>,
+    /// The hosts besides the NCM API to forward the requests to.
+    allowed_hosts: Vec<String>,
+}
+
+impl Proxy {
+    pub fn new(
+        state: HookState,
+        ca: Option<CertificateAuthority>,
+        allowed_hosts: Vec<String>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: build_forward_client(&state.context, UPSTREAM_ENGINE)?,
+            state,
+            ca: ca.map(Arc::new),
+            allowed_hosts,
+        })
+    }
+
+    /// Whether we forward the requests to `host`, which is one of
+    /// the NCM API hosts, or matches one of the `allowed_hosts`.
+    fn is_allowed_host(&self, host: &str) -> bool {
+        is_ncm_host(host)
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|pattern| match_host_pattern(pattern, host))
+    }
+
+    /// Handle a request from the client.
+    pub async fn handle(
+        self: Arc<Self>,
+        request: Request<Body>,
+    ) -> Result<Response<Body>, Infallible> {
+        let result = if request.method() == Method::CONNECT {
+            self.tunnel(request)
+        } else if request.uri().authority().is_none() {
+            self.serve_local(request)
+        } else {
+            self.forward(request).await
+        };
+
+        Ok(result.unwrap_or_else(|e| {
+            warn!("Failed to proxy the request: {e}");
+            let mut response = Response::new(Body::from(e.to_string()));
+            *response.status_mut() = StatusCode::BAD_GATEWAY;
+            response
+        }))
+    }
+
+    /// Serve the requests to the proxy itself, which are not
+    /// in the absolute form, for example, `GET /ca.crt`.
+    fn serve_local(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        let (status, body) = match (request.method(), request.uri().path(), &self.ca) {
+            (&Method::GET, path, Some(ca)) if path == format!("/{CA_CERT_FILE}") => {
+                let mut response = Response::new(Body::from(ca.cert_pem()?));
+                response.headers_mut().insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("application/x-x509-ca-cert"),
+                );
+                return Ok(response);
+            }
+            (_, path, _) if path == format!("/{CA_CERT_FILE}") => {
+                (StatusCode::NOT_FOUND, "The HTTPS interception is disabled.")
+            }
+            _ => (StatusCode::BAD_REQUEST, "This is a proxy server."),
+        };
+
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = status;
+        Ok(response)
+    }
+
+    /// Handle the `CONNECT` request. The connections to the NCM API are
+    /// intercepted if the root CA is available, and the ones to the
+    /// other allowed hosts are tunneled to the upstream as is.
+    fn tunnel(self: Arc<Self>, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        let authority = request
+            .uri()
+            .authority()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no authority in the CONNECT request"))?;
+        if !self.is_allowed_host(authority.host()) {
+            return Ok(forbidden(authority.host()));
+        }
+        let intercept = self.ca.is_some() && is_ncm_host(authority.host());
+
+        tokio::spawn(async move {
+            let result = async {
+                let upgraded = hyper::upgrade::on(request).await?;
+
+                if intercept {
+                    self.intercept(upgraded, authority.clone()).await
+                } else {
+                    debug!("Tunneling to {authority}…");
+                    let mut upgraded = upgraded;
+                    let mut upstream = connect_upstream(&self.state.context, &authority).await?;
+                    tokio::io::copy_bidirectional(&mut upgraded, &mut upstream).await?;
+                    anyhow::Ok(())
+                }
+            };
+
+            if let Err(e) = result.await {
+                debug!("The tunnel to {authority} is closed: {e}");
+            }
+        });
+
+        Ok(Response::new(Body::empty()))
+    }
+
+    /// Decrypt the HTTPS connection to `authority` with the certificate
+    /// issued by our root CA, and serve the requests in it.
+    async fn intercept(
+        self: Arc<Self>,
+        upgraded: Upgraded,
+        authority: Authority,
+    ) -> anyhow::Result<()> {
+        debug!("Intercepting the connection to {authority}…");
+
+        let ca = self.ca.clone().expect("only intercept with the root CA");
+        let host = authority.host().to_string();
+        // Issuing a certificate generates an RSA key, which blocks for a while.
+        let leaf = match ca.cached(&host) {
+            Some(leaf) => leaf,
+            None => tokio::task::spawn_blocking(move || ca.issue(&host)).await??,
+        };
+        let identity = native_tls::Identity::from_pkcs8(&leaf.cert_pem, &leaf.key_pem)?;
+        let acceptor = tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?);
+        let stream = acceptor.accept(upgraded).await?;
+
+        let service = service_fn(move |mut request: Request<Body>| {
+            // The requests in the tunnel are in the origin form.
+            let mut parts = request.uri().clone().into_parts();
+            parts.scheme = Some(Scheme::HTTPS);
+            parts.authority = Some(authority.clone());
+            if let Ok(uri) = Uri::from_parts(parts) {
+                *request.uri_mut() = uri;
+            }
+
+            self.clone().handle(request)
+        });
+
+        Http::new()
+            .http1_only(true)
+            .http1_preserve_header_case(true)
+            .http1_title_case_headers(true)
+            .serve_connection(stream, service)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forward the request to the upstream, and hook the
+    /// response if it is from the NCM API.
+    async fn forward(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        let (mut parts, body) = request.into_parts();
+        remove_hop_by_hop_headers(&mut parts.headers);
+        // The `Host` header follows the URL, whose host may be mapped.
+        parts.headers.remove(HOST);
+
+        let host = parts.uri.host().unwrap_or_default().to_string();
+        if !self.is_allowed_host(&host) {
+            return Ok(forbidden(&host));
+        }
+
+        let body = hyper::body::to_bytes(body).await?;
+        if !is_ncm_host(&host) {
+            return self.send(parts, body).await;
+        }
+
+        let ncm_request = match NcmRequest::decode(parts.uri.path(), &body) {
+            Ok(ncm_request) if hook::should_hook(&ncm_request.path, &self.state) => ncm_request,
+            result => {
+                if let Err(e) = result {
+                    debug!("Failed to decode the request to {}: {e}", parts.uri);
+                }
+                return self.send(parts, body).await;
+            }
+        };
+        info!("Hooking the NCM API “{}”…", ncm_request.path);
+
+        // We need the uncompressed response to patch it.
+        parts.headers.remove(ACCEPT_ENCODING);
+        parts.headers.remove(CONTENT_LENGTH);
+
+        let (mut parts, body) = self.send(parts, body).await?.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+
+        let body = match NcmResponse::decode(&ncm_request, &body) {
+            Ok(mut ncm_response) => {
+                hook::patch(&ncm_request.path, &mut ncm_response, &self.state).await;
+                ncm_response.encode()?.into()
+            }
+            Err(e) => {
+                warn!("Failed to decode the response of {}: {e}", ncm_request.path);
+                body
+            }
+        };
+
+        parts.headers.remove(CONTENT_ENCODING);
+        parts.headers.insert(CONTENT_LENGTH, body.len().into());
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+
+    /// Send the request to the upstream with our client,
+    /// and stream the body of its response back.
+    async fn send(
+        &self,
+        parts: http::request::Parts,
+        body: Bytes,
+    ) -> anyhow::Result<Response<Body>> {
+        let upstream = self
+            .client
+            .request(parts.method, parts.uri.to_string())
+            .headers(parts.headers)
+            .body(body)
+            .send()
+            .await?;
+
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = upstream.status();
+        *response.headers_mut() = upstream.headers().clone();
+        remove_hop_by_hop_headers(response.headers_mut());
+        *response.body_mut() = Body::wrap_stream(futures::stream::try_unfold(
+            upstream,
+            |mut upstream| async move {
+                let chunk = upstream.chunk().await?;
+                Ok::<_, RequestModuleError>(chunk.map(|chunk| (chunk, upstream)))
+            },
+        ));
+
+        Ok(response)
+    }
+}
+
+/// Whether the `host` matches the `pattern`, which is
+/// `*` for any host, `*.example.com` for the subdomains
+/// of `example.com`, or the exact host otherwise.
+fn match_host_pattern(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some("") => true,
+        Some(suffix) if suffix.starts_with('.') => host.ends_with(suffix),
+        _ => pattern.eq_ignore_ascii_case(host),
+    }
+}
+
+/// The response to the requests to the hosts we don't forward.
+fn forbidden(host: &str) -> Response<Body> {
+    debug!("Refusing to forward the request to {host}.");
+
+    let mut response = Response::new(Body::from(format!(
+        "This proxy doesn't forward the requests to {host}."
+    )));
+    *response.status_mut() = StatusCode::FORBIDDEN;
+    response
+}
+
+/// Connect to `authority` for a tunnel, with the host mapping,
+/// DNS settings and proxy of the upstream in the `ctx`.
+async fn connect_upstream(ctx: &Context, authority: &Authority) -> anyhow::Result<TcpStream> {
+    let port = authority.port_u16().unwrap_or(443);
+    let host = translate_host(&ctx.host_mapping, authority.host());
+
+    let proxy = proxy_uri_for(ctx, UPSTREAM_ENGINE, authority.host())?;
+    if let Some(proxy) = proxy.filter(|uri| !uri.is_empty()) {
+        return connect_via_proxy(&proxy, host, port).await;
+    }
+
+    let addrs = match dns::shared_resolver(&ctx.dns)? {
+        Some(resolver) if host.parse::<IpAddr>().is_err() => resolver
+            .lookup(host)
+            .await?
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect(),
+        _ => tokio::net::lookup_host((host, port))
+            .await?
+            .collect::<Vec<_>>(),
+    };
+
+    Ok(TcpStream::connect(&*addrs).await?)
+}
+
+/// Open a tunnel to `host:port` through the HTTP proxy `proxy_uri`.
+async fn connect_via_proxy(proxy_uri: &str, host: &str, port: u16) -> anyhow::Result<TcpStream> {
+    let proxy = url::Url::parse(proxy_uri)?;
+    anyhow::ensure!(
+        proxy.scheme() == "http" && proxy.username().is_empty(),
+        "only the HTTP proxies without credentials can tunnel, but got {proxy_uri}"
+    );
+
+    let mut stream = TcpStream::connect((
+        proxy.host_str().unwrap_or_default(),
+        proxy.port_or_known_default().unwrap_or(80),
+    ))
+    .await?;
+    stream
+        .write_all(
+            format!("CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n").as_bytes(),
+        )
+        .await?;
+
+    // Read the response head byte by byte, so nothing in the tunnel is consumed.
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        anyhow::ensure!(
+            head.len() < MAX_TUNNEL_HEAD,
+            "the response of {proxy_uri} is too long"
+        );
+        head.push(stream.read_u8().await?);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status = head.split_whitespace().nth(1);
+    anyhow::ensure!(
+        status == Some("200"),
+        "{proxy_uri} refused to tunnel to {host}:{port}: {}",
+        head.lines().next().unwrap_or_default()
+    );
+
+    Ok(stream)
+}
+
+fn remove_hop_by_hop_headers(headers: &mut HeaderMap) {
+    for header in HOP_BY_HOP_HEADERS {
+        headers.remove(header);
+    }
+    headers.remove("proxy-connection");
+    headers.remove("keep-alive");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::match_host_pattern;
+
+    #[test]
+    fn test_match_host_pattern() {
+        assert!(match_host_pattern("*", "example.com"));
+        assert!(match_host_pattern(
+            "m701.music.126.net",
+            "m701.music.126.net"
+        ));
+        assert!(match_host_pattern("*.music.126.net", "m701.music.126.net"));
+        assert!(!match_host_pattern("*.music.126.net", "music.126.net"));
+        assert!(!match_host_pattern("*.music.126.net", "evilmusic.126.net"));
+        assert!(!match_host_pattern("music.126.net", "m701.music.126.net"));
+    }
+}
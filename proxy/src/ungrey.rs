@@ -0,0 +1,292 @@
+//! Un-grey the unavailable songs in the song lists of the NCM API.
+//!
+//! Even if the player URLs are rewritten, the NCM client greys out
+//! the songs, and refuses to play them, according to the `st`, `fee`,
+//! `pl`, `dl` and `fl` fields of their privileges. We rewrite these
+//! fields in the responses of `playlist/detail`, `song/detail`,
+//! `v3/song/detail` and `album`, so the songs are marked as playable.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{json, Value as Json};
+use tracing::{debug, info};
+use unm_ncm::song::{NcmPrivilege, NcmSong};
+use unm_types::Song;
+
+use crate::hook::HookState;
+use crate::packet::NcmResponse;
+
+/// The bitrate to set if the privilege has no `maxbr`.
+const DEFAULT_BITRATE: i64 = 320000;
+
+/// Whether we should un-grey the response of the API `path`.
+pub fn should_ungrey(path: &str) -> bool {
+    path.contains("/playlist/detail")
+        || path.ends_with("/song/detail")
+        || path.starts_with("/api/album/")
+        || path.starts_with("/api/v1/album/")
+}
+
+/// Un-grey the songs in the `response`.
+///
+/// If `confirm` is enabled in the config, only the songs
+/// which the executor can find are un-greyed.
+pub async fn patch(response: &mut NcmResponse, state: &HookState) {
+    let ids = if state.ungrey.confirm {
+        let songs = greyed_songs(&response.body);
+        info!("Confirming the availability of {} songs…", songs.len());
+
+        let results = state
+            .executor
+            .search_batch(&state.engines, &songs, &state.context)
+            .await;
+
+        let ids = songs
+            .iter()
+            .zip(results)
+            .filter(|(_, result)| result.is_ok())
+            .filter_map(|(song, _)| song.id.parse::<i64>().ok())
+            .collect::<HashSet<_>>();
+        Some(ids)
+    } else {
+        None
+    };
+
+    let count = ungrey(&mut response.body, ids.as_ref());
+    debug!("Un-greyed {count} songs.");
+}
+
+/// The songs in the response `body`, which are in `songs` or
+/// `playlist.tracks`.
+fn songs(body: &Json) -> impl Iterator<Item = &Json> {
+    let songs = body["songs"].as_array().into_iter().flatten();
+    let tracks = body["playlist"]["tracks"].as_array().into_iter().flatten();
+
+    songs.chain(tracks)
+}
+
+/// The mutable references to the songs in the response `body`.
+fn songs_mut(body: &mut Json) -> Vec<&mut Json> {
+    let Json::Object(body) = body else {
+        return Vec::new();
+    };
+
+    let mut songs = Vec::new();
+    for (key, value) in body.iter_mut() {
+        let list = match key.as_str() {
+            "songs" => value.as_array_mut(),
+            "playlist" => value
+                .get_mut("tracks")
+                .and_then(|tracks| tracks.as_array_mut()),
+            _ => None,
+        };
+        songs.extend(list.into_iter().flatten());
+    }
+
+    songs
+}
+
+/// The privileges in the response `body`, keyed by the song ID.
+///
+/// They are in the `privileges` list, or the `privilege` of each song.
+fn privileges(body: &Json) -> HashMap<i64, NcmPrivilege> {
+    let listed = body["privileges"].as_array().into_iter().flatten();
+    let embedded = songs(body).map(|song| &song["privilege"]);
+
+    listed
+        .chain(embedded)
+        .filter_map(|privilege| serde_json::from_value::<NcmPrivilege>(privilege.clone()).ok())
+        .map(|privilege| (privilege.id, privilege))
+        .collect()
+}
+
+/// The songs which are greyed out in the response `body`.
+pub fn greyed_songs(body: &Json) -> Vec<Song> {
+    let privileges = privileges(body);
+
+    songs(body)
+        .filter(|song| {
+            let greyed = song["st"].as_i64().map_or(false, |st| st < 0);
+            let playable = song["id"]
+                .as_i64()
+                .and_then(|id| privileges.get(&id))
+                .map_or(true, NcmPrivilege::is_playable);
+
+            greyed || !playable
+        })
+        .filter_map(|song| serde_json::from_value::<NcmSong>(song.clone()).ok())
+        .map(Song::from)
+        .collect()
+}
+
+/// Mark the songs in the response `body` as playable,
+/// and return how many songs are un-greyed.
+///
+/// If `ids` is specified, only the songs with these IDs are un-greyed.
+pub fn ungrey(body: &mut Json, ids: Option<&HashSet<i64>>) -> usize {
+    let selected = |item: &Json| {
+        ids.map_or(true, |ids| {
+            item["id"].as_i64().map_or(false, |id| ids.contains(&id))
+        })
+    };
+    // The songs whose privileges are un-greyed.
+    let mut ungreyed = HashSet::new();
+
+    if let Some(privileges) = body["privileges"].as_array_mut() {
+        for privilege in privileges.iter_mut().filter(|p| selected(p)) {
+            if ungrey_privilege(privilege) {
+                ungreyed.extend(privilege["id"].as_i64());
+            }
+        }
+    }
+    let mut count = ungreyed.len();
+
+    for song in songs_mut(body).into_iter().filter(|s| selected(s)) {
+        let embedded = song
+            .get_mut("privilege")
+            .filter(|p| p.is_object())
+            .map_or(false, ungrey_privilege);
+        count += usize::from(embedded);
+
+        // Leave the songs which were playable already as they are.
+        let listed = song["id"]
+            .as_i64()
+            .map_or(false, |id| ungreyed.contains(&id));
+        let greyed = song["st"].as_i64().map_or(false, |st| st < 0);
+        if embedded || listed || greyed {
+            ungrey_song(song);
+        }
+    }
+
+    count
+}
+
+/// Mark the song as available.
+fn ungrey_song(song: &mut Json) {
+    if song["st"].as_i64().map_or(false, |st| st < 0) {
+        song["st"] = json!(0);
+    }
+    if song.get("fee").is_some() {
+        song["fee"] = json!(0);
+    }
+    if song.get("noCopyrightRcmd").is_some() {
+        song["noCopyrightRcmd"] = Json::Null;
+    }
+}
+
+/// Mark the privilege as playable at the max bitrate,
+/// and return if it was unplayable.
+fn ungrey_privilege(privilege: &mut Json) -> bool {
+    let playable = serde_json::from_value::<NcmPrivilege>(privilege.clone())
+        .map_or(false, |p| p.is_playable());
+    if playable {
+        return false;
+    }
+
+    let bitrate = privilege["maxbr"]
+        .as_i64()
+        .filter(|br| *br > 0)
+        .unwrap_or(DEFAULT_BITRATE);
+
+    privilege["st"] = json!(0);
+    privilege["fee"] = json!(0);
+    privilege["pl"] = json!(bitrate);
+    privilege["dl"] = json!(bitrate);
+    privilege["fl"] = json!(bitrate);
+    privilege["subp"] = json!(1);
+    privilege["cs"] = json!(false);
+    privilege["toast"] = json!(false);
+
+    if let Some(level) = privilege.get("maxBrLevel").cloned() {
+        privilege["plLevel"] = level.clone();
+        privilege["dlLevel"] = level.clone();
+        privilege["flLevel"] = level;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serde_json::Value as Json;
+
+    use super::{greyed_songs, should_ungrey, ungrey};
+
+    fn fixture(name: &str) -> Json {
+        let path = format!("{}/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn assert_playable(privilege: &Json) {
+        assert_eq!(privilege["st"], 0);
+        assert_eq!(privilege["fee"], 0);
+        assert!(privilege["pl"].as_i64().unwrap() > 0);
+        assert_eq!(privilege["pl"], privilege["maxbr"]);
+        assert_eq!(privilege["dl"], privilege["maxbr"]);
+        assert_eq!(privilege["fl"], privilege["maxbr"]);
+        assert_eq!(privilege["plLevel"], privilege["maxBrLevel"]);
+    }
+
+    #[test]
+    fn test_should_ungrey() {
+        assert!(should_ungrey("/api/v6/playlist/detail"));
+        assert!(should_ungrey("/api/v3/song/detail"));
+        assert!(should_ungrey("/api/song/detail"));
+        assert!(should_ungrey("/api/v1/album/18905"));
+        assert!(!should_ungrey("/api/song/enhance/player/url"));
+    }
+
+    #[test]
+    fn test_ungrey_playlist_detail() {
+        let mut body = fixture("playlist_detail.json");
+
+        let greyed = greyed_songs(&body);
+        assert_eq!(greyed.len(), 2);
+        assert_eq!(greyed[1].name, "晴天");
+        assert_eq!(greyed[1].artists[0].name, "周杰伦");
+
+        assert_eq!(ungrey(&mut body, None), 2);
+        assert!(greyed_songs(&body).is_empty());
+
+        for privilege in body["privileges"].as_array().unwrap() {
+            assert_playable(privilege);
+        }
+        let track = &body["playlist"]["tracks"][1];
+        assert_eq!(track["st"], 0);
+        assert!(track["noCopyrightRcmd"].is_null());
+    }
+
+    #[test]
+    fn test_ungrey_song_detail() {
+        let mut body = fixture("song_detail.json");
+        let original = body.clone();
+
+        // 七里香 is playable already.
+        let greyed = greyed_songs(&body);
+        assert_eq!(greyed.len(), 1);
+        assert_eq!(greyed[0].id, "185809");
+
+        assert_eq!(ungrey(&mut body, None), 1);
+        assert_playable(&body["privileges"][0]);
+        assert_eq!(body["songs"][0]["fee"], 0);
+        assert_eq!(body["privileges"][1], original["privileges"][1]);
+        assert_eq!(body["songs"][1], original["songs"][1]);
+    }
+
+    #[test]
+    fn test_ungrey_album() {
+        let mut body = fixture("album.json");
+        assert_eq!(greyed_songs(&body).len(), 1);
+
+        // Only the confirmed songs are un-greyed.
+        assert_eq!(ungrey(&mut body, Some(&HashSet::new())), 0);
+        assert_eq!(greyed_songs(&body).len(), 1);
+
+        assert_eq!(ungrey(&mut body, Some(&HashSet::from([185809]))), 1);
+        assert_playable(&body["songs"][0]["privilege"]);
+        assert_eq!(body["songs"][0]["fee"], 0);
+        assert!(greyed_songs(&body).is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+//! The [`Client`] that UNM engines send requests with.
+//!
+//! It is a thin wrapper of [`reqwest::Client`]. The differences are that
+//! [`RequestBuilder::send`] translates the host of the request according to
+//! [`unm_types::Context::host_mapping`], rotates the user agents of the
+//! client profile, attaches the cookies in the jar of the engine, waits
+//! for the limits of the upstream host (see [`crate::throttle`]), and
+//! hands the request to the [`Transport`] of the current scope, so the
+//! requests can be recorded or replayed.
+//!
+//! The [`Response`] holds the permit of the upstream limits
+//! until its body is read, or it is dropped. It also applies the read
+//! timeout of the client profile, which `reqwest` doesn't support,
+//! to waiting for the response and each chunk of its body.
+//!
+//! [`Transport`]: crate::transport::Transport
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use http::header::{HeaderMap, HeaderName, HeaderValue, COOKIE, USER_AGENT};
+use http::{Method, StatusCode};
+use reqwest::cookie::CookieStore;
+use reqwest::{Body, IntoUrl};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::Url;
+
+use crate::cookie::CookieJar;
+use crate::profile::UserAgentRotation;
+use crate::throttle::{self, Permit, Throttle};
+use crate::{translate_url, transport, RequestModuleError, RequestModuleResult};
+
+/// The client for sending requests.
+///
+/// Get one with [`crate::build_client`] or [`crate::build_forward_client`].
+#[derive(Clone, Debug)]
+pub struct Client {
+    inner: reqwest::Client,
+    /// The hosts to replace in the URL of requests.
+    host_mapping: Arc<HashMap<String, String>>,
+    /// The limits of the upstream hosts.
+    throttle: Arc<Throttle>,
+    /// The user agents to rotate, if there are more than one.
+    user_agents: Option<Arc<UserAgentRotation>>,
+    /// The longest time without receiving anything from the upstream.
+    read_timeout: Option<Duration>,
+    /// The cookie jar of the engine.
+    cookie_jar: Arc<CookieJar>,
+}
+
+impl Client {
+    pub(crate) fn new(
+        inner: reqwest::Client,
+        host_mapping: HashMap<String, String>,
+        throttle: Throttle,
+        user_agents: Option<UserAgentRotation>,
+        read_timeout: Option<Duration>,
+        cookie_jar: Arc<CookieJar>,
+    ) -> Self {
+        Self {
+            inner,
+            host_mapping: Arc::new(host_mapping),
+            throttle: Arc::new(throttle),
+            user_agents: user_agents.map(Arc::new),
+            read_timeout,
+            cookie_jar,
+        }
+    }
+
+    /// Start building a `GET` request to the `url`.
+    pub fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    /// Start building a `POST` request to the `url`.
+    pub fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.request(Method::POST, url)
+    }
+
+    /// Start building a request with the `method` to the `url`.
+    pub fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        RequestBuilder {
+            inner: self.inner.request(method, url),
+            host_mapping: self.host_mapping.clone(),
+            throttle: self.throttle.clone(),
+            user_agents: self.user_agents.clone(),
+            read_timeout: self.read_timeout,
+            cookie_jar: self.cookie_jar.clone(),
+        }
+    }
+}
+
+/// The builder of a request, constructed with [`Client`].
+#[derive(Debug)]
+#[must_use = "RequestBuilder does nothing until you 'send' it"]
+pub struct RequestBuilder {
+    inner: reqwest::RequestBuilder,
+    host_mapping: Arc<HashMap<String, String>>,
+    throttle: Arc<Throttle>,
+    user_agents: Option<Arc<UserAgentRotation>>,
+    read_timeout: Option<Duration>,
+    cookie_jar: Arc<CookieJar>,
+}
+
+impl RequestBuilder {
+    /// Add a header to this request.
+    pub fn header<K, V>(self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        Self {
+            inner: self.inner.header(key, value),
+            ..self
+        }
+    }
+
+    /// Merge the `headers` into the headers of this request.
+    pub fn headers(self, headers: HeaderMap) -> Self {
+        Self {
+            inner: self.inner.headers(headers),
+            ..self
+        }
+    }
+
+    /// Append the `query` to the query string of this request.
+    pub fn query<T: Serialize + ?Sized>(self, query: &T) -> Self {
+        Self {
+            inner: self.inner.query(query),
+            ..self
+        }
+    }
+
+    /// Send the `form` as the url-encoded body of this request.
+    pub fn form<T: Serialize + ?Sized>(self, form: &T) -> Self {
+        Self {
+            inner: self.inner.form(form),
+            ..self
+        }
+    }
+
+    /// Send the `json` as the JSON body of this request.
+    pub fn json<T: Serialize + ?Sized>(self, json: &T) -> Self {
+        Self {
+            inner: self.inner.json(json),
+            ..self
+        }
+    }
+
+    /// Set the body of this request.
+    pub fn body<T: Into<Body>>(self, body: T) -> Self {
+        Self {
+            inner: self.inner.body(body),
+            ..self
+        }
+    }
+
+    /// Set the timeout of this request, overriding the one of [`Client`].
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            inner: self.inner.timeout(timeout),
+            ..self
+        }
+    }
+
+    /// Send this request with the transport of the current scope,
+    /// after waiting for the limits of the upstream host.
+    ///
+    /// See [`crate::transport`] for more information.
+    pub async fn send(self) -> RequestModuleResult<Response> {
+        let (client, request) = self.inner.build_split();
+        let mut request = request.map_err(RequestModuleError::ConstructRequestFailed)?;
+
+        if !self.host_mapping.is_empty() {
+            translate_url(&self.host_mapping, request.url_mut())?;
+        }
+
+        // The user agent specified by the engine takes precedence.
+        if let Some(user_agents) = &self.user_agents {
+            if !request.headers().contains_key(USER_AGENT) {
+                request.headers_mut().insert(USER_AGENT, user_agents.next());
+            }
+        }
+
+        // reqwest attaches the cookies only if there is no `Cookie` header.
+        // We attach them here instead, so the transport can see them.
+        if !request.headers().contains_key(COOKIE) {
+            if let Some(cookies) = self.cookie_jar.cookies(request.url()) {
+                request.headers_mut().insert(COOKIE, cookies);
+            }
+        }
+
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let permit = self.throttle.acquire(&host).await;
+
+        let response = with_read_timeout(
+            self.read_timeout,
+            transport::current().execute(&client, request),
+        )
+        .await??;
+        throttle::observe(&host, &response);
+
+        Ok(Response {
+            inner: response,
+            read_timeout: self.read_timeout,
+            _permit: permit,
+        })
+    }
+}
+
+/// The response of a request sent with [`Client`].
+///
+/// It is a thin wrapper of [`reqwest::Response`], which holds the permit
+/// of the upstream limits, so the body being downloaded counts towards
+/// the concurrency of the host. Read the body or drop it to release.
+#[derive(Debug)]
+pub struct Response {
+    inner: reqwest::Response,
+    read_timeout: Option<Duration>,
+    _permit: Permit,
+}
+
+impl Response {
+    /// The status code of this response.
+    pub fn status(&self) -> StatusCode {
+        self.inner.status()
+    }
+
+    /// The headers of this response.
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    /// The final URL of this response.
+    pub fn url(&self) -> &Url {
+        self.inner.url()
+    }
+
+    /// Turn a response of the client or server error into an error.
+    pub fn error_for_status(self) -> reqwest::Result<Self> {
+        Ok(Self {
+            inner: self.inner.error_for_status()?,
+            ..self
+        })
+    }
+
+    /// Read the body as a text.
+    pub async fn text(self) -> RequestModuleResult<String> {
+        let response = self.buffer().await?;
+        response
+            .text()
+            .await
+            .map_err(RequestModuleError::ReadResponseFailed)
+    }
+
+    /// Read the body as a JSON, and deserialize it to `T`.
+    pub async fn json<T: DeserializeOwned>(self) -> RequestModuleResult<T> {
+        let response = self.buffer().await?;
+        response
+            .json()
+            .await
+            .map_err(RequestModuleError::ReadResponseFailed)
+    }
+
+    /// Read the body as bytes.
+    pub async fn bytes(self) -> RequestModuleResult<Bytes> {
+        let response = self.buffer().await?;
+        response
+            .bytes()
+            .await
+            .map_err(RequestModuleError::ReadResponseFailed)
+    }
+
+    /// Read the next chunk of the body, or `None` if it is finished.
+    pub async fn chunk(&mut self) -> RequestModuleResult<Option<Bytes>> {
+        with_read_timeout(self.read_timeout, self.inner.chunk())
+            .await?
+            .map_err(RequestModuleError::ReadResponseFailed)
+    }
+
+    /// Read the whole body with the read timeout into a new response,
+    /// so it can be decoded as what [`reqwest::Response`] does.
+    async fn buffer(self) -> RequestModuleResult<reqwest::Response> {
+        let Some(read_timeout) = self.read_timeout else {
+            return Ok(self.inner);
+        };
+
+        let mut inner = self.inner;
+        let mut body = BytesMut::new();
+        while let Some(chunk) = with_read_timeout(Some(read_timeout), inner.chunk())
+            .await?
+            .map_err(RequestModuleError::ReadResponseFailed)?
+        {
+            body.extend_from_slice(&chunk);
+        }
+
+        let mut response = http::Response::new(body.freeze());
+        *response.status_mut() = inner.status();
+        *response.version_mut() = inner.version();
+        *response.headers_mut() = std::mem::take(inner.headers_mut());
+
+        Ok(response.into())
+    }
+}
+
+/// Wait for `future` in `read_timeout`, if there is any.
+async fn with_read_timeout<T>(
+    read_timeout: Option<Duration>,
+    future: impl Future<Output = T>,
+) -> RequestModuleResult<T> {
+    match read_timeout {
+        Some(read_timeout) => tokio::time::timeout(read_timeout, future)
+            .await
+            .map_err(|_| RequestModuleError::ReadTimeout(read_timeout)),
+        None => Ok(future.await),
+    }
+}
@@ -0,0 +1,488 @@
+//! The per-engine cookie jar, optionally persisted to disk.
+//!
+//! Every client built with [`crate::build_client`] stores the cookies
+//! it receives in the jar of its engine, and sends them back later.
+//! If [`Context::cookie_dir`] is set, the jar of an engine is saved to
+//! `<cookie_dir>/<engine>.json` whenever it changes, so the cookies
+//! refreshed by the upstream survive restarts.
+//!
+//! The engines can seed the jar with the cookie the user configured
+//! (for example, `joox:cookie`) with [`CookieJar::seed`]. The seeded
+//! cookies are treated as the session of the engine: once they have
+//! expired or been removed by the upstream, [`CookieJar::check_session`]
+//! reports it, so the user knows it is time to update the cookie.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::HeaderValue;
+use once_cell::sync::Lazy;
+use reqwest::cookie::CookieStore;
+use serde::{Deserialize, Serialize};
+use unm_types::Context;
+use url::Url;
+
+use crate::{RequestModuleError, RequestModuleResult};
+
+/// The key of a jar: the engine and where to persist.
+type JarKey = (String, Option<PathBuf>);
+
+/// The jars of engines.
+static JARS: Lazy<Mutex<HashMap<JarKey, Arc<CookieJar>>>> = Lazy::new(Default::default);
+
+/// Get the cookie jar of `engine`, which is shared by every
+/// client of this engine with the same [`Context::cookie_dir`].
+pub fn cookie_jar(ctx: &Context, engine: &str) -> Arc<CookieJar> {
+    let path = ctx
+        .cookie_dir
+        .as_ref()
+        .map(|dir| dir.join(format!("{engine}.json")));
+
+    JARS.lock()
+        .expect("cookie jars lock poisoned")
+        .entry((engine.to_string(), path.clone()))
+        .or_insert_with(|| Arc::new(CookieJar::open(engine, path)))
+        .clone()
+}
+
+/// The cookie jar of an engine.
+#[derive(Debug)]
+pub struct CookieJar {
+    engine: String,
+    /// Where to persist this jar.
+    path: Option<PathBuf>,
+    state: Mutex<JarState>,
+}
+
+/// The content of a jar, which is what we persist.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JarState {
+    cookies: Vec<StoredCookie>,
+
+    /// The cookie string we seeded last time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seed: Option<String>,
+
+    /// The seeded cookies that have expired, and when (UNIX timestamp).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    expired: BTreeMap<String, u64>,
+}
+
+/// A cookie in the jar.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    /// The domain of this cookie, without the leading dot.
+    pub domain: String,
+    /// Is this cookie sent to the `domain` only, but not its subdomains?
+    #[serde(default)]
+    pub host_only: bool,
+    pub path: String,
+    /// When this cookie expires (UNIX timestamp).
+    /// It never expires if it is `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    #[serde(default)]
+    pub secure: bool,
+    /// Is this cookie seeded from the config?
+    #[serde(default)]
+    pub seeded: bool,
+}
+
+impl StoredCookie {
+    /// Construct a cookie sent to `domain` and its subdomains.
+    pub fn new(name: impl Into<String>, value: impl Into<String>, domain: &str) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+            host_only: false,
+            path: "/".to_string(),
+            expires: None,
+            secure: false,
+            seeded: false,
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    fn is_same(&self, other: &StoredCookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+        let domain_matched = host == self.domain
+            || (!self.host_only
+                && host
+                    .strip_suffix(self.domain.as_str())
+                    .map_or(false, |sub| sub.ends_with('.')));
+        let path_matched = url.path().starts_with(&self.path);
+
+        domain_matched && path_matched && (!self.secure || url.scheme() == "https")
+    }
+
+    /// Parse the `Set-Cookie` header received from `url`.
+    fn parse(header: &str, url: &Url, now: u64) -> Option<Self> {
+        let cookie = cookie::Cookie::parse(header).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) => {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                let matched = host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .map_or(false, |sub| sub.ends_with('.'));
+
+                // Reject the cookies for other domains.
+                if !matched {
+                    return None;
+                }
+                (domain, false)
+            }
+            None => (host, true),
+        };
+
+        // Max-Age takes precedence over Expires.
+        let expires = match (cookie.max_age(), cookie.expires_datetime()) {
+            (Some(max_age), _) => Some(now.saturating_add_signed(max_age.whole_seconds())),
+            (None, Some(expires)) => Some(expires.unix_timestamp().max(0) as u64),
+            (None, None) => None,
+        };
+
+        Some(Self {
+            name: cookie.name().to_string(),
+            value: cookie.value().to_string(),
+            domain,
+            host_only,
+            path: cookie
+                .path()
+                .filter(|p| p.starts_with('/'))
+                .unwrap_or("/")
+                .to_string(),
+            expires,
+            secure: cookie.secure().unwrap_or(false),
+            seeded: false,
+        })
+    }
+}
+
+impl CookieJar {
+    /// Open the jar of `engine`, which is persisted to `path`.
+    ///
+    /// If the file is missing or broken, we start with an empty jar.
+    pub(crate) fn open(engine: &str, path: Option<PathBuf>) -> Self {
+        let state = path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| match load_state(path) {
+                Ok(state) => Some(state),
+                Err(e) => {
+                    log::warn!("Failed to load the cookies of “{engine}”: {e}. Starting over.");
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            engine: engine.to_string(),
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Get the value of the cookie `name` that will be sent to `url`.
+    pub fn get(&self, url: &Url, name: &str) -> Option<String> {
+        let now = now();
+
+        self.lock()
+            .cookies
+            .iter()
+            .find(|c| c.name == name && !c.is_expired(now) && c.matches(url))
+            .map(|c| c.value.clone())
+    }
+
+    /// Insert or replace a cookie.
+    pub fn insert(&self, cookie: StoredCookie) {
+        let mut state = self.lock();
+        upsert(&mut state, cookie);
+        self.persist(&state);
+    }
+
+    /// Seed this jar with the `cookie` string (`a=1; b=2`) from the config,
+    /// which is sent to the `domains` and their subdomains.
+    ///
+    /// It is a no-op if this string has been seeded, so the cookies
+    /// refreshed by the upstream are not overwritten by the stale ones.
+    pub fn seed(&self, domains: &[&str], cookie: &str) {
+        let cookie = cookie.trim();
+        let mut state = self.lock();
+
+        if cookie.is_empty() || state.seed.as_deref() == Some(cookie) {
+            return;
+        }
+
+        log::debug!("Seeding the cookies of “{}” from the config…", self.engine);
+        state.cookies.retain(|c| !c.seeded);
+        state.expired.clear();
+
+        for pair in cookie.split(';') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+
+            for domain in domains {
+                let mut cookie = StoredCookie::new(name.trim(), value.trim(), domain);
+                cookie.seeded = true;
+                upsert(&mut state, cookie);
+            }
+        }
+
+        state.seed = Some(cookie.to_string());
+        self.persist(&state);
+    }
+
+    /// Check if the seeded session is still alive.
+    ///
+    /// It returns [`RequestModuleError::SessionExpired`] with the
+    /// expired cookies if any seeded cookie has expired or been removed.
+    pub fn check_session(&self) -> RequestModuleResult<()> {
+        let now = now();
+        let mut state = self.lock();
+
+        let newly_expired = state
+            .cookies
+            .iter()
+            .filter(|c| c.seeded && c.is_expired(now))
+            .map(|c| (c.name.clone(), c.expires.unwrap_or(now)))
+            .collect::<Vec<_>>();
+        if !newly_expired.is_empty() {
+            state.expired.extend(newly_expired);
+            state.cookies.retain(|c| !(c.seeded && c.is_expired(now)));
+            self.persist(&state);
+        }
+
+        if state.expired.is_empty() {
+            Ok(())
+        } else {
+            Err(RequestModuleError::SessionExpired {
+                engine: self.engine.clone(),
+                cookies: state.expired.keys().cloned().collect(),
+            })
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JarState> {
+        self.state.lock().expect("cookie jar lock poisoned")
+    }
+
+    /// Save `state` to the disk if this jar should be persisted.
+    ///
+    /// The failure is logged only, since the cookies are still in memory.
+    fn persist(&self, state: &JarState) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if let Err(e) = save_state(path, state) {
+            log::warn!("Failed to save the cookies of “{}”: {e}", self.engine);
+        }
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let now = now();
+        let mut state = self.lock();
+        let mut changed = false;
+
+        for cookie in cookie_headers
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(|v| StoredCookie::parse(v, url, now))
+        {
+            if cookie.is_expired(now) {
+                // The upstream removed this cookie.
+                let removed_seeded = state.cookies.iter().any(|c| c.seeded && c.is_same(&cookie));
+                if removed_seeded {
+                    log::warn!(
+                        "The upstream of “{}” removed the session cookie “{}”.",
+                        self.engine,
+                        cookie.name
+                    );
+                    state.expired.insert(cookie.name.clone(), now);
+                }
+                state.cookies.retain(|c| !c.is_same(&cookie));
+            } else {
+                upsert(&mut state, cookie);
+            }
+
+            changed = true;
+        }
+
+        if changed {
+            self.persist(&state);
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let now = now();
+        let state = self.lock();
+
+        let cookie = state
+            .cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if cookie.is_empty() {
+            None
+        } else {
+            HeaderValue::from_str(&cookie).ok()
+        }
+    }
+}
+
+/// Insert `cookie`, or replace the one with the same name, domain and path.
+///
+/// A refreshed seeded cookie is still a seeded one.
+fn upsert(state: &mut JarState, mut cookie: StoredCookie) {
+    match state.cookies.iter_mut().find(|c| c.is_same(&cookie)) {
+        Some(existing) => {
+            cookie.seeded |= existing.seeded;
+            *existing = cookie;
+        }
+        None => state.cookies.push(cookie),
+    }
+}
+
+fn load_state(path: &Path) -> RequestModuleResult<JarState> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| RequestModuleError::CookieJarIoFailed(path.display().to_string(), e))?;
+
+    serde_json::from_str(&content)
+        .map_err(|e| RequestModuleError::InvalidCookieJar(path.display().to_string(), e))
+}
+
+fn save_state(path: &Path, state: &JarState) -> RequestModuleResult<()> {
+    let content = serde_json::to_string_pretty(state)
+        .map_err(|e| RequestModuleError::InvalidCookieJar(path.display().to_string(), e))?;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| RequestModuleError::CookieJarIoFailed(path.display().to_string(), e))?;
+    }
+
+    std::fs::write(path, content + "\n")
+        .map_err(|e| RequestModuleError::CookieJarIoFailed(path.display().to_string(), e))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+    use reqwest::cookie::CookieStore;
+    use url::Url;
+
+    use super::{CookieJar, StoredCookie};
+    use crate::RequestModuleError;
+
+    fn set_cookies(jar: &CookieJar, url: &Url, headers: &[&str]) {
+        let headers = headers
+            .iter()
+            .map(|h| HeaderValue::from_str(h).unwrap())
+            .collect::<Vec<_>>();
+        jar.set_cookies(&mut headers.iter(), url);
+    }
+
+    #[test]
+    fn test_set_and_send_cookies() {
+        let jar = CookieJar::open("test", None);
+        let url = Url::parse("https://www.kuwo.invalid/api/search").unwrap();
+
+        set_cookies(
+            &jar,
+            &url,
+            &[
+                "a=1; Domain=kuwo.invalid; Path=/",
+                "b=2; Path=/api",
+                "c=3; Domain=elsewhere.invalid",
+                "d=4; Max-Age=0",
+            ],
+        );
+
+        assert_eq!(
+            jar.cookies(&url).unwrap(),
+            HeaderValue::from_static("a=1; b=2")
+        );
+
+        // `b` is host-only and limited to `/api`.
+        let other = Url::parse("https://mobi.kuwo.invalid/").unwrap();
+        assert_eq!(
+            jar.cookies(&other).unwrap(),
+            HeaderValue::from_static("a=1")
+        );
+    }
+
+    #[test]
+    fn test_seed_and_session_expiry() {
+        let jar = CookieJar::open("joox", None);
+        let url = Url::parse("http://api.joox.invalid/web-fcgi-bin/web_get_songinfo").unwrap();
+
+        jar.seed(&["joox.invalid"], "wmid=1; session_key=abc");
+        assert_eq!(jar.get(&url, "session_key").as_deref(), Some("abc"));
+        assert!(jar.check_session().is_ok());
+
+        // The refreshed cookie is kept, even if we seed the same string again.
+        set_cookies(&jar, &url, &["session_key=def; Domain=joox.invalid"]);
+        jar.seed(&["joox.invalid"], "wmid=1; session_key=abc");
+        assert_eq!(jar.get(&url, "session_key").as_deref(), Some("def"));
+
+        // The upstream removed the session.
+        set_cookies(
+            &jar,
+            &url,
+            &["session_key=; Domain=joox.invalid; Max-Age=0"],
+        );
+        match jar.check_session() {
+            Err(RequestModuleError::SessionExpired { engine, cookies }) => {
+                assert_eq!(engine, "joox");
+                assert_eq!(cookies, ["session_key"]);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        // The user updated the cookie.
+        jar.seed(&["joox.invalid"], "wmid=1; session_key=ghi");
+        assert!(jar.check_session().is_ok());
+        assert_eq!(jar.get(&url, "session_key").as_deref(), Some("ghi"));
+    }
+
+    #[test]
+    fn test_persist() {
+        let dir = std::env::temp_dir().join(format!("unm-cookie-test-{}", std::process::id()));
+        let path = dir.join("kuwo.json");
+        let url = Url::parse("http://www.kuwo.invalid/").unwrap();
+
+        let jar = CookieJar::open("kuwo", Some(path.clone()));
+        jar.insert(StoredCookie::new("kw_token", "TOKEN", "kuwo.invalid"));
+
+        let reopened = CookieJar::open("kuwo", Some(path));
+        assert_eq!(reopened.get(&url, "kw_token").as_deref(), Some("TOKEN"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,544 @@
+//! The DNS resolver configured with [`DnsConfig`].
+//!
+//! It looks up the static overrides first, and then queries the
+//! configured DNS server over UDP or DNS-over-HTTPS (RFC 8484).
+//! The truncated UDP responses are queried again over TCP.
+//! The answers are cached until their TTL expires.
+
+mod message;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cached::proc_macro::cached;
+use http::header::{ACCEPT, CONTENT_TYPE};
+use http::HeaderValue;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use unm_types::DnsConfig;
+use url::Url;
+
+use self::message::{build_query, parse_response, Answer, TYPE_A, TYPE_AAAA};
+
+/// The media type of DNS messages.
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// The longest time to cache an answer.
+const MAX_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// The timeout of a query to the DNS server.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The DNS resolver configured with [`DnsConfig`].
+///
+/// It is cheap to clone, and the clones share the same cache.
+#[derive(Clone)]
+pub struct DnsResolver {
+    inner: Arc<DnsResolverInner>,
+}
+
+struct DnsResolverInner {
+    hosts: HashMap<String, IpAddr>,
+    upstream: Upstream,
+    cache: Mutex<HashMap<String, (Instant, Vec<IpAddr>)>>,
+}
+
+enum Upstream {
+    System,
+    Udp(SocketAddr),
+    Https { client: reqwest::Client, url: Url },
+}
+
+impl Upstream {
+    fn from_server(server: Option<&str>) -> DnsModuleResult<Self> {
+        let server = match server.map(str::trim) {
+            None | Some("") => return Ok(Self::System),
+            Some(server) => server,
+        };
+
+        if server.starts_with("https://") || server.starts_with("http://") {
+            let url = Url::parse(server)
+                .map_err(|_| DnsModuleError::InvalidServer(server.to_string()))?;
+            let client = crate::client_builder()
+                .timeout(QUERY_TIMEOUT)
+                .build()
+                .map_err(DnsModuleError::ConstructClientFailed)?;
+
+            return Ok(Self::Https { client, url });
+        }
+
+        server
+            .parse::<SocketAddr>()
+            .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)))
+            .map(Self::Udp)
+            .map_err(|_| DnsModuleError::InvalidServer(server.to_string()))
+    }
+}
+
+impl DnsResolver {
+    /// Construct a resolver with the `config`.
+    ///
+    /// Use [`shared_resolver`] instead if you want to share the cache.
+    pub fn new(config: &DnsConfig) -> DnsModuleResult<Self> {
+        Ok(Self {
+            inner: Arc::new(DnsResolverInner {
+                hosts: config
+                    .hosts
+                    .iter()
+                    .map(|(host, ip)| (host.to_ascii_lowercase(), *ip))
+                    .collect(),
+                upstream: Upstream::from_server(config.server.as_deref())?,
+                cache: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    /// Look up the addresses of `host`.
+    pub async fn lookup(&self, host: &str) -> DnsModuleResult<Vec<IpAddr>> {
+        let host = host.to_ascii_lowercase();
+
+        if let Some(ip) = self.inner.hosts.get(&host) {
+            log::debug!("Resolved “{host}” to {ip} with the static overrides.");
+            return Ok(vec![*ip]);
+        }
+
+        if let Some(addrs) = self.cached(&host) {
+            return Ok(addrs);
+        }
+
+        let (addrs, ttl) = match &self.inner.upstream {
+            Upstream::System => {
+                let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                    .await
+                    .map_err(DnsModuleError::SystemLookupFailed)?
+                    .map(|addr| addr.ip())
+                    .collect::<Vec<_>>();
+
+                // The system resolver has its own cache.
+                return Ok(addrs);
+            }
+            _ => self.query_upstream(&host).await?,
+        };
+
+        if addrs.is_empty() {
+            return Err(DnsModuleError::NoRecord(host));
+        }
+
+        log::debug!("Resolved “{host}” to {addrs:?} (TTL: {ttl:?}).");
+        self.inner
+            .cache
+            .lock()
+            .expect("dns cache lock poisoned")
+            .insert(host, (Instant::now() + ttl, addrs.clone()));
+
+        Ok(addrs)
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let mut cache = self.inner.cache.lock().expect("dns cache lock poisoned");
+
+        match cache.get(host) {
+            Some((expires_at, addrs)) if *expires_at > Instant::now() => Some(addrs.clone()),
+            Some(_) => {
+                cache.remove(host);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Query the A records, and the AAAA records if there is no A record.
+    async fn query_upstream(&self, host: &str) -> DnsModuleResult<(Vec<IpAddr>, Duration)> {
+        let mut answer = self.query(host, TYPE_A).await?;
+        if answer.addrs.is_empty() {
+            answer = self.query(host, TYPE_AAAA).await?;
+        }
+
+        let ttl = Duration::from_secs(answer.ttl.into()).min(MAX_CACHE_TTL);
+        Ok((answer.addrs, ttl))
+    }
+
+    async fn query(&self, host: &str, record_type: u16) -> DnsModuleResult<Answer> {
+        match &self.inner.upstream {
+            Upstream::System => unreachable!("the system resolver is not queried by us"),
+            Upstream::Udp(server) => {
+                log::debug!("Querying “{host}” ({record_type}) from {server} over UDP…");
+
+                let id = fastrand::u16(..);
+                let query = build_query(id, host, record_type)?;
+
+                match parse_response(id, &query_udp(server, &query).await?) {
+                    Err(DnsModuleError::Truncated) => {
+                        log::debug!("The response is truncated. Querying “{host}” over TCP…");
+                        parse_response(id, &query_tcp(server, &query).await?)
+                    }
+                    result => result,
+                }
+            }
+            Upstream::Https { client, url } => {
+                log::debug!("Querying “{host}” ({record_type}) from {url} over HTTPS…");
+
+                // The ID should be 0 in DoH for the cache friendliness.
+                let query = build_query(0, host, record_type)?;
+                let response = client
+                    .post(url.clone())
+                    .header(ACCEPT, HeaderValue::from_static(DNS_MESSAGE))
+                    .header(CONTENT_TYPE, HeaderValue::from_static(DNS_MESSAGE))
+                    .body(query)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(DnsModuleError::DohRequestFailed)?;
+                let body = response
+                    .bytes()
+                    .await
+                    .map_err(DnsModuleError::DohRequestFailed)?;
+
+                parse_response(0, &body)
+            }
+        }
+    }
+}
+
+/// Send the `query` to the `server` over UDP, and receive the response.
+async fn query_udp(server: &SocketAddr, query: &[u8]) -> DnsModuleResult<Vec<u8>> {
+    let bind_addr = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .map_err(DnsModuleError::UdpIoFailed)?;
+    socket
+        .connect(server)
+        .await
+        .map_err(DnsModuleError::UdpIoFailed)?;
+    socket
+        .send(query)
+        .await
+        .map_err(DnsModuleError::UdpIoFailed)?;
+
+    let mut buf = [0; 1232];
+    let len = tokio::time::timeout(QUERY_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .map_err(|_| DnsModuleError::TimedOut)?
+        .map_err(DnsModuleError::UdpIoFailed)?;
+
+    Ok(buf[..len].to_vec())
+}
+
+/// Send the `query` to the `server` over TCP, and receive the response.
+///
+/// The messages are prefixed with their length (RFC 1035 §4.2.2).
+async fn query_tcp(server: &SocketAddr, query: &[u8]) -> DnsModuleResult<Vec<u8>> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(server).await?;
+
+        let mut message = Vec::with_capacity(2 + query.len());
+        message.extend_from_slice(&(query.len() as u16).to_be_bytes());
+        message.extend_from_slice(query);
+        stream.write_all(&message).await?;
+
+        let len = stream.read_u16().await?;
+        let mut response = vec![0; len.into()];
+        stream.read_exact(&mut response).await?;
+
+        Ok(response)
+    };
+
+    tokio::time::timeout(QUERY_TIMEOUT, exchange)
+        .await
+        .map_err(|_| DnsModuleError::TimedOut)?
+        .map_err(DnsModuleError::TcpIoFailed)
+}
+
+impl Resolve for DnsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+
+            Ok(addrs)
+        })
+    }
+}
+
+/// Get the resolver of the `config`, which is shared with
+/// every client with the same DNS settings.
+///
+/// It returns `None` if the `config` is the default one,
+/// which means we should use the system resolver as usual.
+#[cached(
+    key = "String",
+    convert = r#"{ resolver_cache_key(config) }"#,
+    result = true
+)]
+pub fn shared_resolver(config: &DnsConfig) -> DnsModuleResult<Option<DnsResolver>> {
+    if config == &DnsConfig::default() {
+        return Ok(None);
+    }
+
+    DnsResolver::new(config).map(Some)
+}
+
+/// The key of [`shared_resolver`] cache.
+pub(crate) fn resolver_cache_key(config: &DnsConfig) -> String {
+    let mut hosts = config
+        .hosts
+        .iter()
+        .map(|(host, ip)| format!("{host}={ip}"))
+        .collect::<Vec<_>>();
+    hosts.sort_unstable();
+
+    format!(
+        "{}|{}",
+        config.server.as_deref().unwrap_or(""),
+        hosts.join(",")
+    )
+}
+
+/// Error in this module.
+#[derive(Error, Debug)]
+pub enum DnsModuleError {
+    #[error("invalid DNS server: {0}")]
+    InvalidServer(String),
+
+    #[error("invalid host name to query: {0}")]
+    InvalidName(String),
+
+    #[error("failed to construct the DoH client: {0}")]
+    ConstructClientFailed(reqwest::Error),
+
+    #[error("failed to look up with the system resolver: {0}")]
+    SystemLookupFailed(std::io::Error),
+
+    #[error("failed to query over UDP: {0}")]
+    UdpIoFailed(std::io::Error),
+
+    #[error("failed to query over TCP: {0}")]
+    TcpIoFailed(std::io::Error),
+
+    #[error("failed to query over HTTPS: {0}")]
+    DohRequestFailed(reqwest::Error),
+
+    #[error("the DNS server did not respond in time")]
+    TimedOut,
+
+    #[error("the ID of the response mismatched with the query")]
+    IdMismatched,
+
+    #[error("invalid DNS message: {0}")]
+    InvalidMessage(&'static str),
+
+    #[error("the DNS response is truncated")]
+    Truncated,
+
+    #[error("the DNS server failed with code {0}")]
+    ServerFailed(u16),
+
+    #[error("no address record found for {0}")]
+    NoRecord(String),
+}
+
+/// The [`Result`] of this module.
+pub type DnsModuleResult<T> = Result<T, DnsModuleError>;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::IpAddr;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, UdpSocket};
+    use unm_types::{ContextBuilder, DnsConfig};
+
+    use super::message::build_response;
+    use super::{DnsModuleError, DnsResolver};
+    use crate::build_client;
+
+    const LOCALHOST: &str = "127.0.0.1";
+
+    /// Start a stand-in DoH server answering every query with `127.0.0.1`.
+    ///
+    /// It returns the URL of the endpoint.
+    async fn doh_server(queries: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/dns-query", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            for _ in 0..queries {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+
+                // Read until we got the whole body, whose length is in `content-length`.
+                let query = loop {
+                    let len = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..len]);
+
+                    let Some(head_end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                        continue;
+                    };
+                    let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+                    assert!(head.starts_with("post /dns-query"));
+                    assert!(head.contains("content-type: application/dns-message"));
+
+                    let content_length = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length: "))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap();
+                    if request.len() >= head_end + 4 + content_length {
+                        break request[head_end + 4..].to_vec();
+                    }
+                };
+
+                let answer = build_response(&query, &[LOCALHOST.parse().unwrap()], 60);
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/dns-message\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    answer.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(&answer).await.unwrap();
+            }
+        });
+
+        url
+    }
+
+    fn config(server: Option<String>, hosts: &[(&str, &str)]) -> DnsConfig {
+        DnsConfig::builder()
+            .server(server)
+            .hosts(
+                hosts
+                    .iter()
+                    .map(|(host, ip)| (host.to_string(), ip.parse().unwrap()))
+                    .collect(),
+            )
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_static_overrides() {
+        let resolver = DnsResolver::new(&config(None, &[("WWW.Kuwo.cn", "203.0.113.10")])).unwrap();
+
+        assert_eq!(
+            resolver.lookup("www.kuwo.cn").await.unwrap(),
+            ["203.0.113.10".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_doh_lookup_and_cache() {
+        // Only one query is answered, so the second lookup must hit the cache.
+        let resolver = DnsResolver::new(&config(Some(doh_server(1).await), &[])).unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                resolver.lookup("music.example").await.unwrap(),
+                [LOCALHOST.parse::<IpAddr>().unwrap()]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_udp_lookup() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            let (len, peer) = server.recv_from(&mut buf).await.unwrap();
+            let answer = build_response(&buf[..len], &["198.51.100.7".parse().unwrap()], 60);
+            server.send_to(&answer, peer).await.unwrap();
+        });
+
+        let resolver = DnsResolver::new(&config(Some(server_addr.to_string()), &[])).unwrap();
+        assert_eq!(
+            resolver.lookup("u.y.qq.com").await.unwrap(),
+            ["198.51.100.7".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_truncated_udp_lookup_over_tcp() {
+        let udp_server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = udp_server.local_addr().unwrap();
+        let tcp_server = TcpListener::bind(server_addr).await.unwrap();
+
+        // The UDP response is truncated without any record.
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            let (len, peer) = udp_server.recv_from(&mut buf).await.unwrap();
+            let mut answer = build_response(&buf[..len], &[], 60);
+            answer[2] |= 0x02;
+            udp_server.send_to(&answer, peer).await.unwrap();
+        });
+        tokio::spawn(async move {
+            let (mut stream, _) = tcp_server.accept().await.unwrap();
+            let len = stream.read_u16().await.unwrap();
+            let mut query = vec![0; len.into()];
+            stream.read_exact(&mut query).await.unwrap();
+
+            let answer = build_response(&query, &["198.51.100.8".parse().unwrap()], 60);
+            stream
+                .write_all(&(answer.len() as u16).to_be_bytes())
+                .await
+                .unwrap();
+            stream.write_all(&answer).await.unwrap();
+        });
+
+        let resolver = DnsResolver::new(&config(Some(server_addr.to_string()), &[])).unwrap();
+        assert_eq!(
+            resolver.lookup("y.qq.com").await.unwrap(),
+            ["198.51.100.8".parse::<IpAddr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_invalid_server() {
+        assert!(matches!(
+            DnsResolver::new(&config(Some("not a server".to_string()), &[])),
+            Err(DnsModuleError::InvalidServer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_build_client_with_doh() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                .await
+                .unwrap();
+        });
+
+        let ctx = ContextBuilder::default()
+            .dns(config(Some(doh_server(1).await), &[]))
+            .host_mapping(HashMap::new())
+            .build()
+            .unwrap();
+        let response = build_client(&ctx, "example")
+            .unwrap()
+            .get(format!("http://music.example:{port}/"))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+}
@@ -0,0 +1,292 @@
+//! A minimal DNS message codec.
+//!
+//! It only supports what the resolver needs: building the query of
+//! the A/AAAA records of a name, and extracting the addresses from
+//! the response. See RFC 1035 §4 for the message format.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::{DnsModuleError, DnsModuleResult};
+
+/// The type of IPv4 address record.
+pub(crate) const TYPE_A: u16 = 1;
+/// The type of IPv6 address record.
+pub(crate) const TYPE_AAAA: u16 = 28;
+/// The `IN` (Internet) class.
+const CLASS_IN: u16 = 1;
+
+/// The `RD` (recursion desired) flag.
+const FLAG_RD: u16 = 0x0100;
+/// The `QR` flag, which is set in responses.
+const FLAG_QR: u16 = 0x8000;
+/// The `TC` (truncated) flag, which is set if the response
+/// is too long for UDP. The query should be sent over TCP then.
+const FLAG_TC: u16 = 0x0200;
+
+/// The `NXDOMAIN` response code.
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// The addresses extracted from a response.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Answer {
+    pub addrs: Vec<IpAddr>,
+    /// The minimum TTL (in seconds) of the address records.
+    pub ttl: u32,
+}
+
+/// Build the query of the records with `record_type` of `name`.
+pub(crate) fn build_query(id: u16, name: &str, record_type: u16) -> DnsModuleResult<Vec<u8>> {
+    let mut message = Vec::with_capacity(18 + name.len());
+
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(&FLAG_RD.to_be_bytes());
+    // QDCOUNT = 1; ANCOUNT, NSCOUNT, ARCOUNT = 0
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    write_name(&mut message, name)?;
+    message.extend_from_slice(&record_type.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(message)
+}
+
+/// Extract the addresses from the response of the query `id`.
+///
+/// `NXDOMAIN` is treated as an empty answer, and the truncated
+/// response is [`DnsModuleError::Truncated`], since the records
+/// in it can be incomplete.
+pub(crate) fn parse_response(id: u16, message: &[u8]) -> DnsModuleResult<Answer> {
+    let mut reader = Reader { message, pos: 0 };
+
+    if reader.u16()? != id {
+        return Err(DnsModuleError::IdMismatched);
+    }
+
+    let flags = reader.u16()?;
+    if flags & FLAG_QR == 0 {
+        return Err(DnsModuleError::InvalidMessage("not a response"));
+    }
+    if flags & FLAG_TC != 0 {
+        return Err(DnsModuleError::Truncated);
+    }
+    match flags & 0x000f {
+        0 => {}
+        RCODE_NXDOMAIN => {
+            return Ok(Answer {
+                addrs: vec![],
+                ttl: 0,
+            })
+        }
+        rcode => return Err(DnsModuleError::ServerFailed(rcode)),
+    }
+
+    let question_count = reader.u16()?;
+    let answer_count = reader.u16()?;
+    reader.take(4)?; // NSCOUNT, ARCOUNT
+
+    for _ in 0..question_count {
+        reader.skip_name()?;
+        reader.take(4)?; // QTYPE, QCLASS
+    }
+
+    let mut addrs = Vec::with_capacity(answer_count as usize);
+    let mut ttl = u32::MAX;
+
+    for _ in 0..answer_count {
+        reader.skip_name()?;
+        let record_type = reader.u16()?;
+        let class = reader.u16()?;
+        let record_ttl = reader.u32()?;
+        let length = reader.u16()? as usize;
+        let data = reader.take(length)?;
+
+        let addr = match (record_type, class, data) {
+            (TYPE_A, CLASS_IN, &[a, b, c, d]) => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+            (TYPE_AAAA, CLASS_IN, data) if data.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            // For example, CNAME. The resolver has followed it for us.
+            _ => continue,
+        };
+
+        addrs.push(addr);
+        ttl = ttl.min(record_ttl);
+    }
+
+    if addrs.is_empty() {
+        ttl = 0;
+    }
+
+    Ok(Answer { addrs, ttl })
+}
+
+fn write_name(message: &mut Vec<u8>, name: &str) -> DnsModuleResult<()> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(DnsModuleError::InvalidName(name.to_string()));
+        }
+
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+
+    Ok(())
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> DnsModuleResult<&'a [u8]> {
+        let data = self
+            .message
+            .get(self.pos..self.pos + len)
+            .ok_or(DnsModuleError::InvalidMessage("unexpected end of message"))?;
+        self.pos += len;
+
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> DnsModuleResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> DnsModuleResult<u16> {
+        let data = self.take(2)?;
+        Ok(u16::from_be_bytes([data[0], data[1]]))
+    }
+
+    fn u32(&mut self) -> DnsModuleResult<u32> {
+        let data = self.take(4)?;
+        Ok(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+    }
+
+    /// Skip a name, which can end with a compression pointer.
+    fn skip_name(&mut self) -> DnsModuleResult<()> {
+        loop {
+            match self.u8()? {
+                0 => return Ok(()),
+                len if len & 0xc0 == 0xc0 => {
+                    self.u8()?;
+                    return Ok(());
+                }
+                len => {
+                    self.take(len as usize)?;
+                }
+            }
+        }
+    }
+}
+
+/// Build the response of the `query` with the `addrs`,
+/// for the stand-in DNS servers in tests.
+#[cfg(test)]
+pub(crate) fn build_response(query: &[u8], addrs: &[IpAddr], ttl: u32) -> Vec<u8> {
+    let mut message = Vec::with_capacity(query.len() + addrs.len() * 28);
+
+    message.extend_from_slice(&query[..2]);
+    message.extend_from_slice(&(FLAG_QR | FLAG_RD | 0x0080).to_be_bytes());
+    message.extend_from_slice(&query[4..6]);
+    message.extend_from_slice(&(addrs.len() as u16).to_be_bytes());
+    message.extend_from_slice(&[0, 0, 0, 0]);
+    message.extend_from_slice(&query[12..]);
+
+    for addr in addrs {
+        // Pointer to the name in the question.
+        message.extend_from_slice(&[0xc0, 0x0c]);
+        let (record_type, data) = match addr {
+            IpAddr::V4(v4) => (TYPE_A, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (TYPE_AAAA, v6.octets().to_vec()),
+        };
+        message.extend_from_slice(&record_type.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+        message.extend_from_slice(&ttl.to_be_bytes());
+        message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        message.extend_from_slice(&data);
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::*;
+
+    #[test]
+    fn test_build_query() {
+        let query = build_query(0xbeef, "www.kuwo.cn", TYPE_A).unwrap();
+
+        assert_eq!(
+            query,
+            [
+                &[0xbe, 0xef, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0][..],
+                b"\x03www\x04kuwo\x02cn\x00",
+                &[0, 1, 0, 1],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_build_query_with_invalid_name() {
+        assert!(matches!(
+            build_query(1, "www..kuwo.cn", TYPE_A),
+            Err(DnsModuleError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let query = build_query(42, "u.y.qq.com", TYPE_A).unwrap();
+        let addrs = ["203.0.113.10", "203.0.113.11"]
+            .map(|v| v.parse::<IpAddr>().unwrap())
+            .to_vec();
+        let response = build_response(&query, &addrs, 300);
+
+        assert_eq!(
+            parse_response(42, &response).unwrap(),
+            Answer { addrs, ttl: 300 }
+        );
+        assert!(matches!(
+            parse_response(43, &response),
+            Err(DnsModuleError::IdMismatched)
+        ));
+        assert!(matches!(
+            parse_response(42, &response[..response.len() - 1]),
+            Err(DnsModuleError::InvalidMessage(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_truncated_response() {
+        let query = build_query(9, "u.y.qq.com", TYPE_A).unwrap();
+        let mut response = build_response(&query, &["203.0.113.10".parse().unwrap()], 300);
+        response[2] |= (FLAG_TC >> 8) as u8;
+
+        assert!(matches!(
+            parse_response(9, &response),
+            Err(DnsModuleError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_parse_nxdomain_response() {
+        let query = build_query(7, "nothing.invalid", TYPE_AAAA).unwrap();
+        let mut response = build_response(&query, &[], 0);
+        response[3] |= RCODE_NXDOMAIN as u8;
+
+        assert_eq!(
+            parse_response(7, &response).unwrap(),
+            Answer {
+                addrs: vec![],
+                ttl: 0
+            }
+        );
+    }
+}
@@ -2,32 +2,76 @@ use reqwest::Response;
 use serde::de::DeserializeOwned;
 use thiserror::Error;
 
+use crate::json::{parse_js_literal, parse_jsonp, JsLiteralError, Json};
+
 #[async_trait::async_trait]
 pub trait ResponseExt {
+    /// Deserialize the JSONP response, which can be wrapped with
+    /// a callback or not. See [`crate::json::parse_jsonp`].
     async fn jsonp<T: DeserializeOwned>(self) -> RequestExtModuleResult<T>;
+
+    /// Deserialize the response of a JavaScript object literal, like
+    /// `{'name': 'value'}`. See [`crate::json::parse_js_literal`].
+    async fn js_literal<T: DeserializeOwned>(self) -> RequestExtModuleResult<T>;
 }
 
 #[async_trait::async_trait]
 impl ResponseExt for Response {
     async fn jsonp<T: DeserializeOwned>(self) -> RequestExtModuleResult<T> {
-        let json_str = {
-            let jsonp_str = self
-                .text()
-                .await
-                .map_err(RequestExtModuleError::GetTextFailed)?;
-            crate::json::extract_jsonp(&jsonp_str)
-        };
-
-        serde_json::from_str::<T>(&json_str).map_err(RequestExtModuleError::DeserializeFailed)
+        let text = self.text().await;
+        deserialize_text(
+            text.map_err(RequestExtModuleError::GetTextFailed),
+            parse_jsonp,
+        )
+    }
+
+    async fn js_literal<T: DeserializeOwned>(self) -> RequestExtModuleResult<T> {
+        let text = self.text().await;
+        deserialize_text(
+            text.map_err(RequestExtModuleError::GetTextFailed),
+            parse_js_literal,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl ResponseExt for crate::Response {
+    async fn jsonp<T: DeserializeOwned>(self) -> RequestExtModuleResult<T> {
+        let text = self.text().await;
+        deserialize_text(text.map_err(RequestExtModuleError::ReadFailed), parse_jsonp)
+    }
+
+    async fn js_literal<T: DeserializeOwned>(self) -> RequestExtModuleResult<T> {
+        let text = self.text().await;
+        deserialize_text(
+            text.map_err(RequestExtModuleError::ReadFailed),
+            parse_js_literal,
+        )
     }
 }
 
+/// Parse the `text` of a response with `parse`, and deserialize it to `T`.
+fn deserialize_text<T: DeserializeOwned>(
+    text: RequestExtModuleResult<String>,
+    parse: fn(&str) -> Result<Json, JsLiteralError>,
+) -> RequestExtModuleResult<T> {
+    let text = text?;
+
+    serde_json::from_value::<T>(parse(&text)?).map_err(RequestExtModuleError::DeserializeFailed)
+}
+
 /// Error in this module.
 #[derive(Error, Debug)]
 pub enum RequestExtModuleError {
     #[error("failed to call .text() on the response: {0}")]
     GetTextFailed(reqwest::Error),
 
+    #[error("failed to read the response: {0}")]
+    ReadFailed(crate::RequestModuleError),
+
+    #[error("failed to parse the response: {0}")]
+    ParseFailed(#[from] JsLiteralError),
+
     #[error("failed to deserialize to a struct: {0}")]
     DeserializeFailed(serde_json::Error),
 }
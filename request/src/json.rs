@@ -1,19 +1,30 @@
 //! The JSON utilities for the response UNM ecosystem received.
 
+mod literal;
+
+pub use literal::{parse_js_literal, parse_jsonp, JsLiteralError, JsLiteralResult};
 pub use serde_json::Value as Json;
 
 /// Extract the JSON string from a JSONP response.
+///
+/// Prefer [`parse_jsonp`], which also understands the JavaScript
+/// object literals. This function falls back to taking everything
+/// between the first `(` and the last `)` if `data` is not parsable.
 pub fn extract_jsonp(data: &str) -> String {
     // jsonp({"data": {"id": "1", "name": "test"}});
     //       ~ START HERE                        ~ END HERE
+    if let Ok((_, span)) = literal::parse_jsonp_with_span(data) {
+        return data[span].to_string();
+    }
 
-    let left_bracket_index = data.find('(').map(|idx| idx + 1);
-    let right_bracket_index = data.rfind(')');
+    // Both of them are byte indices, so we slice the string
+    // instead of counting the characters.
+    let left_bracket_index = data.find('(').map(|idx| idx + 1).unwrap_or(0);
+    let right_bracket_index = data.rfind(')').unwrap_or(data.len());
 
-    data.chars()
-        .skip(left_bracket_index.unwrap_or(0))
-        .take(right_bracket_index.unwrap_or(data.len()) - left_bracket_index.unwrap_or(0))
-        .collect()
+    data.get(left_bracket_index..right_bracket_index)
+        .unwrap_or_default()
+        .to_string()
 }
 
 /// Throws when the JSON is not able to extract.
@@ -52,7 +63,8 @@ mod extract_jsonp_test {
             r##"{
                 "color":"red",
                 "value":"#f00"
-            }"##]
+            }"##,
+            r#"{"msong":"青花瓷","msinger":"周杰倫"}"#]
             .into_iter()
             .map(|json| Testdata {
                 src: json,
@@ -74,4 +86,12 @@ mod extract_jsonp_test {
             }
         }
     }
+
+    #[test]
+    fn test_extract_jsonp_with_parentheses() {
+        assert_eq!(
+            extract_jsonp(r#"cb({"title": "a (b)"}) // (c)"#),
+            r#"{"title": "a (b)"}"#
+        );
+    }
 }
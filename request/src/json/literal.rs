@@ -0,0 +1,592 @@
+//! The parser of the loose JSON, which is JavaScript object literals
+//! that some upstreams respond instead of the standard JSON.
+//!
+//! Besides the standard JSON, it understands:
+//!
+//! - single-quoted strings and the JavaScript escapes (`\'`, `\xHH`, `\v`…),
+//! - unquoted keys, like `{ id: 1 }`,
+//! - trailing commas, `undefined`, `NaN` and `Infinity` (as `null`),
+//! - hexadecimal numbers and the numbers like `.5`, `+1` or `1.`,
+//! - the `//` and `/* */` comments.
+//!
+//! [`parse_jsonp`] also strips the JSONP callback wrapper,
+//! like `callback({ ... });`.
+
+use serde_json::{Map, Number};
+use thiserror::Error;
+
+use super::Json;
+
+/// The max levels of the nested objects and arrays, which is the same as
+/// the recursion limit of `serde_json`. The deeper ones are rejected
+/// instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Parse the JavaScript object literal `data`.
+pub fn parse_js_literal(data: &str) -> JsLiteralResult<Json> {
+    let mut parser = Parser::new(data);
+
+    parser.skip_trivia()?;
+    let value = parser.parse_value(0)?;
+    parser.skip_trailing()?;
+
+    Ok(value)
+}
+
+/// Parse the JSONP response `data`, which can be wrapped
+/// with a callback or not, like `callback({ ... });`.
+pub fn parse_jsonp(data: &str) -> JsLiteralResult<Json> {
+    let (value, _) = parse_jsonp_with_span(data)?;
+    Ok(value)
+}
+
+/// Parse the JSONP response `data`, and get the byte range
+/// of the value (without the callback wrapper) in `data`.
+pub(crate) fn parse_jsonp_with_span(data: &str) -> JsLiteralResult<(Json, std::ops::Range<usize>)> {
+    let mut parser = Parser::new(data);
+
+    parser.skip_trivia()?;
+    let wrapped = parser.skip_callback()?;
+
+    parser.skip_trivia()?;
+    let start = parser.pos;
+    let value = parser.parse_value(0)?;
+    let end = parser.pos;
+
+    if wrapped {
+        parser.skip_trivia()?;
+        parser.expect(b')', "“)” closing the callback")?;
+    }
+    parser.skip_trailing()?;
+
+    Ok((value, start..end))
+}
+
+struct Parser<'a> {
+    data: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(data: &'a str) -> Self {
+        // Skip the BOM, which some upstreams prepend.
+        let pos = if data.starts_with('\u{feff}') { 3 } else { 0 };
+        Self { data, pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.as_bytes().get(self.pos).copied()
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.data[self.pos..].chars().next()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.data[self.pos..]
+    }
+
+    fn unexpected(&self, expected: &'static str) -> JsLiteralError {
+        match self.peek_char() {
+            Some(found) => JsLiteralError::UnexpectedChar {
+                found,
+                position: self.pos,
+                expected,
+            },
+            None => JsLiteralError::UnexpectedEnd { expected },
+        }
+    }
+
+    fn expect(&mut self, byte: u8, expected: &'static str) -> JsLiteralResult<()> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    /// Skip the whitespaces and comments.
+    fn skip_trivia(&mut self) -> JsLiteralResult<()> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => {
+                        return Err(JsLiteralError::UnexpectedEnd {
+                            expected: "“*/”"
+                        })
+                    }
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skip the `;` and the trivia after the value,
+    /// and make sure there is nothing else.
+    fn skip_trailing(&mut self) -> JsLiteralResult<()> {
+        loop {
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(b';') => self.pos += 1,
+                None => return Ok(()),
+                Some(_) => return Err(self.unexpected("the end of input")),
+            }
+        }
+    }
+
+    /// Skip the callback like `window.callback(`, and return
+    /// if there is one. The `/**/` before it has been skipped.
+    fn skip_callback(&mut self) -> JsLiteralResult<bool> {
+        let rest = self.rest();
+        let is_identifier_start = |c: char| c.is_alphabetic() || c == '_' || c == '$';
+
+        if !rest.starts_with(is_identifier_start) {
+            return Ok(false);
+        }
+
+        let name_len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$' || c == '.'))
+            .unwrap_or(rest.len());
+
+        // It is a keyword like `null`, rather than a callback.
+        let after_name = rest[name_len..].trim_start();
+        if !after_name.starts_with('(') {
+            return Ok(false);
+        }
+
+        self.pos += name_len;
+        self.skip_trivia()?;
+        self.expect(b'(', "“(” opening the callback")?;
+        Ok(true)
+    }
+
+    /// Parse a value, which is in `depth` levels of objects and arrays.
+    fn parse_value(&mut self, depth: usize) -> JsLiteralResult<Json> {
+        match self.peek() {
+            Some(b'{' | b'[') if depth >= MAX_DEPTH => Err(JsLiteralError::TooDeep(self.pos)),
+            Some(b'{') => self.parse_object(depth),
+            Some(b'[') => self.parse_array(depth),
+            Some(quote @ (b'"' | b'\'')) => self.parse_string(quote).map(Json::String),
+            Some(b'0'..=b'9' | b'-' | b'+' | b'.') => self.parse_number(),
+            Some(_) => self.parse_keyword(),
+            None => Err(self.unexpected("a value")),
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> JsLiteralResult<Json> {
+        self.expect(b'{', "“{”")?;
+        let mut map = Map::new();
+
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(b'}') {
+                self.pos += 1;
+                return Ok(Json::Object(map));
+            }
+
+            let key = match self.peek() {
+                Some(quote @ (b'"' | b'\'')) => self.parse_string(quote)?,
+                _ => self.parse_bare_key()?,
+            };
+
+            self.skip_trivia()?;
+            self.expect(b':', "“:” after the key")?;
+            self.skip_trivia()?;
+            map.insert(key, self.parse_value(depth + 1)?);
+
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {}
+                _ => return Err(self.unexpected("“,” or “}”")),
+            }
+        }
+    }
+
+    fn parse_bare_key(&mut self) -> JsLiteralResult<String> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(rest.len());
+
+        if len == 0 {
+            return Err(self.unexpected("a key"));
+        }
+
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn parse_array(&mut self, depth: usize) -> JsLiteralResult<Json> {
+        self.expect(b'[', "“[”")?;
+        let mut array = Vec::new();
+
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Json::Array(array));
+            }
+
+            array.push(self.parse_value(depth + 1)?);
+
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {}
+                _ => return Err(self.unexpected("“,” or “]”")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self, quote: u8) -> JsLiteralResult<String> {
+        self.pos += 1;
+        let mut string = String::new();
+
+        loop {
+            let rest = self.rest();
+            let Some(special) = rest.find(|c: char| c as u32 == u32::from(quote) || c == '\\')
+            else {
+                return Err(JsLiteralError::UnexpectedEnd {
+                    expected: "the closing quote",
+                });
+            };
+
+            string.push_str(&rest[..special]);
+            self.pos += special;
+
+            if self.peek() == Some(quote) {
+                self.pos += 1;
+                return Ok(string);
+            }
+
+            self.parse_escape(&mut string)?;
+        }
+    }
+
+    /// Parse the escape sequence after `\`.
+    fn parse_escape(&mut self, string: &mut String) -> JsLiteralResult<()> {
+        let position = self.pos;
+        self.pos += 1;
+
+        let Some(c) = self.peek_char() else {
+            return Err(JsLiteralError::UnexpectedEnd {
+                expected: "an escape sequence",
+            });
+        };
+        self.pos += c.len_utf8();
+
+        match c {
+            'n' => string.push('\n'),
+            't' => string.push('\t'),
+            'r' => string.push('\r'),
+            'b' => string.push('\u{8}'),
+            'f' => string.push('\u{c}'),
+            'v' => string.push('\u{b}'),
+            '0' if !matches!(self.peek(), Some(b'0'..=b'9')) => string.push('\0'),
+            'x' => {
+                let code = self
+                    .parse_hex(2)
+                    .ok_or(JsLiteralError::InvalidEscape(position))?;
+                string.push(char::from_u32(code).ok_or(JsLiteralError::InvalidEscape(position))?);
+            }
+            'u' => string.push(self.parse_unicode_escape(position)?),
+            // The line continuation.
+            '\n' | '\u{2028}' | '\u{2029}' => {}
+            '\r' => {
+                if self.peek() == Some(b'\n') {
+                    self.pos += 1;
+                }
+            }
+            '0'..='9' => return Err(JsLiteralError::InvalidEscape(position)),
+            // `\'`, `\"`, `\\`, `\/` and the other characters stand for themselves.
+            c => string.push(c),
+        }
+
+        Ok(())
+    }
+
+    /// Parse the `XXXX` or `{X…}` after `\u`, including the surrogate pairs.
+    fn parse_unicode_escape(&mut self, position: usize) -> JsLiteralResult<char> {
+        if self.peek() == Some(b'{') {
+            self.pos += 1;
+            let len = self
+                .rest()
+                .find('}')
+                .ok_or(JsLiteralError::InvalidEscape(position))?;
+            let code = u32::from_str_radix(&self.rest()[..len], 16)
+                .map_err(|_| JsLiteralError::InvalidEscape(position))?;
+            self.pos += len + 1;
+            return char::from_u32(code).ok_or(JsLiteralError::InvalidEscape(position));
+        }
+
+        let high = self
+            .parse_hex(4)
+            .ok_or(JsLiteralError::InvalidEscape(position))?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or(JsLiteralError::InvalidEscape(position));
+        }
+
+        // The high surrogate should be followed by the low one.
+        if !self.rest().starts_with("\\u") {
+            return Err(JsLiteralError::InvalidEscape(position));
+        }
+        self.pos += 2;
+        let low = self
+            .parse_hex(4)
+            .filter(|low| (0xDC00..0xE000).contains(low))
+            .ok_or(JsLiteralError::InvalidEscape(position))?;
+
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+            .ok_or(JsLiteralError::InvalidEscape(position))
+    }
+
+    fn parse_hex(&mut self, len: usize) -> Option<u32> {
+        let digits = self.rest().get(..len)?;
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        self.pos += len;
+        u32::from_str_radix(digits, 16).ok()
+    }
+
+    fn parse_number(&mut self) -> JsLiteralResult<Json> {
+        let position = self.pos;
+        let rest = self.rest();
+
+        let (negative, unsigned) = match rest.as_bytes()[0] {
+            b'-' => (true, &rest[1..]),
+            b'+' => (false, &rest[1..]),
+            _ => (false, rest),
+        };
+        let sign_len = rest.len() - unsigned.len();
+
+        if unsigned.starts_with("Infinity") {
+            self.pos += sign_len + "Infinity".len();
+            return Ok(Json::Null);
+        }
+
+        if let Some(hex) = unsigned
+            .strip_prefix("0x")
+            .or_else(|| unsigned.strip_prefix("0X"))
+        {
+            let len = hex
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(hex.len());
+            let value = i64::from_str_radix(&hex[..len], 16)
+                .map_err(|_| JsLiteralError::InvalidNumber(position))?;
+            self.pos += sign_len + 2 + len;
+            return Ok(Json::from(if negative { -value } else { value }));
+        }
+
+        let len = unsigned
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')))
+            .unwrap_or(unsigned.len());
+        let literal = &unsigned[..len];
+        self.pos += sign_len + len;
+
+        let is_integer = literal.bytes().all(|b| b.is_ascii_digit());
+        let number = match (is_integer, negative) {
+            (true, false) => literal.parse::<u64>().ok().map(Number::from),
+            (true, true) => format!("-{literal}").parse::<i64>().ok().map(Number::from),
+            _ => None,
+        };
+        if let Some(number) = number {
+            return Ok(Json::Number(number));
+        }
+
+        // The float, or the integer too large for `i64` and `u64`.
+        let float = literal
+            .parse::<f64>()
+            .ok()
+            .filter(|_| literal.bytes().any(|b| b.is_ascii_digit()))
+            .ok_or(JsLiteralError::InvalidNumber(position))?;
+        let float = if negative { -float } else { float };
+
+        Ok(Number::from_f64(float)
+            .map(Json::Number)
+            .unwrap_or(Json::Null))
+    }
+
+    fn parse_keyword(&mut self) -> JsLiteralResult<Json> {
+        const KEYWORDS: [(&str, Json); 6] = [
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+            ("null", Json::Null),
+            ("undefined", Json::Null),
+            ("NaN", Json::Null),
+            ("Infinity", Json::Null),
+        ];
+
+        let rest = self.rest();
+        for (keyword, value) in KEYWORDS {
+            let is_whole_word = rest.strip_prefix(keyword).map_or(false, |after| {
+                !after.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == '$')
+            });
+
+            if is_whole_word {
+                self.pos += keyword.len();
+                return Ok(value);
+            }
+        }
+
+        Err(self.unexpected("a value"))
+    }
+}
+
+/// Error when parsing the loose JSON.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum JsLiteralError {
+    #[error("unexpected “{found}” at byte {position}: expected {expected}")]
+    UnexpectedChar {
+        found: char,
+        position: usize,
+        expected: &'static str,
+    },
+
+    #[error("unexpected end of input: expected {expected}")]
+    UnexpectedEnd { expected: &'static str },
+
+    #[error("invalid escape sequence at byte {0}")]
+    InvalidEscape(usize),
+
+    #[error("invalid number at byte {0}")]
+    InvalidNumber(usize),
+
+    #[error("too deeply nested at byte {0}: over {MAX_DEPTH} levels")]
+    TooDeep(usize),
+}
+
+/// The [`Result`] of this module.
+pub type JsLiteralResult<T> = Result<T, JsLiteralError>;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_js_literal, parse_jsonp, JsLiteralError};
+
+    #[test]
+    fn test_parse_single_quoted() {
+        let value = parse_js_literal(
+            r#"{'itemlist':[{'info1':'Don\'t Stop "Me" Now','singer_list':[]}],'sum':1,'ret':0}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "itemlist": [{ "info1": "Don't Stop \"Me\" Now", "singer_list": [] }],
+                "sum": 1,
+                "ret": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_js_syntax() {
+        let value = parse_js_literal(
+            r"
+            // The comment before the value.
+            {
+                id: 0x1F, /* a hexadecimal number */
+                ratio: .5,
+                delta: -1e3,
+                positive: +2,
+                missing: undefined,
+                escaped: '\x41é\u{1F3B5}🎵\/\v',
+                continued: 'a\
+b',
+                list: [1, 2, 3,],
+            }
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "id": 31,
+                "ratio": 0.5,
+                "delta": -1000.0,
+                "positive": 2,
+                "missing": null,
+                "escaped": "Aé🎵🎵/\u{b}",
+                "continued": "ab",
+                "list": [1, 2, 3],
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_jsonp() {
+        let expected = json!({ "title": "Smile :) (Live)", "note": "a)b" });
+
+        for data in [
+            r#"cb({"title": "Smile :) (Live)", "note": "a)b"})"#,
+            r#"/**/ window.jQuery_1.cb ( {'title': 'Smile :) (Live)', 'note': 'a)b'} ); // done"#,
+            r#"cb({"title": "Smile :) (Live)", "note": "a)b"});;"#,
+            r#"{"title": "Smile :) (Live)", "note": "a)b"}"#,
+        ] {
+            assert_eq!(parse_jsonp(data).unwrap(), expected, "{data}");
+        }
+
+        assert_eq!(parse_jsonp("cb(null)").unwrap(), json!(null));
+        assert_eq!(parse_jsonp("null").unwrap(), json!(null));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_jsonp("cb({'a': 1}"),
+            Err(JsLiteralError::UnexpectedEnd {
+                expected: "“)” closing the callback"
+            })
+        );
+        assert_eq!(
+            parse_js_literal("{'a': 'b}"),
+            Err(JsLiteralError::UnexpectedEnd {
+                expected: "the closing quote"
+            })
+        );
+        assert_eq!(
+            parse_js_literal(r"'\u12'"),
+            Err(JsLiteralError::InvalidEscape(1))
+        );
+        assert!(matches!(
+            parse_js_literal("{'a': 1} trailing"),
+            Err(JsLiteralError::UnexpectedChar { position: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_nested() {
+        let nested = |depth: usize| format!("{}1{}", "[{a:".repeat(depth), "}]".repeat(depth));
+
+        // 64 arrays and 64 objects.
+        let value = parse_js_literal(&nested(64)).unwrap();
+        assert_eq!(value.pointer(&"/0/a".repeat(64)), Some(&json!(1)));
+
+        // The 129th level is rejected, even in the JSONP.
+        assert_eq!(
+            parse_js_literal(&format!("[{}]", nested(64))),
+            Err(JsLiteralError::TooDeep(254))
+        );
+        let deep = "[".repeat(100_000);
+        assert_eq!(parse_js_literal(&deep), Err(JsLiteralError::TooDeep(128)));
+        assert_eq!(
+            parse_jsonp(&format!("cb({deep})")),
+            Err(JsLiteralError::TooDeep(131))
+        );
+    }
+}
@@ -1,79 +1,232 @@
+pub mod client;
+pub mod cookie;
+pub mod dns;
 pub mod ext;
+pub mod profile;
+pub mod proxy;
+pub mod throttle;
+pub mod transport;
 
 // FIXME: separate to a crate.
 pub mod json;
 
+use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::Duration;
 
 use cached::proc_macro::cached;
-use http::header::{HeaderMap, HeaderValue};
-use reqwest::{Client, ClientBuilder, Proxy};
+pub use client::{Client, RequestBuilder, Response};
+use concat_string::concat_string;
 use thiserror::Error;
+use unm_types::Context;
 use url::Url;
 
-/// Build the base of [`ClientBuilder`] for UNM to reuse.
-fn build_client_builder() -> ClientBuilder {
-    Client::builder()
-        .timeout(Duration::from_secs(10))
-        .default_headers({
-            let mut header_map = HeaderMap::with_capacity(4);
+use crate::profile::{effective_profile, UserAgentRotation};
+use crate::proxy::ProxySelector;
+use crate::throttle::{throttle_cache_key, Throttle};
 
-            header_map.insert(
-                http::header::ACCEPT,
-                HeaderValue::from_static("application/json, text/plain, */*"),
-            );
-            header_map.insert(
-                http::header::ACCEPT_ENCODING,
-                HeaderValue::from_static("gzip, deflate"),
-            );
-            header_map.insert(
-                http::header::ACCEPT_LANGUAGE,
-                HeaderValue::from_static("zh-CN,zh;q=0.9"),
-            );
-            header_map.insert(http::header::USER_AGENT, HeaderValue::from_static("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/66.0.3359.181 Safari/537.36"));
+/// Construct a [`reqwest::ClientBuilder`] with the TLS backend
+/// of the features: rustls with `pure-rust`, and native-tls otherwise.
+///
+/// rustls takes precedence if both backends are enabled.
+#[cfg(feature = "pure-rust")]
+pub fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().use_rustls_tls()
+}
 
-            header_map
-        })
+/// Construct a [`reqwest::ClientBuilder`] with the TLS backend
+/// of the features: rustls with `pure-rust`, and native-tls otherwise.
+#[cfg(not(feature = "pure-rust"))]
+pub fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
 }
 
-/// Build a client with the specified proxy.
+/// Build a client for `engine` with the client profile, proxy, host
+/// mapping and DNS settings in the `ctx`.
+///
+/// See [`unm_types::ClientProfiles`] for the timeouts and headers,
+/// [`unm_types::ProxyConfig`] for how the proxy is selected,
+/// [`Context::host_mapping`] for how the hosts are translated,
+/// and [`Context::dns`] for how the hosts are resolved.
 #[cached(
-    size = "10", // allow 10 entries
+    size = "32", // allow 32 entries
     time = "600", // store for 10 minutes
     time_refresh = true,
     result = true,
 
     // Allow using reference as parameter
     key = "String",
-    convert = r#"{ proxy.unwrap_or("").to_string() }"#,
+    convert = r#"{ client_cache_key(ctx, engine) }"#,
 )]
-pub fn build_client(proxy: Option<&str>) -> RequestModuleResult<Client> {
-    let mut builder = build_client_builder();
+pub fn build_client(ctx: &Context, engine: &str) -> RequestModuleResult<Client> {
+    let profile = effective_profile(ctx, engine);
+    let builder = profile::build_client_builder(&profile)?;
+    let (mut builder, url_host_mapping) = apply_network_settings(builder, ctx, engine)?;
 
-    // Set the proxy if the user specified it.
-    if let Some(proxy) = proxy {
-        if !proxy.is_empty() {
-            builder =
-                builder.proxy(Proxy::all(proxy).map_err(RequestModuleError::ProxyConstructFailed)?);
-        }
+    // Store the cookies in the jar of this engine.
+    let cookie_jar = cookie::cookie_jar(ctx, engine);
+    builder = builder.cookie_provider(cookie_jar.clone());
+
+    let throttle = Throttle::new(&ctx.throttle, engine);
+    let user_agents = UserAgentRotation::new(&profile)?;
+    let read_timeout = profile.read_timeout_ms.map(Duration::from_millis);
+
+    builder
+        .build()
+        .map(|client| {
+            Client::new(
+                client,
+                url_host_mapping,
+                throttle,
+                user_agents,
+                read_timeout,
+                cookie_jar,
+            )
+        })
+        .map_err(RequestModuleError::ConstructClientFailed)
+}
+
+/// Build a client forwarding the requests of others as they are,
+/// for example, the requests to a proxy server.
+///
+/// The proxy, host mapping, DNS settings and limits of `engine`
+/// apply as [`build_client`] does, but the requests are sent without
+/// the headers, user agents and cookies of `engine`, the redirects
+/// are returned instead of followed, and the bodies are not decompressed.
+/// Only the connect and read timeouts of the client profile apply,
+/// so a long download is not cut off.
+pub fn build_forward_client(ctx: &Context, engine: &str) -> RequestModuleResult<Client> {
+    let profile = effective_profile(ctx, engine);
+    let mut builder = client_builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .no_gzip()
+        .no_brotli()
+        .no_deflate();
+    if let Some(connect_timeout) = profile.connect_timeout_ms {
+        builder = builder.connect_timeout(Duration::from_millis(connect_timeout));
     }
+    let (builder, url_host_mapping) = apply_network_settings(builder, ctx, engine)?;
 
     builder
         .build()
+        .map(|client| {
+            Client::new(
+                client,
+                url_host_mapping,
+                Throttle::new(&ctx.throttle, engine),
+                None,
+                profile.read_timeout_ms.map(Duration::from_millis),
+                // An empty jar which is never persisted.
+                Arc::new(cookie::CookieJar::open(engine, None)),
+            )
+        })
         .map_err(RequestModuleError::ConstructClientFailed)
 }
 
+/// Apply the proxy, DNS settings and host mapping of `engine`
+/// in the `ctx` to the `builder`.
+///
+/// It returns the hosts to replace in the URL of requests,
+/// which are the ones not mapped to an IP address.
+fn apply_network_settings(
+    mut builder: reqwest::ClientBuilder,
+    ctx: &Context,
+    engine: &str,
+) -> RequestModuleResult<(reqwest::ClientBuilder, HashMap<String, String>)> {
+    // Set the proxy if the user specified it.
+    if let Some(proxy) = ProxySelector::new(ctx, engine)?.into_proxy()? {
+        builder = builder.proxy(proxy);
+    }
+
+    // Resolve the hosts with our resolver if the user configured it.
+    if let Some(resolver) = dns::shared_resolver(&ctx.dns)? {
+        builder = builder.dns_resolver(Arc::new(resolver));
+    }
+
+    // The hosts mapped to an IP address are resolved to the address,
+    // so the URL, and thus the `Host` header and SNI, are untouched.
+    // Other hosts are replaced in the URL when sending requests.
+    let mut url_host_mapping = HashMap::new();
+    for (src, tgt) in ctx.host_mapping.iter() {
+        match parse_ip_addr(tgt) {
+            Some(ip) => builder = builder.resolve(src, SocketAddr::new(ip, 0)),
+            None => {
+                url_host_mapping.insert(src.clone(), tgt.clone());
+            }
+        }
+    }
+
+    Ok((builder, url_host_mapping))
+}
+
+/// The key of [`build_client`] cache, which is comprised of the proxy
+/// and client profile of `engine`, the host mapping, the DNS settings,
+/// the limits and where the cookies are persisted.
+fn client_cache_key(ctx: &Context, engine: &str) -> String {
+    let mut host_mapping = ctx
+        .host_mapping
+        .iter()
+        .map(|(src, tgt)| concat_string!(src, "=", tgt))
+        .collect::<Vec<_>>();
+    host_mapping.sort_unstable();
+
+    concat_string!(
+        engine,
+        "|",
+        ctx.proxy_uri.as_deref().unwrap_or(""),
+        "|",
+        ctx.proxy
+            .engines
+            .get(engine)
+            .map(String::as_str)
+            .unwrap_or(""),
+        "|",
+        format!("{:?}|{:?}", ctx.proxy.rules, ctx.proxy.no_proxy),
+        "|",
+        host_mapping.join(","),
+        "|",
+        dns::resolver_cache_key(&ctx.dns),
+        "|",
+        throttle_cache_key(&ctx.throttle, engine),
+        "|",
+        ctx.cookie_dir
+            .as_deref()
+            .map(|dir| dir.to_string_lossy())
+            .unwrap_or_default(),
+        "|",
+        format!("{:?}", effective_profile(ctx, engine))
+    )
+}
+
+/// Parse the IP address, which can be surrounded by brackets (IPv6).
+fn parse_ip_addr(addr: &str) -> Option<IpAddr> {
+    addr.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .ok()
+}
+
 /// Translate the specified host to the user-specified one.
 ///
 /// `map` must be comprised of the `(src, tgt)` pair.
 #[inline]
-pub fn translate_host<'a>(map: &'a HashMap<&str, &str>, host: &'a str) -> &'a str {
-    map.get(&host).unwrap_or(&host)
+pub fn translate_host<'a, K, V>(map: &'a HashMap<K, V>, host: &'a str) -> &'a str
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+{
+    map.get(host).map(AsRef::as_ref).unwrap_or(host)
 }
 
 /// The [`translate_host`] wrapper for [`Url`].
-pub fn translate_url(map: &HashMap<&str, &str>, url: &mut Url) -> RequestModuleResult<()> {
+pub fn translate_url<K, V>(map: &HashMap<K, V>, url: &mut Url) -> RequestModuleResult<()>
+where
+    K: Borrow<str> + Hash + Eq,
+    V: AsRef<str>,
+{
     let host = url.host_str();
 
     if let Some(host) = host {
@@ -91,6 +244,9 @@ pub enum RequestModuleError {
     #[error("invalid header value: {0}")]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
 
+    #[error("invalid header name: {0}")]
+    InvalidHeaderName(#[from] http::header::InvalidHeaderName),
+
     #[error("invalid host: {0}")]
     InvalidHost(url::ParseError),
 
@@ -99,6 +255,45 @@ pub enum RequestModuleError {
 
     #[error("failed to construct proxy: {0}")]
     ProxyConstructFailed(reqwest::Error),
+
+    #[error("failed to read or write the cookie jar {0}: {1}")]
+    CookieJarIoFailed(String, std::io::Error),
+
+    #[error("invalid cookie jar {0}: {1}")]
+    InvalidCookieJar(String, serde_json::Error),
+
+    #[error("the session of “{engine}” has expired (cookies: {}). Please update its cookie in the config.", .cookies.join(", "))]
+    SessionExpired {
+        engine: String,
+        cookies: Vec<String>,
+    },
+
+    #[error("invalid DNS settings: {0}")]
+    InvalidDnsSettings(#[from] dns::DnsModuleError),
+
+    #[error("failed to construct request: {0}")]
+    ConstructRequestFailed(reqwest::Error),
+
+    #[error("failed to send request: {0}")]
+    RequestFailed(reqwest::Error),
+
+    #[error("failed to construct the replayed response: {0}")]
+    ConstructResponseFailed(http::Error),
+
+    #[error("failed to read or write the cassette {0}: {1}")]
+    CassetteIoFailed(String, std::io::Error),
+
+    #[error("invalid cassette {0}: {1}")]
+    InvalidCassette(String, serde_json::Error),
+
+    #[error("no recorded interaction for {method} {url}")]
+    NoRecordedInteraction { method: String, url: String },
+
+    #[error("received nothing from the upstream in {0:?}")]
+    ReadTimeout(Duration),
+
+    #[error("failed to read the response: {0}")]
+    ReadResponseFailed(reqwest::Error),
 }
 
 /// The [`Result`] of this module.
@@ -134,6 +329,376 @@ mod tests {
         }
     }
 
+    mod build_client {
+        use std::collections::HashMap;
+        use std::time::Duration;
+
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+        use unm_types::{ClientProfile, ClientProfiles, ContextBuilder, HttpVersion, ProxyConfig};
+
+        use super::super::{build_client, build_forward_client, RequestModuleError};
+
+        /// Start a one-shot HTTP server that responds with the request head it received.
+        async fn echo_server() -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let head = String::from_utf8_lossy(&buf[..len]).to_lowercase();
+
+                stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{head}",
+                            head.len()
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            });
+
+            port
+        }
+
+        /// Start a one-shot HTTP server that sends the body of `chunks` bytes
+        /// byte by byte, waiting for `interval` before each byte.
+        async fn trickle_server(chunks: usize, interval: Duration) -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+
+                stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {chunks}\r\nconnection: close\r\n\r\n"
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                for _ in 0..chunks {
+                    tokio::time::sleep(interval).await;
+                    if stream.write_all(b"x").await.is_err() {
+                        return;
+                    }
+                }
+            });
+
+            port
+        }
+
+        /// Start a one-shot HTTPS server with a self-signed certificate,
+        /// which speaks HTTP/2 if the client chooses it in ALPN.
+        async fn tls_server() -> u16 {
+            use std::sync::Arc;
+
+            use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            let mut config = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![Certificate(cert.serialize_der().unwrap())],
+                    PrivateKey(cert.serialize_private_key_der()),
+                )
+                .unwrap();
+            config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+            let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            tokio::spawn(async move {
+                let (stream, _) = listener.accept().await.unwrap();
+                let stream = acceptor.accept(stream).await.unwrap();
+                let h2 = stream.get_ref().1.alpn_protocol() == Some(b"h2");
+
+                let service = hyper::service::service_fn(|_| async {
+                    Ok::<_, std::convert::Infallible>(hyper::Response::new(hyper::Body::empty()))
+                });
+                let _ = hyper::server::conn::Http::new()
+                    .http2_only(h2)
+                    .serve_connection(stream, service)
+                    .await;
+            });
+
+            port
+        }
+
+        fn client_with_profile(engine: &str, profile: ClientProfile) -> super::super::Client {
+            let ctx = ContextBuilder::default()
+                .client_profiles(
+                    ClientProfiles::builder()
+                        .engines(HashMap::from([(engine.to_string(), profile)]))
+                        .build(),
+                )
+                .build()
+                .unwrap();
+
+            build_client(&ctx, engine).unwrap()
+        }
+
+        async fn request_with_mapping(src: &str, tgt: &str) -> String {
+            let port = echo_server().await;
+            let ctx = ContextBuilder::default()
+                .host_mapping(HashMap::from([(src.to_string(), tgt.to_string())]))
+                .build()
+                .unwrap();
+
+            build_client(&ctx, "example")
+                .unwrap()
+                .get(format!("http://{src}:{port}/ping"))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+                .replace(&port.to_string(), "PORT")
+        }
+
+        #[tokio::test]
+        async fn test_host_mapped_to_ip_keeps_host_header() {
+            let head = request_with_mapping("www.kuwo.invalid", "127.0.0.1").await;
+
+            assert!(head.starts_with("get /ping http/1.1"));
+            assert!(head.contains("host: www.kuwo.invalid:PORT"));
+        }
+
+        #[tokio::test]
+        async fn test_forward_client_sends_request_as_is() {
+            let port = echo_server().await;
+            let ctx = ContextBuilder::default()
+                .host_mapping(HashMap::from([(
+                    "music.163.invalid".to_string(),
+                    "127.0.0.1".to_string(),
+                )]))
+                .client_profiles(
+                    ClientProfiles::builder()
+                        .default(
+                            ClientProfile::builder()
+                                .user_agents(vec!["unm-a".to_string()])
+                                .headers([("x-unm".to_string(), "1".to_string())].into())
+                                .build(),
+                        )
+                        .build(),
+                )
+                .build()
+                .unwrap();
+
+            let head = build_forward_client(&ctx, "ncm")
+                .unwrap()
+                .get(format!("http://music.163.invalid:{port}/ping"))
+                .header("user-agent", "NeteaseMusic")
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+
+            assert!(head.starts_with("get /ping http/1.1"));
+            assert!(head.contains(&format!("host: music.163.invalid:{port}")));
+            assert!(head.contains("user-agent: neteasemusic"));
+            assert!(!head.contains("x-unm"));
+            assert!(!head.contains("accept-encoding"));
+        }
+
+        #[tokio::test]
+        async fn test_engine_proxy_override() {
+            let port = echo_server().await;
+            let ctx = ContextBuilder::default()
+                .proxy(
+                    ProxyConfig::builder()
+                        .engines(HashMap::from([(
+                            "ytdl".to_string(),
+                            format!("http://127.0.0.1:{port}"),
+                        )]))
+                        .no_proxy(vec!["localhost".to_string()])
+                        .build(),
+                )
+                .build()
+                .unwrap();
+
+            // The echo server is the proxy, so it receives the absolute URL.
+            let head = build_client(&ctx, "ytdl")
+                .unwrap()
+                .get("http://www.youtube.invalid/ping")
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+
+            assert!(head.starts_with("get http://www.youtube.invalid/ping http/1.1"));
+        }
+
+        #[tokio::test]
+        async fn test_client_profile() {
+            let ctx = ContextBuilder::default()
+                .client_profiles(
+                    ClientProfiles::builder()
+                        .engines(HashMap::from([(
+                            "kugou".to_string(),
+                            ClientProfile::builder()
+                                .user_agents(vec!["unm-a".to_string(), "unm-b".to_string()])
+                                .headers([("x-unm".to_string(), "1".to_string())].into())
+                                .build(),
+                        )]))
+                        .build(),
+                )
+                .build()
+                .unwrap();
+            let client = build_client(&ctx, "kugou").unwrap();
+
+            let mut heads = Vec::new();
+            for _ in 0..2 {
+                let port = echo_server().await;
+                heads.push(
+                    client
+                        .get(format!("http://127.0.0.1:{port}/ping"))
+                        .send()
+                        .await
+                        .unwrap()
+                        .text()
+                        .await
+                        .unwrap(),
+                );
+            }
+
+            assert!(heads.iter().all(|head| head.contains("x-unm: 1")));
+            assert!(heads[0].contains("user-agent: unm-a"));
+            assert!(heads[1].contains("user-agent: unm-b"));
+        }
+
+        #[tokio::test]
+        async fn test_user_agent_in_headers_over_rotation() {
+            let client = client_with_profile(
+                "migu",
+                ClientProfile::builder()
+                    .user_agents(vec!["unm-a".to_string(), "unm-b".to_string()])
+                    .headers([("User-Agent".to_string(), "unm-fixed".to_string())].into())
+                    .build(),
+            );
+
+            for _ in 0..2 {
+                let port = echo_server().await;
+                let head = client
+                    .get(format!("http://127.0.0.1:{port}/ping"))
+                    .send()
+                    .await
+                    .unwrap()
+                    .text()
+                    .await
+                    .unwrap();
+
+                assert!(head.contains("user-agent: unm-fixed"));
+            }
+        }
+
+        #[tokio::test]
+        async fn test_http_version_negotiation() {
+            let get_version = |profile: ClientProfile, url: String| async move {
+                // The certificate of the test server is self-signed.
+                crate::profile::build_client_builder(&profile)
+                    .unwrap()
+                    .danger_accept_invalid_certs(true)
+                    .build()
+                    .unwrap()
+                    .get(url)
+                    .send()
+                    .await
+                    .unwrap()
+                    .version()
+            };
+
+            // HTTP/2 is negotiated with ALPN on the TLS connections.
+            let port = tls_server().await;
+            let version = get_version(
+                ClientProfile::default(),
+                format!("https://localhost:{port}/ping"),
+            )
+            .await;
+            assert_eq!(version, http::Version::HTTP_2);
+
+            // `http2` in the settings is the same as `auto`.
+            let profile: ClientProfile =
+                serde_json::from_str(r#"{ "http_version": "http2" }"#).unwrap();
+            assert_eq!(profile.http_version, Some(HttpVersion::Auto));
+            let port = tls_server().await;
+            let version = get_version(profile, format!("https://localhost:{port}/ping")).await;
+            assert_eq!(version, http::Version::HTTP_2);
+
+            let port = tls_server().await;
+            let version = get_version(
+                ClientProfile::builder()
+                    .http_version(Some(HttpVersion::Http1))
+                    .build(),
+                format!("https://localhost:{port}/ping"),
+            )
+            .await;
+            assert_eq!(version, http::Version::HTTP_11);
+
+            // HTTP/1.1 is used on the plain-text connections
+            // without the prior knowledge.
+            let port = echo_server().await;
+            let version = get_version(
+                ClientProfile::default(),
+                format!("http://127.0.0.1:{port}/ping"),
+            )
+            .await;
+            assert_eq!(version, http::Version::HTTP_11);
+        }
+
+        #[tokio::test]
+        async fn test_read_timeout() {
+            let client = client_with_profile(
+                "bilibili",
+                ClientProfile::builder().read_timeout_ms(Some(500)).build(),
+            );
+
+            // The body is slow but steady, so it is read in time.
+            let port = trickle_server(4, Duration::from_millis(200)).await;
+            let response = client
+                .get(format!("http://127.0.0.1:{port}/steady"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.text().await.unwrap(), "xxxx");
+
+            // The body stalls.
+            let port = trickle_server(1, Duration::from_secs(5)).await;
+            let response = client
+                .get(format!("http://127.0.0.1:{port}/stall"))
+                .send()
+                .await
+                .unwrap();
+            assert!(matches!(
+                response.bytes().await,
+                Err(RequestModuleError::ReadTimeout(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_host_mapped_to_domain_rewrites_url() {
+            let head = request_with_mapping("u.y.qq.invalid", "localhost").await;
+
+            assert!(head.starts_with("get /ping http/1.1"));
+            assert!(head.contains("host: localhost:PORT"));
+        }
+    }
+
     mod translate_url {
         use url::Url;
 
@@ -0,0 +1,238 @@
+//! The HTTP client profiles configured in [`ClientProfiles`].
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use http::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use reqwest::ClientBuilder;
+use unm_types::{ClientProfile, ClientProfiles, Context, HttpVersion};
+
+use crate::RequestModuleResult;
+
+/// The default timeout of a whole request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default user agent.
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/66.0.3359.181 Safari/537.36";
+
+/// Get the profile of `engine`, which is the default profile
+/// overridden by the fields set in the profile of `engine`.
+pub fn effective_profile(ctx: &Context, engine: &str) -> ClientProfile {
+    let ClientProfiles {
+        default, engines, ..
+    } = &ctx.client_profiles;
+
+    let Some(profile) = engines.get(engine) else {
+        return default.clone();
+    };
+
+    let mut headers = default.headers.clone();
+    headers.extend(profile.headers.clone());
+
+    ClientProfile::builder()
+        .connect_timeout_ms(profile.connect_timeout_ms.or(default.connect_timeout_ms))
+        .timeout_ms(profile.timeout_ms.or(default.timeout_ms))
+        .read_timeout_ms(profile.read_timeout_ms.or(default.read_timeout_ms))
+        .user_agents(if profile.user_agents.is_empty() {
+            default.user_agents.clone()
+        } else {
+            profile.user_agents.clone()
+        })
+        .headers(headers)
+        .http_version(profile.http_version.or(default.http_version))
+        .pool_max_idle_per_host(
+            profile
+                .pool_max_idle_per_host
+                .or(default.pool_max_idle_per_host),
+        )
+        .build()
+}
+
+/// Build the base of [`ClientBuilder`] with the `profile`.
+pub(crate) fn build_client_builder(profile: &ClientProfile) -> RequestModuleResult<ClientBuilder> {
+    let mut builder = crate::client_builder()
+        .timeout(
+            profile
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_TIMEOUT),
+        )
+        .default_headers(default_headers(profile)?);
+
+    if let Some(connect_timeout) = profile.connect_timeout_ms {
+        builder = builder.connect_timeout(Duration::from_millis(connect_timeout));
+    }
+
+    // Both TLS backends offer HTTP/2 and HTTP/1.1 in ALPN
+    // unless the client is restricted to HTTP/1.1.
+    match profile.http_version.unwrap_or_default() {
+        HttpVersion::Http1 => builder = builder.http1_only(),
+        HttpVersion::Http2PriorKnowledge => builder = builder.http2_prior_knowledge(),
+        _ => {}
+    }
+
+    if let Some(max) = profile.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+
+    Ok(builder)
+}
+
+fn default_headers(profile: &ClientProfile) -> RequestModuleResult<HeaderMap> {
+    let mut header_map = HeaderMap::with_capacity(4 + profile.headers.len());
+
+    header_map.insert(
+        http::header::ACCEPT,
+        HeaderValue::from_static("application/json, text/plain, */*"),
+    );
+    header_map.insert(
+        http::header::ACCEPT_ENCODING,
+        HeaderValue::from_static("gzip, deflate"),
+    );
+    header_map.insert(
+        http::header::ACCEPT_LANGUAGE,
+        HeaderValue::from_static("zh-CN,zh;q=0.9"),
+    );
+    header_map.insert(
+        http::header::USER_AGENT,
+        match profile.user_agents.first() {
+            Some(user_agent) => HeaderValue::from_str(user_agent)?,
+            None => HeaderValue::from_static(DEFAULT_USER_AGENT),
+        },
+    );
+
+    // The `User-Agent` in the headers overrides the `user_agents`.
+    for (name, value) in profile.headers.iter() {
+        header_map.insert(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+
+    Ok(header_map)
+}
+
+/// The user agents used in rotation.
+#[derive(Debug)]
+pub(crate) struct UserAgentRotation {
+    user_agents: Vec<HeaderValue>,
+    next: AtomicUsize,
+}
+
+impl UserAgentRotation {
+    /// Construct the rotation of the user agents in `profile`.
+    ///
+    /// It returns `None` if there is no need to rotate, since the only
+    /// user agent has been in the default headers, or `User-Agent` is
+    /// set in the headers of `profile`, which takes precedence.
+    pub(crate) fn new(profile: &ClientProfile) -> RequestModuleResult<Option<Self>> {
+        if profile.user_agents.len() < 2 {
+            return Ok(None);
+        }
+
+        if profile
+            .headers
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case(USER_AGENT.as_str()))
+        {
+            log::warn!(
+                "Both `user_agents` and `User-Agent` in `headers` are set. Using the latter."
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            user_agents: profile
+                .user_agents
+                .iter()
+                .map(|v| HeaderValue::from_str(v))
+                .collect::<Result<_, _>>()?,
+            next: AtomicUsize::new(0),
+        }))
+    }
+
+    /// Get the next user agent.
+    pub(crate) fn next(&self) -> HeaderValue {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.user_agents.len();
+        self.user_agents[index].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use unm_types::{ClientProfile, ClientProfiles, ContextBuilder, HttpVersion};
+
+    use super::{default_headers, effective_profile, UserAgentRotation};
+
+    #[test]
+    fn test_effective_profile() {
+        let ctx = ContextBuilder::default()
+            .client_profiles(
+                ClientProfiles::builder()
+                    .default(
+                        ClientProfile::builder()
+                            .timeout_ms(Some(5000))
+                            .headers(BTreeMap::from([
+                                ("accept-language".to_string(), "en".to_string()),
+                                ("x-a".to_string(), "1".to_string()),
+                            ]))
+                            .build(),
+                    )
+                    .engines(HashMap::from([(
+                        "ytdl".to_string(),
+                        ClientProfile::builder()
+                            .timeout_ms(Some(30000))
+                            .headers(BTreeMap::from([("x-a".to_string(), "2".to_string())]))
+                            .read_timeout_ms(Some(3000))
+                            .http_version(Some(HttpVersion::Http1))
+                            .build(),
+                    )]))
+                    .build(),
+            )
+            .build()
+            .unwrap();
+
+        let kuwo = effective_profile(&ctx, "kuwo");
+        assert_eq!(kuwo.timeout_ms, Some(5000));
+        assert_eq!(kuwo.http_version, None);
+
+        let ytdl = effective_profile(&ctx, "ytdl");
+        assert_eq!(ytdl.timeout_ms, Some(30000));
+        assert_eq!(ytdl.read_timeout_ms, Some(3000));
+        assert_eq!(ytdl.http_version, Some(HttpVersion::Http1));
+        assert_eq!(ytdl.headers["accept-language"], "en");
+        assert_eq!(ytdl.headers["x-a"], "2");
+    }
+
+    #[test]
+    fn test_user_agent_rotation() {
+        let single = ClientProfile::builder()
+            .user_agents(vec!["a".to_string()])
+            .build();
+        assert!(UserAgentRotation::new(&single).unwrap().is_none());
+
+        let multiple = ClientProfile::builder()
+            .user_agents(vec!["a".to_string(), "b".to_string()])
+            .build();
+        let rotation = UserAgentRotation::new(&multiple).unwrap().unwrap();
+        assert_eq!(
+            [rotation.next(), rotation.next(), rotation.next()],
+            ["a", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn test_user_agent_in_headers_disables_rotation() {
+        let profile = ClientProfile::builder()
+            .user_agents(vec!["a".to_string(), "b".to_string()])
+            .headers(BTreeMap::from([(
+                "User-Agent".to_string(),
+                "c".to_string(),
+            )]))
+            .build();
+        assert!(UserAgentRotation::new(&profile).unwrap().is_none());
+        assert_eq!(default_headers(&profile).unwrap()["user-agent"], "c");
+    }
+}
@@ -0,0 +1,255 @@
+//! The proxy selection with [`Context::proxy_uri`] and [`ProxyConfig`].
+//!
+//! See [`ProxyConfig`] for the precedence of the settings.
+
+use reqwest::Proxy;
+use unm_types::{Context, ProxyConfig};
+use url::Url;
+
+use crate::{RequestModuleError, RequestModuleResult};
+
+/// The proxy selector of an engine.
+#[derive(Clone, Debug)]
+pub struct ProxySelector {
+    no_proxy: Vec<HostPattern>,
+    rules: Vec<(Vec<HostPattern>, String)>,
+    fallback: Option<String>,
+}
+
+impl ProxySelector {
+    /// Construct the proxy selector of `engine` with the settings in `ctx`.
+    ///
+    /// Every proxy URI is validated here, so [`Self::select`] won't fail.
+    pub fn new(ctx: &Context, engine: &str) -> RequestModuleResult<Self> {
+        let ProxyConfig {
+            engines,
+            rules,
+            no_proxy,
+            ..
+        } = &ctx.proxy;
+
+        let fallback = engines
+            .get(engine)
+            .map(String::as_str)
+            .or(ctx.proxy_uri.as_deref())
+            .map(ToString::to_string);
+
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                (
+                    rule.hosts.iter().map(HostPattern::new).collect(),
+                    rule.uri.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for uri in rules.iter().map(|(_, uri)| uri).chain(fallback.iter()) {
+            if !uri.is_empty() {
+                Proxy::all(uri).map_err(RequestModuleError::ProxyConstructFailed)?;
+            }
+        }
+
+        Ok(Self {
+            no_proxy: no_proxy.iter().map(HostPattern::new).collect(),
+            rules,
+            fallback,
+        })
+    }
+
+    /// Select the proxy URI for the request to `host`.
+    ///
+    /// It returns an empty string if we should connect directly,
+    /// and `None` if there is no proxy setting at all.
+    pub fn select(&self, host: &str) -> Option<&str> {
+        if self.no_proxy.iter().any(|p| p.matches(host)) {
+            return Some("");
+        }
+
+        self.rules
+            .iter()
+            .find(|(patterns, _)| patterns.iter().any(|p| p.matches(host)))
+            .map(|(_, uri)| uri.as_str())
+            .or(self.fallback.as_deref())
+    }
+
+    /// Does this selector always return the fallback proxy?
+    fn is_host_independent(&self) -> bool {
+        self.no_proxy.is_empty() && self.rules.is_empty()
+    }
+
+    /// Construct the [`Proxy`] for [`reqwest::ClientBuilder`].
+    ///
+    /// It returns `None` if there is no proxy setting at all,
+    /// so that the proxy in the environment variables still works.
+    pub(crate) fn into_proxy(self) -> RequestModuleResult<Option<Proxy>> {
+        if self.is_host_independent() {
+            match self.fallback.as_deref() {
+                None => return Ok(None),
+                Some(uri) if !uri.is_empty() => {
+                    return Proxy::all(uri)
+                        .map(Some)
+                        .map_err(RequestModuleError::ProxyConstructFailed)
+                }
+                // Connect directly, which is handled below.
+                Some(_) => {}
+            }
+        }
+
+        Ok(Some(Proxy::custom(move |url| {
+            self.select(url.host_str().unwrap_or_default())
+                .filter(|uri| !uri.is_empty())
+                .and_then(|uri| Url::parse(uri).ok())
+        })))
+    }
+}
+
+/// Get the proxy URI of `engine` for the request to `host`,
+/// for example, to pass to the subprocess of an engine.
+///
+/// See [`ProxySelector::select`] for the returned value.
+pub fn proxy_uri_for(
+    ctx: &Context,
+    engine: &str,
+    host: &str,
+) -> RequestModuleResult<Option<String>> {
+    Ok(ProxySelector::new(ctx, engine)?
+        .select(host)
+        .map(ToString::to_string))
+}
+
+/// The host pattern in [`ProxyConfig`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum HostPattern {
+    /// Match every host.
+    Any,
+    /// Match the domain and its subdomains.
+    Domain(String),
+    /// Match the exact host.
+    Exact(String),
+}
+
+impl HostPattern {
+    pub(crate) fn new(pattern: impl AsRef<str>) -> Self {
+        let pattern = pattern.as_ref().trim().to_ascii_lowercase();
+
+        if pattern == "*" {
+            Self::Any
+        } else if let Some(domain) = pattern
+            .strip_prefix("*.")
+            .or_else(|| pattern.strip_prefix('.'))
+        {
+            Self::Domain(domain.to_string())
+        } else {
+            Self::Exact(pattern)
+        }
+    }
+
+    pub(crate) fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        match self {
+            Self::Any => true,
+            Self::Domain(domain) => {
+                host == *domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .map_or(false, |sub| sub.ends_with('.'))
+            }
+            Self::Exact(exact) => host == *exact,
+        }
+    }
+
+    /// How specific this pattern is. The higher, the more specific.
+    pub(crate) fn specificity(&self) -> usize {
+        match self {
+            Self::Any => 0,
+            Self::Domain(domain) => domain.len() + 1,
+            Self::Exact(_) => usize::MAX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use unm_types::{ContextBuilder, ProxyConfig, ProxyRule};
+
+    use super::{HostPattern, ProxySelector};
+
+    #[test]
+    fn test_host_pattern() {
+        let pattern = HostPattern::new("*.kuwo.cn");
+        assert!(pattern.matches("kuwo.cn"));
+        assert!(pattern.matches("www.KUWO.cn"));
+        assert!(!pattern.matches("notkuwo.cn"));
+
+        assert_eq!(HostPattern::new(".kuwo.cn"), pattern);
+        assert!(HostPattern::new("*").matches("u.y.qq.com"));
+        assert!(HostPattern::new("u.y.qq.com").matches("u.y.qq.com"));
+        assert!(!HostPattern::new("u.y.qq.com").matches("y.qq.com"));
+    }
+
+    #[test]
+    fn test_select() {
+        let ctx = ContextBuilder::default()
+            .proxy_uri(Some("http://global.invalid:8080".into()))
+            .proxy(
+                ProxyConfig::builder()
+                    .engines(HashMap::from([
+                        ("kuwo".to_string(), String::new()),
+                        ("ytdl".to_string(), "socks5://127.0.0.1:1080".to_string()),
+                    ]))
+                    .rules(vec![ProxyRule::builder()
+                        .hosts(vec!["*.googlevideo.com".to_string()])
+                        .uri("http://video.invalid:8080".to_string())
+                        .build()])
+                    .no_proxy(vec!["localhost".to_string()])
+                    .build(),
+            )
+            .build()
+            .unwrap();
+
+        let kuwo = ProxySelector::new(&ctx, "kuwo").unwrap();
+        assert_eq!(kuwo.select("www.kuwo.cn"), Some(""));
+
+        let ytdl = ProxySelector::new(&ctx, "ytdl").unwrap();
+        assert_eq!(
+            ytdl.select("www.youtube.com"),
+            Some("socks5://127.0.0.1:1080")
+        );
+        assert_eq!(
+            ytdl.select("rr1.googlevideo.com"),
+            Some("http://video.invalid:8080")
+        );
+        assert_eq!(ytdl.select("localhost"), Some(""));
+
+        let joox = ProxySelector::new(&ctx, "joox").unwrap();
+        assert_eq!(
+            joox.select("api.joox.com"),
+            Some("http://global.invalid:8080")
+        );
+
+        let unconfigured = ProxySelector::new(&Default::default(), "joox").unwrap();
+        assert_eq!(unconfigured.select("api.joox.com"), None);
+    }
+
+    #[test]
+    fn test_invalid_proxy() {
+        let ctx = ContextBuilder::default()
+            .proxy(
+                ProxyConfig::builder()
+                    .engines(HashMap::from([(
+                        "qq".to_string(),
+                        "not a proxy".to_string(),
+                    )]))
+                    .build(),
+            )
+            .build()
+            .unwrap();
+
+        assert!(ProxySelector::new(&ctx, "qq").is_err());
+        assert!(ProxySelector::new(&ctx, "kuwo").is_ok());
+    }
+}
@@ -0,0 +1,536 @@
+//! The rate limits and concurrency caps of the upstream hosts.
+//!
+//! The limits configured in [`ThrottleConfig`] are kept in a global
+//! registry, so every [`Client`](crate::Client) built with the same
+//! settings shares the same token buckets and semaphores.
+//!
+//! Besides, when an upstream host responds `429 Too Many Requests` or
+//! `503 Service Unavailable` with `Retry-After`, the later requests to
+//! this host are delayed until then, whatever the limits are.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use http::header::RETRY_AFTER;
+use http::StatusCode;
+use once_cell::sync::Lazy;
+use reqwest::Response;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use unm_types::{ThrottleConfig, UpstreamLimit};
+
+use crate::proxy::HostPattern;
+
+/// The longest `Retry-After` we respect, to avoid stalling forever.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// The longest delay for the rate limits, in case of the extremely low rates.
+const MAX_RATE_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// The limiters, keyed by the host (and the engine if the limit is of an engine).
+///
+/// The idle limiters are dropped when a new one is added, so it doesn't
+/// grow with every host requested.
+static LIMITERS: Lazy<Mutex<HashMap<String, Arc<Limiter>>>> = Lazy::new(Default::default);
+
+/// When the requests to each host can be sent again.
+/// The expired ones are dropped when a new one is added.
+static RETRY_AFTERS: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(Default::default);
+
+/// The throttling policy of a client, which is built for an engine.
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    engine: String,
+    /// The limits of host patterns, from the most specific one.
+    hosts: Vec<(HostPattern, UpstreamLimit)>,
+    engine_limit: Option<UpstreamLimit>,
+}
+
+/// The permit to send a request, which should be held until it is done.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    _concurrency: Option<OwnedSemaphorePermit>,
+}
+
+impl Throttle {
+    pub(crate) fn new(config: &ThrottleConfig, engine: &str) -> Self {
+        let mut hosts = config
+            .hosts
+            .iter()
+            .map(|(pattern, limit)| (HostPattern::new(pattern), *limit))
+            .collect::<Vec<_>>();
+        hosts.sort_by_key(|(pattern, _)| std::cmp::Reverse(pattern.specificity()));
+
+        Self {
+            engine: engine.to_string(),
+            hosts,
+            engine_limit: config.engines.get(engine).copied(),
+        }
+    }
+
+    /// Get the limiter of `host`, if there is any limit of it.
+    fn limiter(&self, host: &str) -> Option<Arc<Limiter>> {
+        let (key, limit) = match self.hosts.iter().find(|(p, _)| p.matches(host)) {
+            Some((_, limit)) => (host.to_string(), *limit),
+            None => (format!("{}@{host}", self.engine), self.engine_limit?),
+        };
+
+        let mut limiters = LIMITERS.lock().expect("limiters lock poisoned");
+        if let Some(limiter) = limiters.get(&key).filter(|l| l.limit == limit) {
+            return Some(limiter.clone());
+        }
+
+        // It is a new host, or the limit has been changed.
+        // Start over with the new one, and drop the idle ones.
+        limiters.retain(|_, limiter| !limiter.is_idle());
+        let limiter = Arc::new(Limiter::new(limit));
+        limiters.insert(key, limiter.clone());
+
+        Some(limiter)
+    }
+
+    /// Wait until we can send a request to `host`.
+    pub(crate) async fn acquire(&self, host: &str) -> Permit {
+        wait_retry_after(host).await;
+
+        let Some(limiter) = self.limiter(host) else {
+            return Permit { _concurrency: None };
+        };
+
+        let concurrency = match &limiter.semaphore {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        if let Some(bucket) = &limiter.bucket {
+            let delay = bucket.lock().expect("bucket lock poisoned").reserve();
+            if !delay.is_zero() {
+                log::debug!("Delaying the request to “{host}” for {delay:?} (rate limited).");
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        Permit {
+            _concurrency: concurrency,
+        }
+    }
+}
+
+/// Delay the later requests to `host` if `response` asks us to retry later.
+pub(crate) fn observe(host: &str, response: &Response) {
+    if !matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return;
+    }
+
+    let Some(delay) = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_retry_after)
+    else {
+        return;
+    };
+
+    delay_host(host, delay);
+}
+
+fn delay_host(host: &str, delay: Duration) {
+    let delay = delay.min(MAX_RETRY_AFTER);
+    log::warn!("“{host}” asked us to retry after {delay:?}. Delaying the later requests.");
+
+    let now = Instant::now();
+    let until = now + delay;
+    let mut retry_afters = RETRY_AFTERS.lock().expect("retry-after lock poisoned");
+    retry_afters.retain(|_, until| *until > now);
+    retry_afters
+        .entry(host.to_string())
+        .and_modify(|v| *v = (*v).max(until))
+        .or_insert(until);
+}
+
+async fn wait_retry_after(host: &str) {
+    let until = {
+        let mut retry_afters = RETRY_AFTERS.lock().expect("retry-after lock poisoned");
+        match retry_afters.get(host) {
+            Some(until) if *until > Instant::now() => *until,
+            Some(_) => {
+                retry_afters.remove(host);
+                return;
+            }
+            None => return,
+        }
+    };
+
+    log::debug!("Waiting for the Retry-After of “{host}”…");
+    tokio::time::sleep_until(until).await;
+}
+
+/// Parse the `Retry-After` value, which can be
+/// the seconds to wait or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    httpdate::parse_http_date(value)
+        .ok()
+        .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[derive(Debug)]
+struct Limiter {
+    limit: UpstreamLimit,
+    bucket: Option<Mutex<TokenBucket>>,
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl Limiter {
+    fn new(limit: UpstreamLimit) -> Self {
+        Self {
+            limit,
+            bucket: limit
+                .requests_per_second
+                .filter(|rate| {
+                    let valid = rate.is_finite() && *rate > 0.0;
+                    if !valid {
+                        log::warn!("Ignoring the invalid rate limit: {rate} requests per second");
+                    }
+                    valid
+                })
+                .map(|rate| Mutex::new(TokenBucket::new(rate, limit.burst.unwrap_or(1)))),
+            semaphore: limit
+                .max_concurrency
+                .map(|permits| Arc::new(Semaphore::new(permits.max(1)))),
+        }
+    }
+
+    /// Whether this limiter is neither used nor limiting any request,
+    /// so dropping it is the same as keeping it.
+    fn is_idle(self: &Arc<Self>) -> bool {
+        let no_permits = self
+            .semaphore
+            .as_ref()
+            .map_or(true, |semaphore| Arc::strong_count(semaphore) == 1);
+        let full_bucket = self.bucket.as_ref().map_or(true, |bucket| {
+            bucket.lock().expect("bucket lock poisoned").is_full()
+        });
+
+        Arc::strong_count(self) == 1 && no_permits && full_bucket
+    }
+}
+
+/// The token bucket, which hands out the tokens in advance:
+/// the waiting time of a request is reserved when it comes,
+/// so the requests are sent in order.
+#[derive(Debug)]
+struct TokenBucket {
+    /// How many tokens are refilled per second.
+    rate: f64,
+    capacity: f64,
+    /// The available tokens, which is negative if reserved in advance.
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            updated_at: Instant::now(),
+        }
+    }
+
+    /// Take a token, and return how long to wait before using it.
+    fn reserve(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity) - 1.0;
+        self.updated_at = now;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::try_from_secs_f64(-self.tokens / self.rate)
+                .unwrap_or(MAX_RATE_DELAY)
+                .min(MAX_RATE_DELAY)
+        }
+    }
+
+    /// Whether the bucket has been refilled to its capacity.
+    fn is_full(&self) -> bool {
+        let elapsed = Instant::now().duration_since(self.updated_at).as_secs_f64();
+        self.tokens + elapsed * self.rate >= self.capacity
+    }
+}
+
+/// The key of [`Throttle`] for the cache of [`crate::build_client`].
+pub(crate) fn throttle_cache_key(config: &ThrottleConfig, engine: &str) -> String {
+    let mut hosts = config
+        .hosts
+        .iter()
+        .map(|(pattern, limit)| format!("{pattern}={limit:?}"))
+        .collect::<Vec<_>>();
+    hosts.sort_unstable();
+
+    format!("{}|{:?}", hosts.join(","), config.engines.get(engine))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+    use unm_types::{ContextBuilder, ThrottleConfig, UpstreamLimit};
+
+    use super::{
+        delay_host,
+        parse_retry_after,
+        Limiter,
+        Throttle,
+        TokenBucket,
+        LIMITERS,
+        MAX_RATE_DELAY,
+        RETRY_AFTERS,
+    };
+    use crate::transport::cassette::{Cassette, RecordedRequest, RecordedResponse};
+    use crate::transport::{scope, ReplayTransport};
+    use crate::{build_client, Context};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(2.0, 2);
+
+        assert_eq!(bucket.reserve(), Duration::ZERO);
+        assert_eq!(bucket.reserve(), Duration::ZERO);
+        assert_eq!(bucket.reserve(), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(), Duration::from_millis(1000));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(bucket.reserve(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_extreme_rate() {
+        let mut bucket = TokenBucket::new(f64::MIN_POSITIVE, 1);
+        assert_eq!(bucket.reserve(), Duration::ZERO);
+        assert_eq!(bucket.reserve(), MAX_RATE_DELAY);
+
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let limit = UpstreamLimit::builder()
+                .requests_per_second(Some(rate))
+                .build();
+            assert!(Limiter::new(limit).bucket.is_none());
+        }
+    }
+
+    #[test]
+    fn test_deserialize_rate() {
+        let limit: UpstreamLimit =
+            serde_json::from_str(r#"{"requests_per_second": 2.5, "burst": 3}"#).unwrap();
+        assert_eq!(limit.requests_per_second, Some(2.5));
+        assert_eq!(limit.burst, Some(3));
+
+        let limit: UpstreamLimit = serde_json::from_str(r#"{"burst": 3}"#).unwrap();
+        assert_eq!(limit.requests_per_second, None);
+
+        for rate in ["0", "-1", "1e999"] {
+            let json = format!(r#"{{"requests_per_second": {rate}}}"#);
+            assert!(serde_json::from_str::<UpstreamLimit>(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_host_limit_over_engine_limit() {
+        let config = ThrottleConfig::builder()
+            .hosts(HashMap::from([(
+                "*.kuwo.invalid".to_string(),
+                UpstreamLimit::builder()
+                    .requests_per_second(Some(1.0))
+                    .build(),
+            )]))
+            .engines(HashMap::from([(
+                "kuwo".to_string(),
+                UpstreamLimit::builder().max_concurrency(Some(1)).build(),
+            )]))
+            .build();
+        let throttle = Throttle::new(&config, "kuwo");
+
+        // Limited by the host: one request per second.
+        let start = Instant::now();
+        for _ in 0..3 {
+            throttle.acquire("www.kuwo.invalid").await;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+
+        // Limited by the engine: one request at a time.
+        let permit = throttle.acquire("mobi.kuwo.elsewhere.invalid").await;
+        assert!(tokio::time::timeout(
+            Duration::from_secs(1),
+            throttle.acquire("mobi.kuwo.elsewhere.invalid")
+        )
+        .await
+        .is_err());
+        drop(permit);
+        throttle.acquire("mobi.kuwo.elsewhere.invalid").await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_after() {
+        let throttle = Throttle::default();
+        delay_host("u.y.qq.invalid", Duration::from_secs(30));
+
+        let start = Instant::now();
+        throttle.acquire("u.y.qq.invalid").await;
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+
+        // The other hosts are not affected.
+        let start = Instant::now();
+        throttle.acquire("c.y.qq.invalid").await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_respect_retry_after_response() {
+        let url = "http://www.kuwo.retry.invalid/search";
+        let mut cassette = Cassette::default();
+        for status in [429, 200] {
+            cassette.push(
+                RecordedRequest {
+                    method: "GET".to_string(),
+                    url: url.to_string(),
+                    body: None,
+                    headers: Default::default(),
+                },
+                RecordedResponse {
+                    status,
+                    headers: [("retry-after".to_string(), "15".to_string())].into(),
+                    body: Some(String::new()),
+                    json: None,
+                },
+            );
+        }
+
+        scope(Arc::new(ReplayTransport::new(cassette)), async {
+            let client = build_client(&Context::default(), "kuwo").unwrap();
+
+            let start = Instant::now();
+            let response = client.get(url).send().await.unwrap();
+            assert_eq!(response.status(), 429);
+
+            // Only the 429/503 responses are respected.
+            client.get(url).send().await.unwrap();
+            assert_eq!(start.elapsed(), Duration::from_secs(15));
+        })
+        .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_hold_permit_until_body_read() {
+        let url = "http://www.migu.permit.invalid/search";
+        let mut cassette = Cassette::default();
+        for _ in 0..2 {
+            cassette.push(
+                RecordedRequest {
+                    method: "GET".to_string(),
+                    url: url.to_string(),
+                    body: None,
+                    headers: Default::default(),
+                },
+                RecordedResponse {
+                    status: 200,
+                    headers: Default::default(),
+                    body: Some("ok".to_string()),
+                    json: None,
+                },
+            );
+        }
+
+        scope(Arc::new(ReplayTransport::new(cassette)), async {
+            let context = ContextBuilder::default()
+                .throttle(
+                    ThrottleConfig::builder()
+                        .hosts(HashMap::from([(
+                            "www.migu.permit.invalid".to_string(),
+                            UpstreamLimit::builder().max_concurrency(Some(1)).build(),
+                        )]))
+                        .build(),
+                )
+                .build()
+                .unwrap();
+            let client = build_client(&context, "migu").unwrap();
+
+            // The body of the first response has not been read.
+            let response = client.get(url).send().await.unwrap();
+            assert!(
+                tokio::time::timeout(Duration::from_secs(1), client.get(url).send())
+                    .await
+                    .is_err()
+            );
+
+            assert_eq!(response.text().await.unwrap(), "ok");
+            client.get(url).send().await.unwrap();
+        })
+        .await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_evict_idle_limiters() {
+        let config = ThrottleConfig::builder()
+            .hosts(HashMap::from([(
+                "*.joox.evict.invalid".to_string(),
+                UpstreamLimit::builder()
+                    .requests_per_second(Some(1.0))
+                    .build(),
+            )]))
+            .build();
+        let throttle = Throttle::new(&config, "joox");
+        let contains = |host: &str| LIMITERS.lock().unwrap().contains_key(host);
+
+        // The bucket of "a" is still refilling, so it is kept.
+        throttle.acquire("a.joox.evict.invalid").await;
+        throttle.acquire("b.joox.evict.invalid").await;
+        assert!(contains("a.joox.evict.invalid"));
+
+        // It has been refilled, so it is dropped when a new one is added.
+        tokio::time::advance(Duration::from_secs(2)).await;
+        throttle.acquire("c.joox.evict.invalid").await;
+        assert!(!contains("a.joox.evict.invalid"));
+        assert!(!contains("b.joox.evict.invalid"));
+        assert!(contains("c.joox.evict.invalid"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_evict_expired_retry_afters() {
+        let contains = |host: &str| RETRY_AFTERS.lock().unwrap().contains_key(host);
+
+        delay_host("a.kugou.evict.invalid", Duration::from_secs(1));
+        tokio::time::advance(Duration::from_secs(2)).await;
+        delay_host("b.kugou.evict.invalid", Duration::from_secs(1));
+
+        assert!(!contains("a.kugou.evict.invalid"));
+        assert!(contains("b.kugou.evict.invalid"));
+    }
+}
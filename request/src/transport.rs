@@ -0,0 +1,351 @@
+//! The pluggable transport that sends the requests of [`Client`].
+//!
+//! By default, the requests are sent to the Internet with [`LiveTransport`].
+//! You can run a future with another transport with [`scope`], for example,
+//! replaying the recorded responses in tests:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use unm_request::build_client;
+//! use unm_request::transport::{scope, ReplayTransport};
+//! use unm_types::Context;
+//!
+//! # async fn run() -> unm_request::RequestModuleResult<()> {
+//! let transport = Arc::new(ReplayTransport::open("fixtures/search.json")?);
+//!
+//! let response = scope(transport, async {
+//!     let client = build_client(&Context::default(), "example")?;
+//!     client.get("https://example.com/search?q=1").send().await
+//! })
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Client`]: crate::Client
+
+pub mod cassette;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::{Request, Response};
+
+use self::cassette::{Cassette, RecordedRequest, RecordedResponse};
+use crate::{RequestModuleError, RequestModuleResult};
+
+tokio::task_local! {
+    static TRANSPORT: Arc<dyn Transport>;
+}
+
+/// The transport that sends a built request and returns its response.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send the `request` that is built by the `client`.
+    async fn execute(
+        &self,
+        client: &reqwest::Client,
+        request: Request,
+    ) -> RequestModuleResult<Response>;
+}
+
+/// Run the `future` with the specified `transport`.
+///
+/// All the requests sent with [`crate::Client`] in this future
+/// (and not in a spawned task) use this transport.
+pub async fn scope<F: std::future::Future>(transport: Arc<dyn Transport>, future: F) -> F::Output {
+    TRANSPORT.scope(transport, future).await
+}
+
+/// Get the transport of the current scope.
+///
+/// It is [`LiveTransport`] if we are not in any [`scope`].
+pub(crate) fn current() -> Arc<dyn Transport> {
+    TRANSPORT
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| Arc::new(LiveTransport))
+}
+
+/// The transport sending requests to the Internet.
+pub struct LiveTransport;
+
+#[async_trait]
+impl Transport for LiveTransport {
+    async fn execute(
+        &self,
+        client: &reqwest::Client,
+        request: Request,
+    ) -> RequestModuleResult<Response> {
+        client
+            .execute(request)
+            .await
+            .map_err(RequestModuleError::RequestFailed)
+    }
+}
+
+/// The transport sending requests to the Internet,
+/// and recording the interactions to a cassette file.
+///
+/// The cassette file is overwritten.
+pub struct RecordTransport {
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl RecordTransport {
+    /// Record the interactions to the cassette at `path`.
+    pub fn create<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            cassette: Mutex::new(Cassette::default()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The requests sent with this transport, in order.
+    ///
+    /// Unlike the ones in the cassette, the credentials are not redacted.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Transport for RecordTransport {
+    async fn execute(
+        &self,
+        client: &reqwest::Client,
+        request: Request,
+    ) -> RequestModuleResult<Response> {
+        log::debug!("Recording {} {}…", request.method(), request.url());
+
+        let recorded_request = RecordedRequest::from_request(&request);
+        let response = LiveTransport.execute(client, request).await?;
+        let recorded_response = RecordedResponse::from_response(response).await?;
+        let replayed_response = recorded_response.to_response()?;
+
+        let mut cassette = self.cassette.lock().expect("cassette lock poisoned");
+        cassette.push(recorded_request.redacted(), recorded_response);
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .push(recorded_request);
+        cassette.save(&self.path)?;
+
+        Ok(replayed_response)
+    }
+}
+
+/// The transport responding with the interactions recorded
+/// in a cassette file, without touching the Internet.
+///
+/// A request matches an interaction with the same URL. If there is
+/// no such an interaction, it matches the first interaction with the
+/// same method, host and path, since some engines put volatile values
+/// (such as timestamps) into the query. Every interaction is replayed
+/// only once.
+pub struct ReplayTransport {
+    interactions: Mutex<Vec<Option<(RecordedRequest, RecordedResponse)>>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl ReplayTransport {
+    /// Replay the interactions in the cassette at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> RequestModuleResult<Self> {
+        Ok(Self::new(Cassette::load(path.as_ref())?))
+    }
+
+    /// Replay the interactions in the `cassette`.
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            interactions: Mutex::new(
+                cassette
+                    .interactions
+                    .into_iter()
+                    .map(|i| Some((i.request, i.response)))
+                    .collect(),
+            ),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The requests received by this transport, in order,
+    /// for checking what the client sent.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn execute(
+        &self,
+        _: &reqwest::Client,
+        request: Request,
+    ) -> RequestModuleResult<Response> {
+        log::debug!("Replaying {} {}…", request.method(), request.url());
+        self.requests
+            .lock()
+            .expect("requests lock poisoned")
+            .push(RecordedRequest::from_request(&request));
+
+        let mut interactions = self.interactions.lock().expect("cassette lock poisoned");
+        let position = {
+            let unused = || {
+                interactions
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, i)| i.as_ref().map(|(req, _)| (idx, req)))
+            };
+
+            unused()
+                .find(|(_, req)| req.matches_exactly(&request))
+                .or_else(|| unused().find(|(_, req)| req.matches_loosely(&request)))
+                .map(|(idx, _)| idx)
+        };
+
+        let (_, response) = position
+            .and_then(|idx| interactions[idx].take())
+            .ok_or_else(|| RequestModuleError::NoRecordedInteraction {
+                method: request.method().to_string(),
+                url: request.url().to_string(),
+            })?;
+
+        response.to_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use http::header::COOKIE;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use unm_types::Context;
+
+    use super::cassette::Cassette;
+    use super::{scope, RecordTransport, ReplayTransport};
+    use crate::{build_client, RequestModuleError};
+
+    const CASSETTE: &str = r#"{
+        "interactions": [
+            {
+                "request": { "method": "GET", "url": "https://example.com/search?q=a&_=1" },
+                "response": { "status": 200, "json": { "result": "a" } }
+            },
+            {
+                "request": { "method": "GET", "url": "https://example.com/search?q=b&_=1" },
+                "response": { "status": 200, "body": "jsonp({\"result\": \"b\"})" }
+            }
+        ]
+    }"#;
+
+    fn replay_transport() -> Arc<ReplayTransport> {
+        Arc::new(ReplayTransport::new(
+            serde_json::from_str::<Cassette>(CASSETTE).unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_replay_exact_match() {
+        let body = scope(replay_transport(), async {
+            let client = build_client(&Context::default(), "example").unwrap();
+            let response = client
+                .get("https://example.com/search?q=b&_=1")
+                .send()
+                .await
+                .unwrap();
+
+            response.text().await.unwrap()
+        })
+        .await;
+
+        assert_eq!(body, r#"jsonp({"result": "b"})"#);
+    }
+
+    #[tokio::test]
+    async fn test_replay_loose_match_and_exhaustion() {
+        scope(replay_transport(), async {
+            let client = build_client(&Context::default(), "example").unwrap();
+            let send = || client.get("https://example.com/search?q=a&_=2").send();
+
+            // Matched with the first unused interaction with the same path.
+            let first = send().await.unwrap();
+            assert_eq!(first.text().await.unwrap(), r#"{"result":"a"}"#);
+            let second = send().await.unwrap();
+            assert_eq!(second.text().await.unwrap(), r#"jsonp({"result": "b"})"#);
+
+            assert!(matches!(
+                send().await,
+                Err(RequestModuleError::NoRecordedInteraction { .. })
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hello?name=unm", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 15\r\nconnection: close\r\n\r\n{\"hello\":\"unm\"}")
+                .await
+                .unwrap();
+        });
+
+        let path =
+            std::env::temp_dir().join(format!("unm-request-record-{}.json", std::process::id()));
+
+        let record_transport = Arc::new(RecordTransport::create(&path));
+        let recorded = scope(record_transport.clone(), async {
+            let client = build_client(&Context::default(), "example").unwrap();
+            client
+                .get(&url)
+                .header(COOKIE, "session=secret")
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        })
+        .await;
+        assert_eq!(recorded, r#"{"hello":"unm"}"#);
+        assert_eq!(
+            record_transport.requests()[0].headers["cookie"],
+            "session=secret"
+        );
+
+        // The credentials are not saved.
+        let cassette = std::fs::read_to_string(&path).unwrap();
+        assert!(cassette.contains(r#""cookie": "<redacted>""#));
+        assert!(!cassette.contains("secret"));
+
+        // The server has gone. This must be replayed.
+        let replay_transport = Arc::new(ReplayTransport::open(&path).unwrap());
+        let replayed = scope(replay_transport.clone(), async {
+            let client = build_client(&Context::default(), "example").unwrap();
+            client.get(&url).send().await.unwrap().text().await.unwrap()
+        })
+        .await;
+        assert_eq!(replayed, r#"{"hello":"unm"}"#);
+        assert_eq!(replay_transport.requests()[0].url, url);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! The cassette file storing the recorded HTTP interactions.
+//!
+//! A cassette is a JSON file like:
+//!
+//! ```json
+//! {
+//!     "interactions": [
+//!         {
+//!             "request": { "method": "GET", "url": "https://example.com/?q=1" },
+//!             "response": {
+//!                 "status": 200,
+//!                 "headers": { "content-type": "application/json" },
+//!                 "json": { "result": [] }
+//!             }
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! The response body is stored in `json` if it is a valid JSON,
+//! so we can read and edit it easily. Otherwise, it is stored
+//! as the plain text in `body`.
+//!
+//! The cassettes written by hand instead of recorded from the real
+//! services are marked with `"synthetic": true`.
+//!
+//! The request headers are recorded for reference, with the values of
+//! the credentials (such as `Cookie`) redacted. They are not used for
+//! matching.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{RequestModuleError, RequestModuleResult};
+
+/// The recorded HTTP interactions.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    /// Whether the interactions are written by hand
+    /// instead of recorded from the real services.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic: bool,
+    /// The interactions, in the order they were recorded.
+    pub interactions: Vec<Interaction>,
+}
+
+/// A pair of the recorded request and its response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// The recorded request.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// The method of this request, for example, `GET`.
+    pub method: String,
+    /// The full URL of this request.
+    pub url: String,
+    /// The body of this request, if it is a text.
+    ///
+    /// It is for reference only and not used for matching.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// The headers of this request.
+    ///
+    /// It is for reference only and not used for matching.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+/// The recorded response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    /// The status code of this response.
+    pub status: u16,
+    /// The headers of this response.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The body of this response, if it is not a JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// The body of this response, if it is a JSON.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json: Option<serde_json::Value>,
+}
+
+impl Cassette {
+    /// Load the cassette from `path`.
+    pub fn load(path: &Path) -> RequestModuleResult<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| RequestModuleError::CassetteIoFailed(path.display().to_string(), e))?;
+
+        serde_json::from_str(&content)
+            .map_err(|e| RequestModuleError::InvalidCassette(path.display().to_string(), e))
+    }
+
+    /// Save the cassette to `path`.
+    pub fn save(&self, path: &Path) -> RequestModuleResult<()> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| RequestModuleError::InvalidCassette(path.display().to_string(), e))?;
+
+        std::fs::write(path, content + "\n")
+            .map_err(|e| RequestModuleError::CassetteIoFailed(path.display().to_string(), e))
+    }
+
+    /// Append an interaction to this cassette.
+    pub fn push(&mut self, request: RecordedRequest, response: RecordedResponse) {
+        self.interactions.push(Interaction { request, response });
+    }
+}
+
+/// The request headers whose values are not saved to the cassettes.
+const REDACTED_HEADERS: &[http::header::HeaderName] =
+    &[http::header::COOKIE, http::header::AUTHORIZATION];
+
+impl RecordedRequest {
+    pub(crate) fn from_request(request: &Request) -> Self {
+        Self {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            body: request
+                .body()
+                .and_then(|b| b.as_bytes())
+                .and_then(|b| std::str::from_utf8(b).ok())
+                .map(ToString::to_string),
+            headers: request
+                .headers()
+                .iter()
+                .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+                .collect(),
+        }
+    }
+
+    /// Get the copy of this request to save, whose credentials are redacted.
+    pub(crate) fn redacted(&self) -> Self {
+        let mut request = self.clone();
+        for (name, value) in request.headers.iter_mut() {
+            if REDACTED_HEADERS.iter().any(|h| h.as_str() == name) {
+                *value = "<redacted>".to_string();
+            }
+        }
+
+        request
+    }
+
+    /// Does this recorded request have the same method and URL as `request`?
+    pub(crate) fn matches_exactly(&self, request: &Request) -> bool {
+        self.method == request.method().as_str() && self.url == request.url().as_str()
+    }
+
+    /// Does this recorded request have the same method, scheme,
+    /// host and path as `request`? The query is ignored.
+    pub(crate) fn matches_loosely(&self, request: &Request) -> bool {
+        let Ok(url) = url::Url::parse(&self.url) else {
+            return false;
+        };
+        let target = request.url();
+
+        self.method == request.method().as_str()
+            && url.scheme() == target.scheme()
+            && url.host_str() == target.host_str()
+            && url.port_or_known_default() == target.port_or_known_default()
+            && url.path() == target.path()
+    }
+}
+
+impl RecordedResponse {
+    pub(crate) async fn from_response(response: Response) -> RequestModuleResult<Self> {
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            // The body we recorded has been decoded, and its length may change.
+            .filter(|(k, _)| {
+                *k != http::header::CONTENT_ENCODING && *k != http::header::CONTENT_LENGTH
+            })
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let body = response
+            .text()
+            .await
+            .map_err(RequestModuleError::RequestFailed)?;
+
+        Ok(match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(json) if json.is_object() || json.is_array() => Self {
+                status,
+                headers,
+                body: None,
+                json: Some(json),
+            },
+            _ => Self {
+                status,
+                headers,
+                body: Some(body),
+                json: None,
+            },
+        })
+    }
+
+    pub(crate) fn to_response(&self) -> RequestModuleResult<Response> {
+        let body = match (&self.json, &self.body) {
+            (Some(json), _) => json.to_string(),
+            (None, Some(body)) => body.clone(),
+            (None, None) => String::new(),
+        };
+
+        let mut builder = http::Response::builder().status(self.status);
+        for (k, v) in self.headers.iter() {
+            builder = builder.header(k, v);
+        }
+
+        let response = builder
+            .body(body)
+            .map_err(RequestModuleError::ConstructResponseFailed)?;
+
+        Ok(response.into())
+    }
+}
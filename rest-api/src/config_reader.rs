@@ -16,6 +16,33 @@ pub struct ApiConfigTomlStructure {
     pub context: Context,
     #[serde(default)]
     pub rate_limit: RateLimitConfig,
+    /// The directory of the root CA, which is shared with the proxy.
+    ///
+    /// If specified, the root CA certificate is exported at `/ca.crt`.
+    #[serde(default)]
+    pub ca_dir: Option<String>,
+    /// The HTTPS listener, whose certificate is issued by the root CA
+    /// in `ca_dir`.
+    ///
+    /// If not specified, the API is only served over HTTP.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// The configuration of serving the API over HTTPS.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    /// The address to listen on, for example, `0.0.0.0:3443`.
+    pub address: String,
+    /// The host to issue the certificate for.
+    ///
+    /// By default, it is `localhost`.
+    #[serde(default = "default_tls_host")]
+    pub host: String,
+}
+
+fn default_tls_host() -> String {
+    "localhost".to_string()
 }
 
 #[derive(Default, Debug, Deserialize)]
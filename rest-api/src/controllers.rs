@@ -2,5 +2,8 @@
 //!
 //! It redirects the request to services.
 
+#[cfg(feature = "openssl")]
+pub(crate) mod cert;
 pub(crate) mod retrieve;
 pub(crate) mod search;
+pub(crate) mod tracklist;
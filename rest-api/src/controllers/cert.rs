@@ -0,0 +1,44 @@
+//! API: `/ca.crt`
+//!
+//! The root CA for intercepting the HTTPS connections.
+
+use std::sync::Arc;
+
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use http::header::CONTENT_TYPE;
+use http::StatusCode;
+use serde_json::json;
+use tracing::{info, warn};
+use unm_api_utils::cert::CertificateAuthority;
+
+pub async fn ca_cert(
+    Extension(ca): Extension<Option<Arc<CertificateAuthority>>>,
+) -> impl IntoResponse {
+    info!("[CA] Exporting the root CA certificate");
+
+    let Some(ca) = ca else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "The root CA is not configured." })),
+        )
+            .into_response();
+    };
+
+    match ca.cert_pem() {
+        Ok(pem) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, "application/x-x509-ca-cert")],
+            pem,
+        )
+            .into_response(),
+        Err(e) => {
+            warn!("Failed to export the root CA: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": e.to_string() })),
+            )
+                .into_response()
+        }
+    }
+}
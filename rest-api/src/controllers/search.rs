@@ -17,7 +17,7 @@ pub async fn search_v1(
 ) -> impl IntoResponse {
     info!(
         "[v1][Search] Searching the song “{}” with the engines “{:?}”",
-        payload.song,
+        payload.describe(),
         payload.engines.get_engines_list()
     );
 
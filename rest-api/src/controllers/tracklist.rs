@@ -0,0 +1,45 @@
+//! API: `/api/v[n]/playlist` and `/api/v[n]/album`
+//!
+//! Supported version: `v1`.
+
+use std::sync::Arc;
+
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use tracing::info;
+use unm_types::Context;
+
+use crate::executor::tracklist::{TrackListKind, TrackListPayload};
+
+pub async fn playlist_v1(
+    Extension(default_context): Extension<Arc<Context>>,
+    Json(payload): Json<TrackListPayload>,
+) -> impl IntoResponse {
+    resolve_v1(TrackListKind::Playlist, &default_context, payload).await
+}
+
+pub async fn album_v1(
+    Extension(default_context): Extension<Arc<Context>>,
+    Json(payload): Json<TrackListPayload>,
+) -> impl IntoResponse {
+    resolve_v1(TrackListKind::Album, &default_context, payload).await
+}
+
+async fn resolve_v1(
+    kind: TrackListKind,
+    default_context: &Context,
+    payload: TrackListPayload,
+) -> axum::response::Response {
+    info!(
+        "[v1][{kind:?}] Resolving “{}”, and searching {:?} tracks",
+        payload.id.as_deref().unwrap_or_default(),
+        payload.search
+    );
+
+    let context = payload.context.construct_context(default_context.clone());
+
+    match payload.resolve(kind, &context).await {
+        Ok(response) => response.into_response(),
+        Err(e) => e.into_response(),
+    }
+}
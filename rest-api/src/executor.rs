@@ -4,15 +4,18 @@ pub(crate) mod context;
 pub(crate) mod engine;
 pub(crate) mod retrieve;
 pub(crate) mod search;
+pub(crate) mod tracklist;
 
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
 use once_cell::sync::OnceCell;
+use serde::{Deserialize, Deserializer};
 use serde_json::json;
 use thiserror::Error;
 use tracing::{debug, instrument, trace};
 use unm_engine::executor::{Executor, ExecutorError};
+use unm_ncm::api::NcmApiModuleError;
 
 static EXECUTOR: OnceCell<Executor> = OnceCell::new();
 
@@ -33,10 +36,33 @@ pub enum ApiExecutorError {
 
     #[error("Failed to retrieve: {0}")]
     RetrieveFailed(ExecutorError),
+
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(&'static str),
+
+    #[error("Failed to request NetEase Cloud Music: {0}")]
+    NcmApiFailed(NcmApiModuleError),
 }
 
 pub type ApiExecutorResult<T> = Result<T, ApiExecutorError>;
 
+/// Deserialize the NetEase Cloud Music ID, which can be a number or a string.
+pub(crate) fn deserialize_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u64),
+        String(String),
+    }
+
+    Ok(Option::<Id>::deserialize(deserializer)?.map(|id| match id {
+        Id::Number(id) => id.to_string(),
+        Id::String(id) => id,
+    }))
+}
+
 impl IntoResponse for ApiExecutorError {
     #[instrument]
     fn into_response(self) -> axum::response::Response {
@@ -48,12 +74,21 @@ impl IntoResponse for ApiExecutorError {
             ApiExecutorError::SearchFailed(executor_error) => match executor_error {
                 ExecutorError::EnginesMissing { .. } => StatusCode::UNPROCESSABLE_ENTITY,
                 ExecutorError::NoMatchedSong { .. } => StatusCode::NOT_FOUND,
+                ExecutorError::MetadataResolveError(_) => StatusCode::BAD_GATEWAY,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
             ApiExecutorError::RetrieveFailed(executor_error) => match executor_error {
                 ExecutorError::EngineResolveFailed { .. } => StatusCode::UNPROCESSABLE_ENTITY,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
+            ApiExecutorError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            ApiExecutorError::NcmApiFailed(ncm_error) => match ncm_error {
+                NcmApiModuleError::InvalidPlaylistId(_) | NcmApiModuleError::InvalidAlbumId(_) => {
+                    StatusCode::BAD_REQUEST
+                }
+                NcmApiModuleError::UnexpectedCode(Some(404)) => StatusCode::NOT_FOUND,
+                _ => StatusCode::BAD_GATEWAY,
+            },
         };
 
         (code, Json(json!({ "error": error_response }))).into_response()
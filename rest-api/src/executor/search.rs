@@ -5,7 +5,7 @@ use unm_types::{Context, Song, SongSearchInformation};
 
 use super::context::ApiContext;
 use super::engine::ApiEnginesList;
-use super::{get_unm_executor, ApiExecutorError, ApiExecutorResult};
+use super::{deserialize_id, get_unm_executor, ApiExecutorError, ApiExecutorResult};
 
 #[derive(Deserialize)]
 pub struct SearchPayload {
@@ -17,7 +17,15 @@ pub struct SearchPayload {
     pub engines: ApiEnginesList,
 
     /// The song to search.
-    pub song: Song,
+    ///
+    /// It can be omitted if `id` is specified.
+    #[serde(default)]
+    pub song: Option<Song>,
+
+    /// The NetEase Cloud Music track ID of the song to search,
+    /// whose metadata is looked up if `song` is not specified.
+    #[serde(default, deserialize_with = "deserialize_id")]
+    pub id: Option<String>,
 
     /// The context for searching.
     #[serde(default)]
@@ -39,11 +47,27 @@ impl SearchPayload {
         debug!("Searching the specified song…");
 
         let engines_list = self.engines.get_engines_list();
-        let result = get_unm_executor()
-            .search(&engines_list, &self.song, context)
-            .await
-            .map_err(ApiExecutorError::SearchFailed)?;
+        let executor = get_unm_executor();
+        let result = match (&self.song, &self.id) {
+            (Some(song), _) => executor.search(&engines_list, song, context).await,
+            (None, Some(id)) => executor.search_by_id(&engines_list, id, context).await,
+            (None, None) => {
+                return Err(ApiExecutorError::InvalidPayload(
+                    "either “song” or “id” should be specified",
+                ))
+            }
+        }
+        .map_err(ApiExecutorError::SearchFailed)?;
 
         Ok(Json(result))
     }
+
+    /// Describe what to search, for logging.
+    pub fn describe(&self) -> String {
+        match (&self.song, &self.id) {
+            (Some(song), _) => song.to_string(),
+            (None, Some(id)) => format!("#{id}"),
+            (None, None) => String::new(),
+        }
+    }
 }
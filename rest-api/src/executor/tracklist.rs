@@ -0,0 +1,138 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use unm_ncm::{Track, TrackList};
+use unm_types::{Context, SongSearchInformation};
+
+use super::context::ApiContext;
+use super::engine::ApiEnginesList;
+use super::{deserialize_id, get_unm_executor, ApiExecutorError, ApiExecutorResult};
+
+/// The kind of the track list.
+#[derive(Clone, Copy, Debug)]
+pub enum TrackListKind {
+    Playlist,
+    Album,
+}
+
+/// Which tracks to search after resolving the track list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchTarget {
+    /// Search the tracks unavailable on NetEase Cloud Music only.
+    Unavailable,
+    /// Search all the tracks.
+    All,
+}
+
+#[derive(Deserialize)]
+pub struct TrackListPayload {
+    /// The NetEase Cloud Music ID of the playlist or album.
+    #[serde(default, deserialize_with = "deserialize_id")]
+    pub id: Option<String>,
+
+    /// Which tracks to search. If not specified, we don't search.
+    #[serde(default)]
+    pub search: Option<SearchTarget>,
+
+    /// The list with the engines to search with.
+    ///
+    /// If not specified, we use all the supported engines.
+    /// For more information, see [`ApiEnginesList`].
+    #[serde(default)]
+    pub engines: ApiEnginesList,
+
+    /// The context for resolving and searching.
+    #[serde(default)]
+    pub context: ApiContext,
+}
+
+/// The resolved track list, with the search results.
+#[derive(Serialize)]
+pub struct TrackListResponse {
+    pub id: String,
+    pub name: String,
+    pub tracks: Vec<TrackResponse>,
+}
+
+#[derive(Serialize)]
+pub struct TrackResponse {
+    #[serde(flatten)]
+    pub track: Track,
+
+    /// The search result of this track, if it has been searched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_result: Option<SongSearchInformation>,
+
+    /// Why the search of this track failed, if it has been searched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_error: Option<String>,
+}
+
+impl TrackListPayload {
+    /// Resolve the playlist or album to the tracks,
+    /// and search the tracks specified in `search`.
+    pub async fn resolve(
+        &self,
+        kind: TrackListKind,
+        context: &Context,
+    ) -> ApiExecutorResult<Json<TrackListResponse>> {
+        debug!("Resolving the {kind:?}…");
+
+        let id = self
+            .id
+            .as_deref()
+            .ok_or(ApiExecutorError::InvalidPayload("“id” should be specified"))?;
+
+        let TrackList {
+            id, name, tracks, ..
+        } = match kind {
+            TrackListKind::Playlist => unm_ncm::get_playlist(id, context).await,
+            TrackListKind::Album => unm_ncm::get_album(id, context).await,
+        }
+        .map_err(ApiExecutorError::NcmApiFailed)?;
+
+        let mut tracks = tracks
+            .into_iter()
+            .map(|track| TrackResponse {
+                track,
+                search_result: None,
+                search_error: None,
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(target) = self.search {
+            self.search_tracks(target, &mut tracks, context).await;
+        }
+
+        Ok(Json(TrackListResponse { id, name, tracks }))
+    }
+
+    async fn search_tracks(
+        &self,
+        target: SearchTarget,
+        tracks: &mut [TrackResponse],
+        context: &Context,
+    ) {
+        let mut targets = tracks
+            .iter_mut()
+            .filter(|t| target == SearchTarget::All || t.track.unavailable)
+            .collect::<Vec<_>>();
+        debug!("Searching {} tracks…", targets.len());
+
+        let songs = targets
+            .iter()
+            .map(|t| t.track.song.clone())
+            .collect::<Vec<_>>();
+        let results = get_unm_executor()
+            .search_batch(&self.engines.get_engines_list(), &songs, context)
+            .await;
+
+        for (track, result) in targets.iter_mut().zip(results) {
+            match result {
+                Ok(result) => track.search_result = Some(result),
+                Err(e) => track.search_error = Some(e.to_string()),
+            }
+        }
+    }
+}
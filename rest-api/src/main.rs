@@ -3,6 +3,8 @@ pub(crate) mod controllers;
 pub(crate) mod executor;
 pub(crate) mod retrieve;
 pub(crate) mod schema;
+#[cfg(feature = "openssl")]
+pub(crate) mod tls;
 
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -17,9 +19,20 @@ use serde_json::{json, Value};
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 use tracing::{debug, info, warn};
+#[cfg(feature = "openssl")]
+use unm_api_utils::cert::CertificateAuthority;
 use unm_types::ContextBuilder;
 
-use crate::config_reader::{ApiConfigTomlStructure, ExternalConfigReader};
+use crate::config_reader::{ApiConfigTomlStructure, ExternalConfigReader, TlsConfig};
+
+/// The root CA shared with the proxy.
+#[cfg(feature = "openssl")]
+type RootCa = Arc<CertificateAuthority>;
+
+/// The root CA needs OpenSSL to load, so it is never available.
+#[cfg(not(feature = "openssl"))]
+#[derive(Clone)]
+enum RootCa {}
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
@@ -47,6 +60,12 @@ async fn main() {
             })
     });
 
+    debug!("Constructing the client for forwarding the audio…");
+    retrieve::init_client(&default_context);
+
+    let ca = load_ca(config.as_ref().ok().and_then(|v| v.ca_dir.as_deref()));
+    let tls_config = config.as_ref().ok().and_then(|v| v.tls.clone());
+
     debug!("Extracting the rate limit configuration…");
     let rate_limit_config = config
         .map(|v| {
@@ -92,7 +111,9 @@ async fn main() {
 
     let app = Router::new()
         // `GET /` goes to `root`
-        .route("/", get(root))
+        .route("/", get(root));
+    // The root CA shared with the proxy
+    let app = route_ca_cert(app, ca.clone())
         // Docs
         .nest(
             "/docs",
@@ -109,6 +130,8 @@ async fn main() {
             Router::new()
                 .route("/search", post(controllers::search::search_v1))
                 .route("/retrieve", post(controllers::retrieve::retrieve_v1))
+                .route("/playlist", post(controllers::tracklist::playlist_v1))
+                .route("/album", post(controllers::tracklist::album_v1))
                 .layer(Extension(default_context))
         })
         // Schema [v1]
@@ -116,10 +139,15 @@ async fn main() {
             Router::new()
                 .route("/index", get(schema::schema_v1_index))
                 .route("/search", get(schema::schema_v1_search))
+                .route("/tracklist", get(schema::schema_v1_tracklist))
                 .route("/error", get(schema::schema_v1_error))
         })
         .layer(limit_layer);
 
+    if let Some(tls_config) = tls_config {
+        spawn_https(app.clone(), ca, tls_config);
+    }
+
     let serve_address =
         std::env::var("SERVE_ADDRESS").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
     debug!("Will listen on: {serve_address}");
@@ -134,6 +162,66 @@ async fn main() {
         .unwrap();
 }
 
+/// Load the root CA in `ca_dir`, or generate one there.
+#[cfg(feature = "openssl")]
+fn load_ca(ca_dir: Option<&str>) -> Option<RootCa> {
+    debug!("Loading the root CA…");
+    ca_dir
+        .and_then(|dir| {
+            CertificateAuthority::load_or_generate(dir)
+                .map_err(|e| warn!("Failed to load the root CA because of {e}"))
+                .ok()
+        })
+        .map(Arc::new)
+}
+
+/// The root CA needs OpenSSL to load, so `ca_dir` is ignored.
+#[cfg(not(feature = "openssl"))]
+fn load_ca(ca_dir: Option<&str>) -> Option<RootCa> {
+    if ca_dir.is_some() {
+        warn!("Ignoring `ca_dir` since loading the root CA needs the `openssl` feature.");
+    }
+
+    None
+}
+
+/// Export the root CA at `/ca.crt`.
+#[cfg(feature = "openssl")]
+fn route_ca_cert(app: Router, ca: Option<RootCa>) -> Router {
+    app.route(
+        "/ca.crt",
+        get(controllers::cert::ca_cert).layer(Extension(ca)),
+    )
+}
+
+/// The root CA is unavailable, so is `/ca.crt`.
+#[cfg(not(feature = "openssl"))]
+fn route_ca_cert(app: Router, _ca: Option<RootCa>) -> Router {
+    app
+}
+
+/// Serve the `app` over HTTPS in the background, with the
+/// certificate issued by the root CA.
+#[cfg(feature = "openssl")]
+fn spawn_https(app: Router, ca: Option<RootCa>, config: TlsConfig) {
+    let Some(ca) = ca else {
+        warn!("Ignoring `[tls]` since the root CA in `ca_dir` is unavailable.");
+        return;
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = tls::serve(app, ca, config).await {
+            warn!("Failed to serve over HTTPS because of {e}");
+        }
+    });
+}
+
+/// The root CA is unavailable, so is the HTTPS listener.
+#[cfg(not(feature = "openssl"))]
+fn spawn_https(_app: Router, _ca: Option<RootCa>, _config: TlsConfig) {
+    warn!("Ignoring `[tls]` since serving over HTTPS needs the `openssl` feature.");
+}
+
 async fn root() -> Json<Value> {
     Json(json!({
         "success": true,
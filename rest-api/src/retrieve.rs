@@ -1,44 +1,48 @@
 //! A service that forward the retrieved URI.
 
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::response::IntoResponse;
 use axum::Json;
-use http::{HeaderMap, StatusCode};
-use once_cell::sync::Lazy;
+use http::StatusCode;
+use once_cell::sync::OnceCell;
 use reqwest::{Client, Url};
 use serde_json::json;
 use thiserror::Error;
-use tracing::{debug, instrument};
-use unm_types::RetrievedSongInfo;
+use tracing::{debug, instrument, warn};
+use unm_api_utils::retrieve::determine_header;
+use unm_request::dns::shared_resolver;
+use unm_types::{Context, RetrievedSongInfo};
 
-static CLIENT: Lazy<Client> = Lazy::new(|| {
+static CLIENT: OnceCell<Client> = OnceCell::new();
+
+/// Initiate the client for forwarding with the DNS settings in `context`.
+///
+/// The default client is used if it has not been initiated.
+pub fn init_client(context: &Context) {
     debug!("Constructing the client…");
 
-    reqwest::Client::builder()
-        .build()
-        .expect("failed to construct reqwest client")
-});
-
-/// Determine the header for requesting by the specified engine.
-#[instrument]
-pub fn determine_header(engine: &str) -> HeaderMap {
-    debug!("Determining the header to use…");
-
-    let mut hm = HeaderMap::new();
-
-    if engine == unm_engine_bilibili::ENGINE_ID {
-        hm.insert(
-            http::header::REFERER,
-            http::HeaderValue::from_static("https://www.bilibili.com/"),
-        );
-        hm.insert(
-            http::header::USER_AGENT,
-            http::HeaderValue::from_static("okhttp/3.4.1"),
-        );
+    let mut builder = unm_request::client_builder();
+
+    match shared_resolver(&context.dns) {
+        Ok(Some(resolver)) => builder = builder.dns_resolver(Arc::new(resolver)),
+        Ok(None) => {}
+        Err(e) => warn!("Ignoring the invalid DNS settings: {e}"),
     }
 
-    hm
+    let client = builder.build().expect("failed to construct reqwest client");
+    if CLIENT.set(client).is_err() {
+        warn!("The client has been initiated. Ignoring.");
+    }
+}
+
+fn client() -> &'static Client {
+    CLIENT.get_or_init(|| {
+        unm_request::client_builder()
+            .build()
+            .expect("failed to construct reqwest client")
+    })
 }
 
 pub async fn request_as_stream(
@@ -50,14 +54,15 @@ pub async fn request_as_stream(
     );
 
     let url = Url::parse(&retrieved.url)?;
-    let request = CLIENT
+    let client = client();
+    let request = client
         .get(url)
-        .headers(determine_header(&retrieved.source))
+        .headers(determine_header(retrieved))
         .timeout(Duration::from_secs(10))
         .build()
         .map_err(RetrieveError::ConstructRequestFailed)?;
 
-    let response = CLIENT
+    let response = client
         .execute(request)
         .await
         .map_err(RetrieveError::RequestFailed)?;
@@ -18,6 +18,14 @@ pub async fn schema_v1_search() -> impl IntoResponse {
     )
 }
 
+pub async fn schema_v1_tracklist() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(CONTENT_TYPE, "application/json")],
+        include_str!("./schema/v1/tracklist.json"),
+    )
+}
+
 pub async fn schema_v1_error() -> impl IntoResponse {
     (
         StatusCode::OK,
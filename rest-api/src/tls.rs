@@ -0,0 +1,53 @@
+//! Serve the API over HTTPS with a certificate issued by the root CA.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use hyper::server::conn::Http;
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+use unm_api_utils::cert::CertificateAuthority;
+
+use crate::config_reader::TlsConfig;
+
+/// Serve the `app` over HTTPS at the address in `config`, with the
+/// certificate of its host issued by the root CA `ca`.
+pub async fn serve(
+    app: Router,
+    ca: Arc<CertificateAuthority>,
+    config: TlsConfig,
+) -> anyhow::Result<()> {
+    let host = config.host.clone();
+    // Issuing a certificate generates an RSA key, which blocks for a while.
+    let leaf = match ca.cached(&host) {
+        Some(leaf) => leaf,
+        None => tokio::task::spawn_blocking(move || ca.issue(&host)).await??,
+    };
+    let identity = native_tls::Identity::from_pkcs8(&leaf.cert_pem, &leaf.key_pem)?;
+    let acceptor = tokio_native_tls::TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?);
+
+    let addr: SocketAddr = config.address.parse()?;
+    let listener = TcpListener::bind(addr).await?;
+    info!("listening on {} (HTTPS, for {})", addr, config.host);
+
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("Failed to handshake with {remote}: {e}");
+                    return;
+                }
+            };
+
+            if let Err(e) = Http::new().serve_connection(stream, app).await {
+                debug!("The HTTPS connection from {remote} is closed: {e}");
+            }
+        });
+    }
+}
@@ -0,0 +1,65 @@
+#!/usr/bin/env bash
+# -*- coding: utf-8 -*-
+#
+# Re-record the fixtures of the engines from the live services.
+#
+#     scripts/record-fixtures.sh [engine…]
+#
+# All the engines are recorded if none is specified. Only the cassettes
+# of the ordinary search and retrieve are recorded; the ones of the edge
+# cases (paid songs, paging, failed clients…) stay hand-written.
+# Review the diff before committing: remove the sensitive data and update
+# the assertions of the tests to the recorded responses.
+
+cd "$(dirname "$0")/.." || exit 1
+
+# The query that `ytdl` searches for the sample song (周杰伦 - 青花瓷).
+YTDL_QUERY="青花瓷 周杰伦 official lyric audio music"
+
+# The tests whose cassettes can be recorded from the live services.
+tests_of() {
+    case "$1" in
+        bilibili) echo "tests::test_search_and_retrieve tests::test_search_and_retrieve_video" ;;
+        joox | kugou | migu | youtube) echo "tests::test_search_and_retrieve" ;;
+        kuwo) echo "tests::test_search_and_retrieve tests::test_retrieve_lossless" ;;
+        # Both tests of pyncm share a cassette, so only record one of them.
+        pyncm) echo "tests::test_search_and_retrieve" ;;
+        qq) echo "tests::qq_search" ;;
+        *) return 1 ;;
+    esac
+}
+
+record_ytdl() {
+    local exe="${YTDL_EXE:-yt-dlp}"
+
+    for source in ytsearch bilisearch scsearch; do
+        echo "  → Recording ytdl ($source)"
+        "$exe" -f bestaudio --dump-json --no-playlist -- "${source}5:$YTDL_QUERY" \
+            > "engines/ytdl/fixtures/$source.json" || return 1
+    done
+}
+
+engines=("$@")
+if [ ${#engines[@]} -eq 0 ]; then
+    engines=(bilibili joox kugou kuwo migu pyncm qq youtube ytdl)
+fi
+
+for engine in "${engines[@]}"; do
+    if [ "$engine" = "ytdl" ]; then
+        record_ytdl || exit 1
+        continue
+    fi
+
+    tests="$(tests_of "$engine")" || {
+        echo "Unknown engine: $engine" >&2
+        exit 1
+    }
+
+    echo "  → Recording $engine"
+    # shellcheck disable=SC2086
+    UNM_RECORD_FIXTURES=1 cargo test -p "unm_engine_$engine" --lib -- --exact $tests || {
+        echo "    The assertions of $engine may need to be updated to the recorded responses."
+    }
+done
+
+echo "Done! Check the changes with \`git diff engines/\`."
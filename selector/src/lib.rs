@@ -1,3 +1,6 @@
+pub mod metadata;
+pub mod pages;
+
 use unm_types::Song;
 
 /// The "similar song selector" to pass to `.find()`.
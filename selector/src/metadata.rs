@@ -0,0 +1,41 @@
+//! Read the metadata of the candidates, which the engines
+//! of the video sites share when matching songs.
+
+/// The suffix of the auto-generated YouTube channels of the artists,
+/// which upload the official audio ("Art Tracks").
+pub const TOPIC_SUFFIX: &str = " - Topic";
+
+/// Whether the channel `name` is an auto-generated "- Topic" channel.
+pub fn is_topic_channel(name: &str) -> bool {
+    name.ends_with(TOPIC_SUFFIX)
+}
+
+/// Parse the duration in `m:ss` or `h:mm:ss` to milliseconds.
+pub fn parse_duration(duration: &str) -> Option<i64> {
+    duration
+        .split(':')
+        .try_fold(0, |total, part| {
+            Some(total * 60 + part.trim().parse::<i64>().ok()?)
+        })
+        .map(|seconds| seconds * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_topic_channel, parse_duration};
+
+    #[test]
+    fn test_is_topic_channel() {
+        assert!(is_topic_channel("Jay Chou - Topic"));
+        assert!(!is_topic_channel("Jay Chou"));
+        assert!(!is_topic_channel("Topic"));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("3:59"), Some(239000));
+        assert_eq!(parse_duration("1:02:03"), Some(3723000));
+        assert_eq!(parse_duration("LIVE"), None);
+        assert_eq!(parse_duration("invalid"), None);
+    }
+}
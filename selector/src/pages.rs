@@ -0,0 +1,132 @@
+//! Find the similar song in the search results fetched page by page.
+
+use std::future::Future;
+
+use unm_types::Song;
+
+use crate::SimilarSongSelector;
+
+/// A page of the search results.
+#[derive(Clone, Debug, Default)]
+pub struct SearchPage {
+    /// The candidates on this page which the engine can play.
+    pub songs: Vec<Song>,
+    /// Whether there is no more page to fetch.
+    pub is_last: bool,
+}
+
+/// Find the song similar to `expected` in the pages fetched by `fetch_page`,
+/// which is called with the page number from `1` up to `max_pages`,
+/// until the last page.
+///
+/// # Example
+///
+/// ```
+/// use unm_selector::pages::{find_in_pages, SearchPage};
+/// use unm_types::Song;
+///
+/// # futures::executor::block_on(async {
+/// let song = find_in_pages(&Song::default(), 3, |page| async move {
+///     Ok::<_, ()>(SearchPage {
+///         songs: vec![Song::builder()
+///             .id(page.to_string())
+///             .name("青花瓷".to_string())
+///             .build()],
+///         is_last: true,
+///     })
+/// })
+/// .await;
+/// assert_eq!(song.unwrap().unwrap().id, "1");
+/// # });
+/// ```
+pub async fn find_in_pages<F, Fut, E>(
+    expected: &Song,
+    max_pages: usize,
+    mut fetch_page: F,
+) -> Result<Option<Song>, E>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<SearchPage, E>>,
+{
+    for page in 1..=max_pages {
+        let SearchPage { songs, is_last } = fetch_page(page).await?;
+
+        if let Some(song) = find_similar(expected, songs) {
+            return Ok(Some(song));
+        }
+        if is_last {
+            break;
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the song similar to `expected` in `songs`.
+///
+/// The selector is not `Send`, so it must not live across an `.await`.
+pub fn find_similar(expected: &Song, songs: Vec<Song>) -> Option<Song> {
+    let SimilarSongSelector { selector, .. } = SimilarSongSelector::new(expected);
+
+    songs.into_iter().find(|song| selector(&song))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use futures::executor::block_on;
+    use unm_types::Song;
+
+    use super::{find_in_pages, SearchPage};
+
+    fn song(id: &str, duration: i64) -> Song {
+        Song::builder()
+            .id(id.to_string())
+            .name(id.to_string())
+            .duration(Some(duration))
+            .build()
+    }
+
+    #[test]
+    fn test_find_in_pages() {
+        let expected = song("expected", 239000);
+        let fetched = RefCell::new(Vec::new());
+        let fetch = |is_last_page: usize| {
+            let fetched = &fetched;
+            move |page: usize| async move {
+                fetched.borrow_mut().push(page);
+                Ok::<_, ()>(SearchPage {
+                    songs: match page {
+                        1 => vec![song("live", 301000)],
+                        _ => vec![song(&format!("page-{page}"), 240000)],
+                    },
+                    is_last: page == is_last_page,
+                })
+            }
+        };
+
+        // The first page has no similar song.
+        let found = block_on(find_in_pages(&expected, 3, fetch(3))).unwrap();
+        assert_eq!(found.unwrap().id, "page-2");
+        assert_eq!(fetched.take(), [1, 2]);
+
+        // Stop at the last page.
+        let found = block_on(find_in_pages(&expected, 3, fetch(1))).unwrap();
+        assert!(found.is_none());
+        assert_eq!(fetched.take(), [1]);
+
+        // Stop at the max pages.
+        let found = block_on(find_in_pages(&expected, 1, fetch(3))).unwrap();
+        assert!(found.is_none());
+        assert_eq!(fetched.take(), [1]);
+    }
+
+    #[test]
+    fn test_find_in_pages_error() {
+        let result = block_on(find_in_pages(&Song::default(), 3, |page| async move {
+            Err::<SearchPage, _>(page)
+        }));
+        assert_eq!(result.unwrap_err(), 1);
+    }
+}
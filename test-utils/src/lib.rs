@@ -1,12 +1,16 @@
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures::FutureExt;
 use log::LevelFilter;
 use simple_logger::SimpleLogger;
 use unm_engine::interface::Engine;
-use unm_types::{Artist, ContextBuilder, Song};
+use unm_request::transport::cassette::RecordedRequest;
+use unm_request::transport::{scope, RecordTransport, ReplayTransport};
+use unm_types::{Artist, Context, ContextBuilder, RetrievedSongInfo, Song, SongSearchInformation};
 
 /// Measure the time taken by the given closure.
 #[inline]
@@ -37,6 +41,74 @@ pub fn set_logger() {
         .unwrap();
 }
 
+/// Run the `future` with the HTTP interactions replayed from the cassette at `path`.
+///
+/// Set the environment variable `UNM_RECORD_FIXTURES` to send the requests
+/// to the Internet and record the interactions to the cassette instead.
+/// See [`unm_request::transport`] for more information.
+pub async fn with_cassette<T>(path: impl AsRef<Path>, future: impl Future<Output = T>) -> T {
+    with_cassette_requests(path, future).await.0
+}
+
+/// Like [`with_cassette`], but also return the requests that were sent,
+/// so the tests can check their headers, for example, `Cookie`.
+pub async fn with_cassette_requests<T>(
+    path: impl AsRef<Path>,
+    future: impl Future<Output = T>,
+) -> (T, Vec<RecordedRequest>) {
+    let path = path.as_ref();
+
+    if std::env::var_os("UNM_RECORD_FIXTURES").is_some() {
+        log::info!("Recording the interactions to {}…", path.display());
+        let transport = Arc::new(RecordTransport::create(path));
+        let output = scope(transport.clone(), future).await;
+
+        (output, transport.requests())
+    } else {
+        let transport =
+            Arc::new(ReplayTransport::open(path).expect("the cassette should be valid"));
+        let output = scope(transport.clone(), future).await;
+
+        (output, transport.requests())
+    }
+}
+
+/// The song we search in the engine tests: 周杰伦 - 青花瓷.
+pub fn sample_song() -> Song {
+    Song::builder()
+        .name("青花瓷".into())
+        .duration(Some(239882))
+        .artists(vec![Artist::builder().name("周杰伦".into()).build()])
+        .build()
+}
+
+/// Search the `song` with the `engine` and retrieve the matched one.
+///
+/// It asserts that both results are from `engine_id`, and returns them for
+/// the engine tests to check the fields that the engine parses.
+/// Run it in [`with_cassette`] to replay the interactions.
+pub async fn search_and_retrieve(
+    engine: &dyn Engine,
+    engine_id: &str,
+    song: &Song,
+    ctx: &Context,
+) -> (SongSearchInformation, RetrievedSongInfo) {
+    let info = engine
+        .search(song, ctx)
+        .await
+        .expect("failed to search")
+        .expect("should have a search result");
+    assert_eq!(info.source, engine_id);
+
+    let retrieved = engine
+        .retrieve(&info.identifier, ctx)
+        .await
+        .expect("failed to retrieve");
+    assert_eq!(retrieved.source, engine_id);
+
+    (info, retrieved)
+}
+
 pub async fn engine_example_wrapper(engine: impl Engine) {
     set_logger();
 
@@ -1,12 +1,14 @@
 pub mod config;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::PathBuf;
 
 use config::ConfigManager;
 use derive_builder::Builder;
 use reqwest::Proxy;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use typed_builder::TypedBuilder;
 
 /// The serialized identifier for passing to `retrieve()`.
@@ -105,6 +107,219 @@ pub struct RetrievedSongInfo {
     pub source: Cow<'static, str>,
     /// The URL of this song.
     pub url: String,
+    /// The headers required to request the URL, for example, the `Referer`.
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// The bitrate (bps) of this song, if the engine knows it.
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u32>,
+    /// The format (file extension) of this song, for example: `flac`,
+    /// if the engine knows it.
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+/// The DNS settings for resolving the hosts of requests.
+///
+/// With the default settings, the system resolver is used.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, TypedBuilder)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct DnsConfig {
+    /// The static host → IP address overrides,
+    /// which take precedence over the DNS server.
+    #[builder(default)]
+    pub hosts: HashMap<String, IpAddr>,
+
+    /// The DNS server to query. Use the system resolver if it is `None`.
+    ///
+    /// It can be a DNS server (`8.8.8.8` or `8.8.8.8:53`) queried over UDP,
+    /// or a DNS-over-HTTPS endpoint (`https://1.1.1.1/dns-query`).
+    #[builder(default)]
+    pub server: Option<String>,
+}
+
+/// The proxy settings that refine [`Context::proxy_uri`]
+/// for each engine and each host.
+///
+/// A proxy URI can be an empty string, which means connecting directly.
+/// For the request of an engine to a host, the proxy is determined by:
+///
+/// 1. If the host matches `no_proxy`, connect directly.
+/// 2. The first rule in `rules` whose `hosts` matches the host.
+/// 3. The override of this engine in `engines`.
+/// 4. [`Context::proxy_uri`].
+///
+/// A host pattern can be a host (`www.kuwo.cn`), a domain with its
+/// subdomains (`*.kuwo.cn` or `.kuwo.cn`), or `*` for every host.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, TypedBuilder)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ProxyConfig {
+    /// The proxy URI of each engine, for example:
+    /// `ytdl` → `socks5://127.0.0.1:1080`, or `kuwo` → `` (direct).
+    #[builder(default)]
+    pub engines: HashMap<String, String>,
+
+    /// The proxy rules matched with the host of requests, in order.
+    #[builder(default)]
+    pub rules: Vec<ProxyRule>,
+
+    /// The host patterns to connect directly.
+    #[builder(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// A rule to use `uri` as the proxy of `hosts`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, TypedBuilder)]
+#[non_exhaustive]
+pub struct ProxyRule {
+    /// The host patterns this rule applies to.
+    pub hosts: Vec<String>,
+
+    /// The proxy URI. Connect directly if it is empty.
+    #[serde(default)]
+    #[builder(default)]
+    pub uri: String,
+}
+
+/// The limits on how fast the engines request the upstream hosts.
+///
+/// The limits are shared by every client that `unm_request` builds.
+/// The limit of a host takes precedence over the limit of an engine.
+/// See [`ProxyConfig`] for the syntax of host patterns; if several
+/// patterns match, the most specific one is used.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, TypedBuilder)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ThrottleConfig {
+    /// The limit of each host pattern, for example: `*.kuwo.cn`.
+    #[builder(default)]
+    pub hosts: HashMap<String, UpstreamLimit>,
+
+    /// The limit of the hosts requested by each engine, for example: `qq`.
+    #[builder(default)]
+    pub engines: HashMap<String, UpstreamLimit>,
+}
+
+/// The limit of an upstream host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, TypedBuilder)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct UpstreamLimit {
+    /// How many requests can be sent per second on average,
+    /// which should be positive and finite.
+    #[builder(default)]
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub requests_per_second: Option<f64>,
+
+    /// How many requests can be sent at once after idling.
+    /// By default, it is `1`.
+    #[builder(default)]
+    pub burst: Option<u32>,
+
+    /// How many requests can be in flight at the same time.
+    #[builder(default)]
+    pub max_concurrency: Option<usize>,
+}
+
+/// Deserialize [`UpstreamLimit::requests_per_second`],
+/// and reject the rates that are not positive and finite.
+fn deserialize_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    use serde::de::Error;
+
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(rate) if !(rate.is_finite() && rate > 0.0) => Err(D::Error::custom(format!(
+            "`requests_per_second` should be positive and finite, but it is {rate}"
+        ))),
+        rate => Ok(rate),
+    }
+}
+
+/// The HTTP client profiles of engines.
+///
+/// The profile of an engine overrides the fields set in the `default` one.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, TypedBuilder)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ClientProfiles {
+    /// The profile for every engine.
+    #[builder(default)]
+    pub default: ClientProfile,
+
+    /// The profile of each engine, for example: `kuwo`.
+    #[builder(default)]
+    pub engines: HashMap<String, ClientProfile>,
+}
+
+/// The settings of an HTTP client.
+///
+/// The unset fields fall back to the built-in defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, TypedBuilder)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct ClientProfile {
+    /// The timeout (in milliseconds) of connecting to the upstream.
+    #[builder(default)]
+    pub connect_timeout_ms: Option<u64>,
+
+    /// The timeout (in milliseconds) of a whole request,
+    /// from connecting to reading the response body.
+    /// By default, it is `10000`.
+    #[builder(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// The longest time (in milliseconds) without receiving anything
+    /// from the upstream, when waiting for the response or reading
+    /// its body. Unlike `timeout_ms`, a slow but steady download
+    /// is not interrupted.
+    #[builder(default)]
+    pub read_timeout_ms: Option<u64>,
+
+    /// The user agents to send. If there are several,
+    /// they are used in rotation for each request.
+    ///
+    /// They are ignored if `User-Agent` is set in `headers`.
+    #[builder(default)]
+    pub user_agents: Vec<String>,
+
+    /// The extra default headers, which override the built-in ones.
+    ///
+    /// `User-Agent` here takes precedence over `user_agents`.
+    #[builder(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Which HTTP version to use.
+    #[builder(default)]
+    pub http_version: Option<HttpVersion>,
+
+    /// How many idle connections to keep for each host.
+    #[builder(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+}
+
+/// The HTTP version preference of a client.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum HttpVersion {
+    /// Negotiate with the upstream: HTTP/2 is preferred in ALPN on the
+    /// TLS connections, and HTTP/1.1 is used if the upstream doesn't
+    /// support it, or on the plain-text connections.
+    ///
+    /// `http2` is accepted as an alias, since preferring HTTP/2 is
+    /// exactly what the negotiation does.
+    #[default]
+    #[serde(alias = "http2")]
+    Auto,
+    /// Use HTTP/1.1 only.
+    Http1,
+    /// Use HTTP/2 directly without negotiating ("prior knowledge"),
+    /// even on the plain-text connections. The upstream must support it.
+    Http2PriorKnowledge,
 }
 
 /// The context.
@@ -128,6 +343,41 @@ pub struct Context {
 
     /// The config for engines.
     pub config: Option<ConfigManager>,
+
+    /// The host mapping applied to the requests of engines.
+    ///
+    /// The key is the original host, and the value is where to connect
+    /// instead. If the value is an IP address, we connect to it directly
+    /// and keep the original `Host` header and SNI. Otherwise, the host
+    /// in the URL is replaced with the value.
+    ///
+    /// For example: `www.kuwo.cn` → `203.0.113.10`, or
+    /// `u.y.qq.com` → `u6.y.qq.com`.
+    #[serde(default)]
+    pub host_mapping: HashMap<String, String>,
+
+    /// The DNS settings for resolving the hosts of requests.
+    #[serde(default)]
+    pub dns: DnsConfig,
+
+    /// The per-engine and per-host proxy settings.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+
+    /// The rate limits and concurrency caps of the upstream hosts.
+    #[serde(default)]
+    pub throttle: ThrottleConfig,
+
+    /// The directory to persist the cookies of engines,
+    /// for example: `./cookies`.
+    ///
+    /// The cookies are kept in memory only if it is `None`.
+    #[serde(default)]
+    pub cookie_dir: Option<PathBuf>,
+
+    /// The HTTP client profiles of engines.
+    #[serde(default)]
+    pub client_profiles: ClientProfiles,
 }
 
 impl Context {